@@ -1,19 +1,23 @@
 mod bitboard;
 mod color;
+mod fen;
 mod hash;
 mod mv;
 mod piece;
 mod piece_storage;
 mod square;
 
+use crate::movegen::{MoveList, compute_legal_moves};
+use crate::pgn::GameResult;
 pub use bitboard::*;
 pub use color::*;
+pub use fen::*;
 pub use hash::*;
 pub use mv::*;
 pub use piece::*;
 pub use piece_storage::*;
 pub use square::*;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Board {
@@ -27,6 +31,8 @@ pub struct Board {
     history: Vec<BoardHash>,
     repetition_count: HashMap<BoardHash, u8>,
     is_threefold: bool,
+    /// Castling moves are encoded as the king capturing its own rook
+    chess960: bool,
 }
 
 impl Board {
@@ -75,6 +81,7 @@ impl Board {
             history: vec![hash],
             repetition_count,
             is_threefold: false,
+            chess960: false,
         }
     }
 
@@ -93,190 +100,133 @@ impl Board {
         Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap()
     }
 
-    pub fn hash(&self) -> BoardHash {
-        self.hash
-    }
-
-    pub fn from_fen(fen: &str) -> Result<Board, String> {
-        let invalid_fen_err = |err: String| Err(format!("Invalid fen: {} - {}", fen, err));
-
-        let sections = fen.split(" ").collect::<Vec<&str>>();
-        if sections.len() != 6 {
-            return invalid_fen_err("must have 6 fields".to_string());
+    /// The Chess960 starting position with the given index (0-959) in Scharnagl's numbering,
+    /// where 518 is the standard starting position.
+    pub fn starting_chess960(index: u16) -> Option<Board> {
+        if index >= 960 {
+            return None;
         }
 
-        // * Board
-        let mut pieces = [None; 64];
-        let ranks = sections[0].split("/").collect::<Vec<&str>>();
-        if ranks.len() != 8 {
-            return invalid_fen_err("must have 8 ranks".to_string());
-        }
-        for (r, rank) in ranks.into_iter().enumerate() {
-            let mut f: usize = 0;
-            for c in rank.chars() {
-                if c.is_ascii_digit() {
-                    f += c.to_string().parse::<usize>().unwrap();
-                    continue;
-                }
-
-                if f >= 8 {
-                    return invalid_fen_err(format!("file {} out of range", f));
-                }
-
-                let Some(piece) = Piece::from_char(c) else {
-                    return invalid_fen_err(format!("unrecognised piece '{c}'"));
-                };
-
-                pieces[(7 - r) * 8 + f] = Some(piece);
+        let mut back_rank: [Option<PieceKind>; 8] = [None; 8];
 
-                f += 1;
-            }
-        }
+        // Light and dark square bishops
+        let mut n = index as usize;
+        back_rank[(n % 4) * 2 + 1] = Some(PieceKind::Bishop);
+        n /= 4;
+        back_rank[(n % 4) * 2] = Some(PieceKind::Bishop);
+        n /= 4;
 
-        // * To move
-        let to_move = match sections[1] {
-            "w" => Color::White,
-            "b" => Color::Black,
-            _ => return invalid_fen_err("invalid fen".to_string()),
+        let mut place_in_empty = |kind: PieceKind, n: usize| {
+            let file = (0..8).filter(|&f| back_rank[f].is_none()).nth(n).unwrap();
+            back_rank[file] = Some(kind);
         };
-
-        // * Castling availability
-        let castling_chars: HashSet<char> = if sections[2] == "-" {
-            HashSet::new()
-        } else {
-            HashSet::from_iter(sections[2].chars())
-        };
-
-        for c in castling_chars.iter() {
-            match c {
-                'K' | 'Q' | 'k' | 'q' => {}
-                _ => return invalid_fen_err(format!("unrecognised castling character '{c}'")),
-            }
-        }
-
-        let castling_rights = CastlingRights::new(
-            castling_chars.contains(&'Q'),
-            castling_chars.contains(&'K'),
-            castling_chars.contains(&'q'),
-            castling_chars.contains(&'k'),
+        place_in_empty(PieceKind::Queen, n % 6);
+        n /= 6;
+        const KNIGHT_PLACEMENTS: [(usize, usize); 10] = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ];
+        let (first, second) = KNIGHT_PLACEMENTS[n];
+        // Place the second knight first so that the first's index is unaffected
+        place_in_empty(PieceKind::Knight, second);
+        place_in_empty(PieceKind::Knight, first);
+        place_in_empty(PieceKind::Rook, 0);
+        place_in_empty(PieceKind::King, 0);
+        place_in_empty(PieceKind::Rook, 0);
+
+        let back_rank = back_rank
+            .iter()
+            .map(|k| Piece::new(k.unwrap(), Color::Black).as_char())
+            .collect::<String>();
+        let fen = format!(
+            "{back_rank}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+            back_rank.to_ascii_uppercase()
         );
 
-        // * En Passant
-        let en_passant = if sections[3] == "-" {
-            None
-        } else {
-            if sections[3].len() != 2 {
-                return invalid_fen_err("en passant must be '-' or 2 character".to_string());
-            }
-
-            let Some(square) = Square::from_name(sections[3]) else {
-                return invalid_fen_err("invalid en passant square".to_string());
-            };
-
-            Some(square)
-        };
-
-        // * Halfmoves since last capture or pawn move
-        let Ok(halfmoves_since_event): Result<u32, _> = sections[4].parse() else {
-            return invalid_fen_err("halfmoves not a number".to_string());
-        };
-
-        // * Fullmoves
-        let Ok(fullmoves): Result<u32, _> = sections[5].parse() else {
-            return invalid_fen_err("fullmoves not a number".to_string());
-        };
-
-        Ok(Board::new(
-            &pieces,
-            to_move,
-            en_passant,
-            castling_rights,
-            halfmoves_since_event,
-            fullmoves,
-        ))
+        let mut board = Board::from_fen(&fen).unwrap();
+        board.chess960 = true;
+        Some(board)
     }
 
-    pub fn to_fen(&self) -> String {
-        let mut output = String::new();
-
-        let mut cur_x = 0;
-
-        for y in (0u8..8).rev() {
-            for x in 0u8..8 {
-                debug_assert!(x < 8);
-                debug_assert!(y < 8);
-                // SAFETY: Range limited
-                unsafe {
-                    if let Some(piece) = self.pieces.get(Square::at_xy_unchecked(x, y)) {
-                        if cur_x != x {
-                            output += &format!("{}", x - cur_x);
-                            cur_x = x;
-                        }
-
-                        output.push(piece.as_char());
-
-                        cur_x += 1;
-                    }
-                }
-            }
+    pub fn hash(&self) -> BoardHash {
+        self.hash
+    }
 
-            if cur_x != 8 {
-                output += &format!("{}", 8 - cur_x);
-            }
-            cur_x = 0;
+    /// The key of the position in Polyglot opening books. Unlike `hash`, it is the same in every
+    /// build.
+    pub fn polyglot_key(&self) -> PolyglotKey {
+        self.pieces.polyglot_key().with_position_state(self)
+    }
 
-            if y != 0 {
-                output.push('/');
-            }
+    /// If `mv` is a castling move, returns the square of the castling rook and whether it is
+    /// kingside. Castling is accepted both as the king moving two files to the c/g file, and as
+    /// the king capturing its own rook (as in Chess960).
+    pub fn castling_rook(&self, mv: Move) -> Option<(Square, bool)> {
+        let king = self.pieces.get(mv.source)?;
+        if king.kind() != PieceKind::King
+            || mv.source.rank() != king.color().back_rank()
+            || mv.destination.rank() != mv.source.rank()
+        {
+            return None;
         }
 
-        output += &format!(" {} ", self.color_to_move.as_char());
+        let kingside = mv.destination.file() > mv.source.file();
+        let rook_square = self.castling_rights.rook_square(king.color(), kingside)?;
 
-        let mut castling_string = String::new();
-        if self.castling_rights.kingside(Color::White) {
-            castling_string.push('K');
-        }
-        if self.castling_rights.queenside(Color::White) {
-            castling_string.push('Q');
-        }
-        if self.castling_rights.kingside(Color::Black) {
-            castling_string.push('k');
-        }
-        if self.castling_rights.queenside(Color::Black) {
-            castling_string.push('q');
-        }
-        if castling_string.is_empty() {
-            castling_string.push('-');
-        }
+        let dx = mv.destination.file().as_u8() as i32 - mv.source.file().as_u8() as i32;
+        let king_destination_file = if kingside { BoardFile::G } else { BoardFile::C };
+        let is_two_file_move = dx.abs() == 2 && mv.destination.file() == king_destination_file;
 
-        output += &castling_string;
+        (mv.destination == rook_square || is_two_file_move).then_some((rook_square, kingside))
+    }
 
-        if let Some(square) = self.en_passant_destination {
-            output += &format!(" {}", square.name())
-        } else {
-            output += " -";
-        }
+    /// Convert a move from an external source (UCI, an opening book) to the form generated by
+    /// `compute_legal_moves`. Castling may be given either as the king moving two files or as
+    /// the king capturing its own rook. Returns None if the move isn't legal.
+    pub fn normalise_move(&self, mv: Move) -> Option<Move> {
+        let mut legal_moves = MoveList::new();
+        compute_legal_moves(&mut legal_moves, self);
 
-        output += &format!(" {} {}", self.halfmoves_since_event, self.fullmoves);
+        if legal_moves.contains(&mv) {
+            return Some(mv);
+        }
 
-        output
+        let castling = self.castling_rook(mv)?;
+        legal_moves.into_iter().find(|&legal| {
+            legal.source == mv.source
+                && mv.promotion.is_none()
+                && self.castling_rook(legal) == Some(castling)
+        })
     }
 
-    // TODO: Make tolerant of castling by moving king to edge - some opening books use this form and cutechess seems tolerant of it
     pub fn make_move(&mut self, mv: Move) -> UnmakeInfo {
         let source_piece = self
             .pieces
             .get(mv.source)
             .expect("There should always be a piece at the source square");
-        let destination_piece = self.pieces.get(mv.destination).map(|p| p.kind());
+        let castling = self.castling_rook(mv);
+        let destination_piece = self
+            .pieces
+            .get(mv.destination)
+            .map(|p| p.kind())
+            .filter(|_| castling.is_none());
         let dx = mv.destination.file().as_u8() as i32 - mv.source.file().as_u8() as i32;
         let dy = mv.destination.rank().as_u8() as i32 - mv.source.rank().as_u8() as i32;
 
         let mut unmake_info = UnmakeInfo {
             piece: source_piece.kind(),
             source: mv.source,
-            destination: mv.destination,
+            destination: mv.destination, // Changed for castling
             captured: destination_piece, // Changed for en passant
+            castling_rook: castling.map(|(rook_square, _)| rook_square),
             old_en_passant_destination: self.en_passant_destination,
             old_castling_rights: self.castling_rights,
             old_halfmoves_since_event: self.halfmoves_since_event,
@@ -293,8 +243,43 @@ impl Board {
             destination_piece,
             (dx, dy),
             mv.promotion,
+            castling,
         ) {
-            (PieceKind::Pawn, None, (dx, _), _) if dx != 0 => {
+            (_, _, (_, _), _, Some((rook_square, kingside))) => {
+                // Castling
+                let rank = mv.source.rank();
+                let (king_destination, rook_destination) = if kingside {
+                    (
+                        Square::at(BoardFile::G, rank),
+                        Square::at(BoardFile::F, rank),
+                    )
+                } else {
+                    (
+                        Square::at(BoardFile::C, rank),
+                        Square::at(BoardFile::D, rank),
+                    )
+                };
+
+                (self.hash, self.castling_rights) = self
+                    .castling_rights
+                    .without_color(self.hash, source_piece.color());
+
+                // In Chess960 the king or rook may already be on its destination, so clear both
+                // source squares before placing either piece
+                self.hash = self.pieces.set(self.hash, mv.source, None);
+                self.hash = self.pieces.set(self.hash, rook_square, None);
+                self.hash = self
+                    .pieces
+                    .set(self.hash, king_destination, Some(source_piece));
+                self.hash = self.pieces.set(
+                    self.hash,
+                    rook_destination,
+                    Some(Piece::new(PieceKind::Rook, source_piece.color())),
+                );
+
+                unmake_info.destination = king_destination;
+            }
+            (PieceKind::Pawn, None, (dx, _), _, _) if dx != 0 => {
                 // En passant
                 self.hash = self.pieces.set(self.hash, mv.source, None);
 
@@ -310,7 +295,7 @@ impl Board {
 
                 unmake_info.captured = Some(PieceKind::Pawn);
             }
-            (_, _, (_, _), Some(piece)) => {
+            (_, _, (_, _), Some(piece), _) => {
                 // Promotion
                 self.hash = self.pieces.set(self.hash, mv.source, None);
                 self.hash = self.pieces.set(
@@ -319,45 +304,7 @@ impl Board {
                     Some(Piece::new(piece, source_piece.color())),
                 );
             }
-            (PieceKind::King, _, (-2, _), _) => {
-                // Long castle
-                (self.hash, self.castling_rights) = self
-                    .castling_rights
-                    .without_color(self.hash, source_piece.color());
-
-                self.hash = self.pieces.set(self.hash, mv.source, None);
-                self.hash = self
-                    .pieces
-                    .set(self.hash, mv.destination, Some(source_piece));
-                self.hash =
-                    self.pieces
-                        .set(self.hash, Square::at(BoardFile::A, mv.source.rank()), None);
-                self.hash = self.pieces.set(
-                    self.hash,
-                    Square::at(BoardFile::D, mv.source.rank()),
-                    Some(Piece::new(PieceKind::Rook, source_piece.color())),
-                );
-            }
-            (PieceKind::King, _, (2, _), _) => {
-                // Short castle
-                (self.hash, self.castling_rights) = self
-                    .castling_rights
-                    .without_color(self.hash, source_piece.color());
-
-                self.hash = self.pieces.set(self.hash, mv.source, None);
-                self.hash = self
-                    .pieces
-                    .set(self.hash, mv.destination, Some(source_piece));
-                self.hash =
-                    self.pieces
-                        .set(self.hash, Square::at(BoardFile::H, mv.source.rank()), None);
-                self.hash = self.pieces.set(
-                    self.hash,
-                    Square::at(BoardFile::F, mv.source.rank()),
-                    Some(Piece::new(PieceKind::Rook, source_piece.color())),
-                );
-            }
-            (PieceKind::Pawn, _, (_, 2 | -2), _) => {
+            (PieceKind::Pawn, _, (_, 2 | -2), _, _) => {
                 // Seems like double pawn push doesn't actually set the e.p. target square unless
                 // an enemy pawn is there to capture it.
                 // (This doesn't actually matter but is important for FEN to be correct).
@@ -401,48 +348,37 @@ impl Board {
                     .pieces
                     .set(self.hash, mv.destination, Some(source_piece));
 
-                // Void castling rights if moving king or rooks, or if rook is taken
+                // Void castling rights if moving king or rooks
+                let color = source_piece.color();
                 (self.hash, self.castling_rights) = match source_piece.kind() {
-                    PieceKind::King => self
-                        .castling_rights
-                        .without_color(self.hash, source_piece.color()),
+                    PieceKind::King => self.castling_rights.without_color(self.hash, color),
                     PieceKind::Rook
-                        if mv.source
-                            == Square::at(BoardFile::H, source_piece.color().back_rank()) =>
+                        if Some(mv.source) == self.castling_rights.rook_square(color, true) =>
                     {
-                        self.castling_rights
-                            .without_kingside(self.hash, source_piece.color())
+                        self.castling_rights.without_kingside(self.hash, color)
                     }
                     PieceKind::Rook
-                        if mv.source
-                            == Square::at(BoardFile::A, source_piece.color().back_rank()) =>
+                        if Some(mv.source) == self.castling_rights.rook_square(color, false) =>
                     {
-                        self.castling_rights
-                            .without_queenside(self.hash, source_piece.color())
+                        self.castling_rights.without_queenside(self.hash, color)
                     }
                     _ => (self.hash, self.castling_rights),
                 };
-
-                if destination_piece.is_some_and(|k| k == PieceKind::Rook) {
-                    (self.hash, self.castling_rights) = match mv.destination {
-                        Square::A1 => self
-                            .castling_rights
-                            .without_queenside(self.hash, Color::White),
-                        Square::H1 => self
-                            .castling_rights
-                            .without_kingside(self.hash, Color::White),
-                        Square::A8 => self
-                            .castling_rights
-                            .without_queenside(self.hash, Color::Black),
-                        Square::H8 => self
-                            .castling_rights
-                            .without_kingside(self.hash, Color::Black),
-                        _ => (self.hash, self.castling_rights),
-                    };
-                }
             }
         };
 
+        // Void castling rights if a castling rook is taken (including by promotion)
+        if destination_piece == Some(PieceKind::Rook) {
+            let enemy = !source_piece.color();
+            if Some(mv.destination) == self.castling_rights.rook_square(enemy, true) {
+                (self.hash, self.castling_rights) =
+                    self.castling_rights.without_kingside(self.hash, enemy);
+            } else if Some(mv.destination) == self.castling_rights.rook_square(enemy, false) {
+                (self.hash, self.castling_rights) =
+                    self.castling_rights.without_queenside(self.hash, enemy);
+            }
+        }
+
         self.halfmoves_since_event += 1;
         if unmake_info.captured.is_some() || source_piece.kind() == PieceKind::Pawn {
             self.halfmoves_since_event = 0;
@@ -474,8 +410,6 @@ impl Board {
         }
         self.is_threefold = um.old_is_threefold;
 
-        let dx = um.destination.file().as_u8() as i32 - um.source.file().as_u8() as i32;
-
         self.hash = self.hash.toggle_move();
         self.color_to_move = !self.color_to_move;
         self.halfmoves_since_event = um.old_halfmoves_since_event;
@@ -495,42 +429,30 @@ impl Board {
         self.castling_rights = um.old_castling_rights;
 
         // Don't need to handle promotion or leap
-        match (um.piece, um.destination.rank(), dx) {
-            (PieceKind::Pawn, _, _) if Some(um.destination) == um.old_en_passant_destination => {
-                // En Passant
-                self.hash = self.pieces.set(self.hash, um.destination, None);
-                self.hash = self.pieces.set(
-                    self.hash,
-                    um.source,
-                    Some(Piece::new(um.piece, self.color_to_move)),
-                );
+        match (um.piece, um.castling_rook) {
+            (_, Some(rook_square)) => {
+                // Castling
+                let rook_destination = if um.destination.file() == BoardFile::G {
+                    Square::at(BoardFile::F, um.source.rank())
+                } else {
+                    Square::at(BoardFile::D, um.source.rank())
+                };
 
-                self.hash = self.pieces.set(
-                    self.hash,
-                    Square::at(um.destination.file(), um.source.rank()),
-                    Some(Piece::new(PieceKind::Pawn, !self.color_to_move)),
-                );
-            }
-            (PieceKind::King, _, -2) => {
-                // Long castle
                 self.hash = self.pieces.set(self.hash, um.destination, None);
+                self.hash = self.pieces.set(self.hash, rook_destination, None);
                 self.hash = self.pieces.set(
                     self.hash,
                     um.source,
                     Some(Piece::new(um.piece, self.color_to_move)),
                 );
-
-                self.hash =
-                    self.pieces
-                        .set(self.hash, Square::at(BoardFile::D, um.source.rank()), None);
                 self.hash = self.pieces.set(
                     self.hash,
-                    Square::at(BoardFile::A, um.source.rank()),
+                    rook_square,
                     Some(Piece::new(PieceKind::Rook, self.color_to_move)),
                 );
             }
-            (PieceKind::King, _, 2) => {
-                // Short castle
+            (PieceKind::Pawn, _) if Some(um.destination) == um.old_en_passant_destination => {
+                // En Passant
                 self.hash = self.pieces.set(self.hash, um.destination, None);
                 self.hash = self.pieces.set(
                     self.hash,
@@ -538,13 +460,10 @@ impl Board {
                     Some(Piece::new(um.piece, self.color_to_move)),
                 );
 
-                self.hash =
-                    self.pieces
-                        .set(self.hash, Square::at(BoardFile::F, um.source.rank()), None);
                 self.hash = self.pieces.set(
                     self.hash,
-                    Square::at(BoardFile::H, um.source.rank()),
-                    Some(Piece::new(PieceKind::Rook, self.color_to_move)),
+                    Square::at(um.destination.file(), um.source.rank()),
+                    Some(Piece::new(PieceKind::Pawn, !self.color_to_move)),
                 );
             }
             _ => {
@@ -588,9 +507,113 @@ impl Board {
         self.halfmoves_since_event
     }
 
+    pub fn fullmoves(&self) -> u32 {
+        self.fullmoves
+    }
+
+    /// Plies played since the start of the game, according to the fullmove counter.
+    pub fn ply(&self) -> u32 {
+        (self.fullmoves.max(1) - 1) * 2 + if self.color_to_move.is_white() { 0 } else { 1 }
+    }
+
     pub fn is_threefold(&self) -> bool {
         self.is_threefold
     }
+
+    /// Whether the game is over, and why. Checkmate takes precedence over the draw rules, which
+    /// are applied automatically rather than needing a claim.
+    pub fn status(&self) -> GameStatus {
+        let mut legal_moves = MoveList::new();
+        let is_check = compute_legal_moves(&mut legal_moves, self);
+
+        if legal_moves.is_empty() {
+            if is_check {
+                GameStatus::Checkmate(!self.color_to_move)
+            } else {
+                GameStatus::Stalemate
+            }
+        } else if self.is_threefold {
+            GameStatus::ThreefoldRepetition
+        } else if self.halfmoves_since_event >= 100 {
+            GameStatus::FiftyMoves
+        } else if self.has_insufficient_material() {
+            GameStatus::InsufficientMaterial
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+
+    /// Neither side can mate with any sequence of moves: bare kings, a single minor piece, or
+    /// only bishops all on squares of one colour.
+    pub fn has_insufficient_material(&self) -> bool {
+        let mut knights = 0;
+        // By the colour of the square they're on
+        let mut bishops = [0; 2];
+
+        for (sq, piece) in self.pieces.iter() {
+            match piece.kind() {
+                PieceKind::King => {}
+                PieceKind::Knight => knights += 1,
+                PieceKind::Bishop => {
+                    bishops[((sq.file().as_u8() + sq.rank().as_u8()) % 2) as usize] += 1
+                }
+                _ => return false,
+            }
+        }
+
+        knights + bishops[0] + bishops[1] <= 1 || (knights == 0 && bishops.contains(&0))
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Set whether castling moves are generated as the king capturing its own rook. Positions
+    /// with castling rooks away from the a/h files, or a castling king away from the e file,
+    /// must be played as Chess960.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// Won by the given colour.
+    Checkmate(Color),
+    Stalemate,
+    ThreefoldRepetition,
+    /// 100 plies without a capture or pawn move.
+    FiftyMoves,
+    InsufficientMaterial,
+}
+
+impl GameStatus {
+    pub fn is_over(self) -> bool {
+        self != GameStatus::Ongoing
+    }
+
+    pub fn result(self) -> GameResult {
+        match self {
+            GameStatus::Ongoing => GameResult::Unknown,
+            GameStatus::Checkmate(Color::White) => GameResult::WhiteWin,
+            GameStatus::Checkmate(Color::Black) => GameResult::BlackWin,
+            _ => GameResult::Draw,
+        }
+    }
+
+    /// e.g. "White mates", as fastchess and cutechess describe results.
+    pub fn description(self) -> &'static str {
+        match self {
+            GameStatus::Ongoing => "In progress",
+            GameStatus::Checkmate(Color::White) => "White mates",
+            GameStatus::Checkmate(Color::Black) => "Black mates",
+            GameStatus::Stalemate => "Draw by stalemate",
+            GameStatus::ThreefoldRepetition => "Draw by 3-fold repetition",
+            GameStatus::FiftyMoves => "Draw by fifty moves rule",
+            GameStatus::InsufficientMaterial => "Draw by insufficient mating material",
+        }
+    }
 }
 
 // From least to most significant:
@@ -598,16 +621,29 @@ impl Board {
 // - White kingside
 // - Black queenside
 // - Black kingside
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub struct CastlingRights(u8);
+// The file of each castling rook is stored in the same order, for Chess960.
+#[derive(Clone, Copy, Debug)]
+pub struct CastlingRights {
+    rights: u8,
+    rook_files: [BoardFile; 4],
+}
+
+const STANDARD_ROOK_FILES: [BoardFile; 4] =
+    [BoardFile::A, BoardFile::H, BoardFile::A, BoardFile::H];
 
 impl CastlingRights {
     pub fn none() -> CastlingRights {
-        CastlingRights(0)
+        CastlingRights {
+            rights: 0,
+            rook_files: STANDARD_ROOK_FILES,
+        }
     }
 
     pub fn all() -> CastlingRights {
-        CastlingRights(0b00001111)
+        CastlingRights {
+            rights: 0b00001111,
+            rook_files: STANDARD_ROOK_FILES,
+        }
     }
 
     pub fn new(
@@ -630,60 +666,97 @@ impl CastlingRights {
             val |= 0b1000;
         }
 
-        CastlingRights(val)
+        CastlingRights {
+            rights: val,
+            rook_files: STANDARD_ROOK_FILES,
+        }
     }
 
-    pub fn queenside(self, color: Color) -> bool {
-        if color.is_white() {
-            (self.0 & 0b0001) != 0
-        } else {
-            (self.0 & 0b0100) != 0
+    const fn index(color: Color, kingside: bool) -> usize {
+        (if color.is_white() { 0 } else { 2 }) + (if kingside { 1 } else { 0 })
+    }
+
+    /// Add the right to castle with the rook on `rook_file`.
+    pub fn with_right(self, color: Color, kingside: bool, rook_file: BoardFile) -> CastlingRights {
+        let index = CastlingRights::index(color, kingside);
+        let mut rook_files = self.rook_files;
+        rook_files[index] = rook_file;
+
+        CastlingRights {
+            rights: self.rights | (1 << index),
+            rook_files,
         }
     }
 
+    pub fn queenside(self, color: Color) -> bool {
+        (self.rights & (1 << CastlingRights::index(color, false))) != 0
+    }
+
     pub fn kingside(self, color: Color) -> bool {
-        if color.is_white() {
-            (self.0 & 0b0010) != 0
-        } else {
-            (self.0 & 0b1000) != 0
-        }
+        (self.rights & (1 << CastlingRights::index(color, true))) != 0
+    }
+
+    /// The square of the castling rook, if the right to castle on that side is held.
+    pub fn rook_square(self, color: Color, kingside: bool) -> Option<Square> {
+        let index = CastlingRights::index(color, kingside);
+        ((self.rights & (1 << index)) != 0)
+            .then(|| Square::at(self.rook_files[index], color.back_rank()))
+    }
+
+    /// Whether every held right is with a rook on the a or h file.
+    pub fn has_standard_rook_files(self) -> bool {
+        (0..4)
+            .all(|i| (self.rights & (1 << i)) == 0 || self.rook_files[i] == STANDARD_ROOK_FILES[i])
+    }
+
+    fn with_mask(self, hash: BoardHash, mask: u8) -> (BoardHash, CastlingRights) {
+        let new_rights = CastlingRights {
+            rights: self.rights & mask,
+            rook_files: self.rook_files,
+        };
+        (hash.update_castling_rights(self, new_rights), new_rights)
     }
 
     pub fn without_color(self, hash: BoardHash, color: Color) -> (BoardHash, CastlingRights) {
         if color.is_white() {
-            let new_rights = CastlingRights(self.0 & 0b11111100);
-            (hash.update_castling_rights(self, new_rights), new_rights)
+            self.with_mask(hash, 0b11111100)
         } else {
-            let new_rights = CastlingRights(self.0 & 0b11110011);
-            (hash.update_castling_rights(self, new_rights), new_rights)
+            self.with_mask(hash, 0b11110011)
         }
     }
 
     pub fn without_kingside(self, hash: BoardHash, color: Color) -> (BoardHash, CastlingRights) {
         if color.is_white() {
-            let new_rights = CastlingRights(self.0 & 0b11111101);
-            (hash.update_castling_rights(self, new_rights), new_rights)
+            self.with_mask(hash, 0b11111101)
         } else {
-            let new_rights = CastlingRights(self.0 & 0b11110111);
-            (hash.update_castling_rights(self, new_rights), new_rights)
+            self.with_mask(hash, 0b11110111)
         }
     }
 
     pub fn without_queenside(self, hash: BoardHash, color: Color) -> (BoardHash, CastlingRights) {
         if color.is_white() {
-            let new_rights = CastlingRights(self.0 & 0b11111110);
-            (hash.update_castling_rights(self, new_rights), new_rights)
+            self.with_mask(hash, 0b11111110)
         } else {
-            let new_rights = CastlingRights(self.0 & 0b11111011);
-            (hash.update_castling_rights(self, new_rights), new_rights)
+            self.with_mask(hash, 0b11111011)
         }
     }
 
     pub const fn as_u8(&self) -> u8 {
-        self.0
+        self.rights
+    }
+}
+
+// Rook files only matter for the rights that are held
+impl PartialEq for CastlingRights {
+    fn eq(&self, other: &Self) -> bool {
+        self.rights == other.rights
+            && (0..4)
+                .all(|i| (self.rights & (1 << i)) == 0 || self.rook_files[i] == other.rook_files[i])
     }
 }
 
+impl Eq for CastlingRights {}
+
 impl Default for CastlingRights {
     fn default() -> Self {
         CastlingRights::all()
@@ -698,6 +771,8 @@ pub struct UnmakeInfo {
     pub source: Square,
     pub destination: Square,
     pub captured: Option<PieceKind>,
+    /// Original square of the rook, if the move was castling
+    pub castling_rook: Option<Square>,
     pub old_en_passant_destination: Option<Square>,
     pub old_castling_rights: CastlingRights,
     pub old_halfmoves_since_event: u32,
@@ -881,6 +956,240 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_unmake_chess960_castle() {
+        // King already on its destination square
+        test_move_and_unmake(
+            "1r2k1r1/8/8/8/8/8/8/R1K3R1 w KQkq - 0 1",
+            Move::new(Square::C1, Square::A1, None),
+            "1r2k1r1/8/8/8/8/8/8/2KR2R1 b kq - 1 1",
+        );
+        // Rook already on its destination square
+        test_move_and_unmake(
+            "1r2k1r1/8/8/8/8/8/8/R1K3R1 b KQkq - 0 1",
+            Move::new(Square::E8, Square::G8, None),
+            "1r3rk1/8/8/8/8/8/8/R1K3R1 w KQ - 1 2",
+        );
+    }
+
+    #[test]
+    fn test_chess960_fen() {
+        // Shredder-FEN is read, X-FEN is written
+        let board = Board::from_fen("1r2k1r1/8/8/8/8/8/8/R1K3R1 w AGbg - 0 1").unwrap();
+        assert!(board.is_chess960());
+        assert_eq!(board.to_fen(), "1r2k1r1/8/8/8/8/8/8/R1K3R1 w KQkq - 0 1");
+
+        // The rook file is needed when the castling rook isn't the outermost
+        const INNER_ROOK: &str = "4k3/8/8/8/8/8/8/RR2K3 w B - 0 1";
+        let board = Board::from_fen(INNER_ROOK).unwrap();
+        assert_eq!(
+            board.castling_rights().rook_square(Color::White, false),
+            Some(Square::B1)
+        );
+        assert_eq!(board.to_fen(), INNER_ROOK);
+
+        assert!(!Board::starting().is_chess960());
+    }
+
+    #[test]
+    fn test_starting_chess960() {
+        assert_eq!(
+            Board::starting_chess960(518).unwrap().to_fen(),
+            Board::starting().to_fen()
+        );
+        assert_eq!(
+            Board::starting_chess960(0).unwrap().to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert_eq!(
+            Board::starting_chess960(959).unwrap().to_fen(),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1"
+        );
+        assert!(Board::starting_chess960(960).is_none());
+    }
+
+    #[test]
+    fn test_rook_capture_voids_castling() {
+        test_move_and_unmake(
+            "r3k2r/1P6/8/8/8/8/8/4K3 w kq - 0 1",
+            Move::new(Square::B7, Square::A8, Some(PieceKind::Queen)),
+            "Q3k2r/8/8/8/8/8/8/4K3 b k - 0 1",
+        );
+    }
+
+    #[test]
+    fn test_normalise_move() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let e1g1 = Move::new(Square::E1, Square::G1, None);
+        let e1c1 = Move::new(Square::E1, Square::C1, None);
+
+        assert_eq!(board.normalise_move(e1g1), Some(e1g1));
+        assert_eq!(
+            board.normalise_move(Move::new(Square::E1, Square::H1, None)),
+            Some(e1g1)
+        );
+        assert_eq!(
+            board.normalise_move(Move::new(Square::E1, Square::A1, None)),
+            Some(e1c1)
+        );
+        assert_eq!(
+            board.normalise_move(Move::new(Square::E2, Square::E4, None)),
+            None
+        );
+
+        // Chess960 boards use king-takes-rook
+        let mut board = board;
+        board.set_chess960(true);
+        assert_eq!(
+            board.normalise_move(e1g1),
+            Some(Move::new(Square::E1, Square::H1, None))
+        );
+
+        // King moving onto its rook without castling rights is illegal
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w kq - 0 1").unwrap();
+        assert_eq!(
+            board.normalise_move(Move::new(Square::E1, Square::H1, None)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_polyglot_key() {
+        // Keys from the Polyglot book format specification
+        let cases: [(&[&str], u64); 9] = [
+            (&[], 0x463b96181691fc9c),
+            (&["e2e4"], 0x823c9b50fd114196),
+            (&["e2e4", "d7d5"], 0x0756b94461c50fb0),
+            (&["e2e4", "d7d5", "e4e5"], 0x662fafb965db29d4),
+            (&["e2e4", "d7d5", "e4e5", "f7f5"], 0x22a48b5a8e47ff78),
+            (
+                &["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"],
+                0x652a607ca3f242c1,
+            ),
+            (
+                &["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"],
+                0x00fdd303c946bdd9,
+            ),
+            (
+                &["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"],
+                0x3c8123ea7b067637,
+            ),
+            (
+                &["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"],
+                0x5c3f9b829b279560,
+            ),
+        ];
+
+        for (moves, key) in cases {
+            let mut board = Board::starting();
+            for mv in moves {
+                board.make_move(Move::from_uci(mv).unwrap());
+            }
+            assert_eq!(
+                board.polyglot_key(),
+                PolyglotKey::from_u64(key),
+                "{moves:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_polyglot_key_incremental() {
+        // Castling, en passant, promotion and captures of castling rooks
+        let games: [(&str, &[&str]); 3] = [
+            (
+                "r3k2r/1pp4p/8/P7/8/8/1pP4P/RN2K2R w KQkq - 0 1",
+                &["c2c4", "b2a1q", "e1g1", "b7b5", "a5b6", "e8c8", "b6c7"],
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                &[
+                    "e2e4", "d7d5", "e4d5", "e7e5", "d5e6", "f8b4", "e6f7", "e8f8", "f7g8n",
+                ],
+            ),
+            (
+                "rk4r1/p5p1/8/8/8/8/P5P1/RK4R1 w AGag - 0 1",
+                &["b1g1", "b8a8", "f1f8", "d8f8"],
+            ),
+        ];
+
+        for (fen, moves) in games {
+            let mut board = Board::from_fen(fen).unwrap();
+            let mut unmakes = Vec::new();
+            let mut keys = vec![board.polyglot_key()];
+            for mv in moves {
+                let mv = board
+                    .normalise_move(Move::from_uci(mv).unwrap())
+                    .unwrap_or_else(|| panic!("{mv} illegal in {}", board.to_fen()));
+                unmakes.push(board.make_move(mv));
+                assert_eq!(
+                    board.polyglot_key(),
+                    PolyglotKey::from_board(&board),
+                    "{mv:?}"
+                );
+                keys.push(board.polyglot_key());
+            }
+
+            while let Some(um) = unmakes.pop() {
+                keys.pop();
+                board.unmake_last_move(um);
+                assert_eq!(Some(&board.polyglot_key()), keys.last());
+            }
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        let status = |fen: &str| Board::from_fen(fen).unwrap().status();
+
+        assert_eq!(Board::starting().status(), GameStatus::Ongoing);
+        assert_eq!(
+            status("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"),
+            GameStatus::Checkmate(Color::Black)
+        );
+        assert_eq!(
+            status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"),
+            GameStatus::Stalemate
+        );
+        assert_eq!(
+            status("7k/8/6K1/8/8/8/8/R7 w - - 100 80"),
+            GameStatus::FiftyMoves
+        );
+        // Mate on the 100th halfmove still counts
+        assert_eq!(
+            status("R6k/8/6K1/8/8/8/8/8 b - - 100 80"),
+            GameStatus::Checkmate(Color::White)
+        );
+
+        assert_eq!(
+            status("7k/8/6K1/8/8/8/8/6N1 w - - 0 1"),
+            GameStatus::InsufficientMaterial
+        );
+        // Bishops on the same colour squares
+        assert_eq!(
+            status("6bk/8/6K1/8/8/8/8/1B6 w - - 0 1"),
+            GameStatus::InsufficientMaterial
+        );
+        assert_eq!(
+            status("5b1k/8/6K1/8/8/8/8/1B6 w - - 0 1"),
+            GameStatus::Ongoing
+        );
+        assert_eq!(
+            status("7k/8/6K1/8/8/8/8/5NN1 w - - 0 1"),
+            GameStatus::Ongoing
+        );
+        assert_eq!(status("7k/8/6K1/8/8/8/P7/8 w - - 0 1"), GameStatus::Ongoing);
+
+        let mut board = Board::starting();
+        for mv in [
+            "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8", "g1f3",
+        ] {
+            board.make_move(Move::from_uci(mv).unwrap());
+        }
+        assert_eq!(board.status(), GameStatus::ThreefoldRepetition);
+        assert_eq!(board.status().result(), GameResult::Draw);
+    }
+
     #[test]
     fn test_halfmove_since_event_counter() {}
 }
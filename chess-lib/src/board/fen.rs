@@ -0,0 +1,563 @@
+//! https://www.chessprogramming.org/Forsyth-Edwards_Notation
+//! Reading and writing FEN, including X-FEN and Shredder-FEN castling for Chess960, and checking
+//! that a position is legal.
+
+use crate::board::{Board, BoardFile, BoardRank, CastlingRights, Color, Piece, PieceKind, Square};
+use crate::movegen::{MoveList, compute_legal_moves, king_attacks};
+use std::fmt::{Display, Formatter};
+use strum::IntoEnumIterator;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    /// Wrong number of space separated fields.
+    FieldCount(usize),
+    /// Wrong number of '/' separated ranks.
+    RankCount(usize),
+    /// A rank which doesn't describe exactly 8 squares.
+    RankLength(BoardRank),
+    InvalidPiece(char),
+    InvalidColor(String),
+    InvalidCastling(char),
+    InvalidEnPassant(String),
+    InvalidHalfmoves(String),
+    InvalidFullmoves(String),
+    /// The FEN is well-formed, but the position is not legal.
+    IllegalPosition(PositionError),
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::FieldCount(n) => write!(f, "wrong number of fields, found {n}"),
+            FenError::RankCount(n) => write!(f, "must have 8 ranks, found {n}"),
+            FenError::RankLength(rank) => {
+                write!(f, "rank {} must have 8 squares", rank.as_char())
+            }
+            FenError::InvalidPiece(c) => write!(f, "unrecognised piece '{c}'"),
+            FenError::InvalidColor(s) => write!(f, "unrecognised colour to move '{s}'"),
+            FenError::InvalidCastling(c) => write!(f, "unrecognised castling character '{c}'"),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{s}'"),
+            FenError::InvalidHalfmoves(s) => write!(f, "halfmoves '{s}' not a number"),
+            FenError::InvalidFullmoves(s) => write!(f, "fullmoves '{s}' not a number"),
+            FenError::IllegalPosition(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl From<PositionError> for FenError {
+    fn from(e: PositionError) -> Self {
+        FenError::IllegalPosition(e)
+    }
+}
+
+/// Reasons a position can't occur in a game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PositionError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    AdjacentKings,
+    /// The side that just moved has left its king in check.
+    OpponentInCheck,
+    PawnOnBackRank(Square),
+    /// A castling right is held without the king and rook on their squares.
+    InvalidCastlingRights {
+        color: Color,
+        kingside: bool,
+    },
+    /// The en passant square doesn't follow a double pawn push.
+    InvalidEnPassant(Square),
+}
+
+impl Display for PositionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let color_name = |color: Color| if color.is_white() { "white" } else { "black" };
+
+        match self {
+            PositionError::MissingKing(color) => write!(f, "{} has no king", color_name(*color)),
+            PositionError::TooManyKings(color) => {
+                write!(f, "{} has more than one king", color_name(*color))
+            }
+            PositionError::AdjacentKings => write!(f, "kings are adjacent"),
+            PositionError::OpponentInCheck => write!(f, "side not to move is in check"),
+            PositionError::PawnOnBackRank(square) => {
+                write!(f, "pawn on back rank at {}", square.name())
+            }
+            PositionError::InvalidCastlingRights { color, kingside } => write!(
+                f,
+                "{} {} castling right without king and rook in place",
+                color_name(*color),
+                if *kingside { "kingside" } else { "queenside" }
+            ),
+            PositionError::InvalidEnPassant(square) => {
+                write!(f, "invalid en passant square {}", square.name())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+impl Board {
+    /// Parse a FEN with all 6 fields, checking that the position is legal.
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let fields = fen.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() != 6 {
+            return Err(FenError::FieldCount(fields.len()));
+        }
+
+        Board::from_fen_fields(&fields)
+    }
+
+    /// Parse a FEN which may omit the halfmove and fullmove clocks, as in EPD. Missing clocks
+    /// default to 0 and 1.
+    pub fn from_fen_lenient(fen: &str) -> Result<Board, FenError> {
+        let mut fields = fen.split_whitespace().collect::<Vec<&str>>();
+        if !(4..=6).contains(&fields.len()) {
+            return Err(FenError::FieldCount(fields.len()));
+        }
+
+        let defaults = ["0", "1"];
+        let missing = 6 - fields.len();
+        fields.extend_from_slice(&defaults[2 - missing..]);
+
+        Board::from_fen_fields(&fields)
+    }
+
+    fn from_fen_fields(fields: &[&str]) -> Result<Board, FenError> {
+        // * Board
+        let mut pieces = [None; 64];
+        let ranks = fields[0].split("/").collect::<Vec<&str>>();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
+        for (rank, rank_str) in BoardRank::iter().rev().zip(ranks) {
+            let mut f: usize = 0;
+            for c in rank_str.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    f += empty as usize;
+                    continue;
+                }
+
+                if f >= 8 {
+                    return Err(FenError::RankLength(rank));
+                }
+
+                let Some(piece) = Piece::from_char(c) else {
+                    return Err(FenError::InvalidPiece(c));
+                };
+
+                pieces[rank.as_u8() as usize * 8 + f] = Some(piece);
+
+                f += 1;
+            }
+
+            if f != 8 {
+                return Err(FenError::RankLength(rank));
+            }
+        }
+
+        // * To move
+        let to_move = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            s => return Err(FenError::InvalidColor(s.to_string())),
+        };
+
+        // * Castling availability
+        // KQkq as standard, where in Chess960 K/Q mean the outermost rook (X-FEN), or the files
+        // of the castling rooks (Shredder-FEN).
+        let get_piece = |sq: Square| pieces[sq.as_u8() as usize];
+        let mut castling_rights = CastlingRights::none();
+        let mut chess960 = false;
+        for c in fields[2].chars().filter(|&c| c != '-') {
+            let color = Color::from_is_white(c.is_ascii_uppercase());
+            let (kingside, rook_file) = match c.to_ascii_lowercase() {
+                'k' => (
+                    true,
+                    outermost_rook_file(get_piece, color, true).unwrap_or(BoardFile::H),
+                ),
+                'q' => (
+                    false,
+                    outermost_rook_file(get_piece, color, false).unwrap_or(BoardFile::A),
+                ),
+                file @ 'a'..='h' => {
+                    let Some(king_file) = king_file(get_piece, color) else {
+                        return Err(FenError::InvalidCastling(c));
+                    };
+                    let rook_file = BoardFile::from_char(file).unwrap();
+                    chess960 = true;
+                    (rook_file > king_file, rook_file)
+                }
+                _ => return Err(FenError::InvalidCastling(c)),
+            };
+            castling_rights = castling_rights.with_right(color, kingside, rook_file);
+        }
+
+        // Castling with the king or rooks away from their standard squares is only possible in
+        // Chess960
+        for color in [Color::White, Color::Black] {
+            if (castling_rights.kingside(color) || castling_rights.queenside(color))
+                && king_file(get_piece, color).is_some_and(|f| f != BoardFile::E)
+            {
+                chess960 = true;
+            }
+        }
+        chess960 |= !castling_rights.has_standard_rook_files();
+
+        // * En Passant
+        let en_passant = match fields[3] {
+            "-" => None,
+            s => Some(
+                Square::from_name(s)
+                    .filter(|_| s.len() == 2)
+                    .ok_or_else(|| FenError::InvalidEnPassant(s.to_string()))?,
+            ),
+        };
+
+        // * Halfmoves since last capture or pawn move
+        let Ok(halfmoves_since_event): Result<u32, _> = fields[4].parse() else {
+            return Err(FenError::InvalidHalfmoves(fields[4].to_string()));
+        };
+
+        // * Fullmoves
+        let Ok(fullmoves): Result<u32, _> = fields[5].parse() else {
+            return Err(FenError::InvalidFullmoves(fields[5].to_string()));
+        };
+
+        let mut board = Board::new(
+            &pieces,
+            to_move,
+            en_passant,
+            castling_rights,
+            halfmoves_since_event,
+            fullmoves,
+        );
+        board.chess960 = chess960;
+
+        board.validate()?;
+
+        Ok(board)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut output = String::new();
+
+        let mut cur_x = 0;
+
+        for y in (0u8..8).rev() {
+            for x in 0u8..8 {
+                debug_assert!(x < 8);
+                debug_assert!(y < 8);
+                // SAFETY: Range limited
+                unsafe {
+                    if let Some(piece) = self.pieces.get(Square::at_xy_unchecked(x, y)) {
+                        if cur_x != x {
+                            output += &format!("{}", x - cur_x);
+                            cur_x = x;
+                        }
+
+                        output.push(piece.as_char());
+
+                        cur_x += 1;
+                    }
+                }
+            }
+
+            if cur_x != 8 {
+                output += &format!("{}", 8 - cur_x);
+            }
+            cur_x = 0;
+
+            if y != 0 {
+                output.push('/');
+            }
+        }
+
+        output += &format!(" {} ", self.color_to_move.as_char());
+
+        // X-FEN - the rook file is only given if K/Q would refer to a different rook
+        let mut castling_string = String::new();
+        for (color, kingside, c) in [
+            (Color::White, true, 'K'),
+            (Color::White, false, 'Q'),
+            (Color::Black, true, 'k'),
+            (Color::Black, false, 'q'),
+        ] {
+            let Some(rook_square) = self.castling_rights.rook_square(color, kingside) else {
+                continue;
+            };
+
+            if self.chess960
+                && outermost_rook_file(|sq| self.pieces.get(sq), color, kingside)
+                    != Some(rook_square.file())
+            {
+                let file = rook_square.file().as_char();
+                castling_string.push(if color.is_white() {
+                    file.to_ascii_uppercase()
+                } else {
+                    file
+                });
+            } else {
+                castling_string.push(c);
+            }
+        }
+        if castling_string.is_empty() {
+            castling_string.push('-');
+        }
+
+        output += &castling_string;
+
+        if let Some(square) = self.en_passant_destination {
+            output += &format!(" {}", square.name())
+        } else {
+            output += " -";
+        }
+
+        output += &format!(" {} {}", self.halfmoves_since_event, self.fullmoves);
+
+        output
+    }
+
+    /// Check that the position could occur in a game - `compute_legal_moves` may panic on a
+    /// position that fails this check. Positions from `from_fen` are always valid.
+    pub fn validate(&self) -> Result<(), PositionError> {
+        let mut king_squares = [Square::A1; 2];
+        for (i, color) in [Color::White, Color::Black].into_iter().enumerate() {
+            let kings = self
+                .pieces
+                .piece_bitboard(Piece::new(PieceKind::King, color));
+            let mut kings = kings.iter();
+            king_squares[i] = kings.next().ok_or(PositionError::MissingKing(color))?;
+            if kings.next().is_some() {
+                return Err(PositionError::TooManyKings(color));
+            }
+        }
+        if king_attacks(king_squares[0]).contains(king_squares[1]) {
+            return Err(PositionError::AdjacentKings);
+        }
+
+        for (square, piece) in self.pieces.iter() {
+            if piece.kind() == PieceKind::Pawn
+                && (square.rank() == BoardRank::R1 || square.rank() == BoardRank::R8)
+            {
+                return Err(PositionError::PawnOnBackRank(square));
+            }
+        }
+
+        for color in [Color::White, Color::Black] {
+            for kingside in [true, false] {
+                let Some(rook_square) = self.castling_rights.rook_square(color, kingside) else {
+                    continue;
+                };
+                let king_file = king_file(|sq| self.pieces.get(sq), color);
+                let is_valid = self.pieces.get(rook_square)
+                    == Some(Piece::new(PieceKind::Rook, color))
+                    && king_file.is_some_and(|f| (rook_square.file() > f) == kingside);
+                if !is_valid {
+                    return Err(PositionError::InvalidCastlingRights { color, kingside });
+                }
+            }
+        }
+
+        if let Some(square) = self.en_passant_destination {
+            // The pawn that just moved is behind the square, and the squares it passed over are
+            // empty
+            let up = self.color_to_move.up();
+            let expected_rank = if self.color_to_move.is_white() {
+                BoardRank::R6
+            } else {
+                BoardRank::R3
+            };
+            let is_valid = square.rank() == expected_rank
+                && self.pieces.get(square).is_none()
+                && square
+                    .translated_by((0, up))
+                    .is_some_and(|s| self.pieces.get(s).is_none())
+                && square.translated_by((0, -up)).is_some_and(|s| {
+                    self.pieces.get(s) == Some(Piece::new(PieceKind::Pawn, !self.color_to_move))
+                });
+            if !is_valid {
+                return Err(PositionError::InvalidEnPassant(square));
+            }
+        }
+
+        // Generate moves for the side not to move, to find whether its king is attacked
+        let mut flipped = self.clone();
+        flipped.color_to_move = !self.color_to_move;
+        flipped.en_passant_destination = None;
+        if compute_legal_moves(&mut MoveList::new(), &flipped) {
+            return Err(PositionError::OpponentInCheck);
+        }
+
+        Ok(())
+    }
+}
+
+/// The file of the king of `color` on its back rank, if it is there.
+fn king_file(get_piece: impl Fn(Square) -> Option<Piece>, color: Color) -> Option<BoardFile> {
+    BoardFile::iter().find(|&f| {
+        get_piece(Square::at(f, color.back_rank())) == Some(Piece::new(PieceKind::King, color))
+    })
+}
+
+/// The file of the rook furthest from the king on the given side, on the back rank of `color`.
+fn outermost_rook_file(
+    get_piece: impl Fn(Square) -> Option<Piece>,
+    color: Color,
+    kingside: bool,
+) -> Option<BoardFile> {
+    let king_file = king_file(&get_piece, color)?;
+    let is_rook = |f: BoardFile| {
+        get_piece(Square::at(f, color.back_rank())) == Some(Piece::new(PieceKind::Rook, color))
+    };
+
+    if kingside {
+        BoardFile::iter()
+            .rev()
+            .take_while(|&f| f > king_file)
+            .find(|&f| is_rook(f))
+    } else {
+        BoardFile::iter()
+            .take_while(|&f| f < king_file)
+            .find(|&f| is_rook(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fen_syntax_errors() {
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0",
+                FenError::FieldCount(5),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::RankCount(7),
+            ),
+            (
+                "rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::RankLength(BoardRank::R7),
+            ),
+            (
+                "rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::RankLength(BoardRank::R6),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+                FenError::InvalidPiece('X'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+                FenError::InvalidColor("x".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+                FenError::InvalidCastling('x'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+                FenError::InvalidEnPassant("e9".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - a 1",
+                FenError::InvalidHalfmoves("a".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 -1",
+                FenError::InvalidFullmoves("-1".to_string()),
+            ),
+        ];
+
+        for (fen, error) in cases {
+            assert_eq!(Board::from_fen(fen), Err(error), "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_illegal_positions() {
+        let cases = [
+            (
+                "8/8/8/8/8/8/8/4K3 w - - 0 1",
+                PositionError::MissingKing(Color::Black),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+                PositionError::TooManyKings(Color::White),
+            ),
+            (
+                "8/8/8/8/8/8/4k3/4K3 w - - 0 1",
+                PositionError::AdjacentKings,
+            ),
+            (
+                "4k2R/8/8/8/8/8/8/4K3 w - - 0 1",
+                PositionError::OpponentInCheck,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/P3K3 w - - 0 1",
+                PositionError::PawnOnBackRank(Square::A1),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+                PositionError::InvalidCastlingRights {
+                    color: Color::White,
+                    kingside: true,
+                },
+            ),
+            (
+                "4k2r/8/8/8/8/8/8/4K3 w q - 0 1",
+                PositionError::InvalidCastlingRights {
+                    color: Color::Black,
+                    kingside: false,
+                },
+            ),
+            (
+                "4k3/8/8/8/4P3/8/8/4K3 b - e4 0 1",
+                PositionError::InvalidEnPassant(Square::E4),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 b - e3 0 1",
+                PositionError::InvalidEnPassant(Square::E3),
+            ),
+        ];
+
+        for (fen, error) in cases {
+            assert_eq!(
+                Board::from_fen(fen),
+                Err(FenError::IllegalPosition(error)),
+                "{fen}"
+            );
+        }
+
+        assert_eq!(Board::starting().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_lenient_fen() {
+        assert_eq!(
+            Board::from_fen_lenient("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -")
+                .unwrap()
+                .to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert_eq!(
+            Board::from_fen_lenient("4k3/8/8/8/8/8/8/4K3 w - - 7")
+                .unwrap()
+                .to_fen(),
+            "4k3/8/8/8/8/8/8/4K3 w - - 7 1"
+        );
+        assert_eq!(
+            Board::from_fen_lenient("4k3/8/8/8/8/8/8/4K3 w -"),
+            Err(FenError::FieldCount(3))
+        );
+        assert_eq!(
+            Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - -"),
+            Err(FenError::FieldCount(4))
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::board::hash::BoardHash;
+use crate::board::hash::{BoardHash, PolyglotKey};
 use crate::board::{Color, Piece, PieceKind, bitboard::Bitboard, square::Square};
 use strum::IntoEnumIterator;
 
@@ -6,6 +6,8 @@ use strum::IntoEnumIterator;
 pub struct PieceStorage {
     piece_bitboards: [Bitboard; 12],
     square_contents: [Option<Piece>; 64],
+    /// Piece placement part of the Polyglot key, kept up to date by `set`.
+    polyglot_key: PolyglotKey,
 }
 
 impl Default for PieceStorage {
@@ -19,6 +21,7 @@ impl PieceStorage {
         Self {
             square_contents: [None; 64],
             piece_bitboards: [Bitboard::empty(); 12],
+            polyglot_key: PolyglotKey::zero(),
         }
     }
 
@@ -35,12 +38,14 @@ impl PieceStorage {
         // update old piece bitboard
         if let Some(piece) = self.get(sq) {
             hash = hash.toggle_piece(piece, sq);
+            self.polyglot_key = self.polyglot_key.toggle_piece(piece, sq);
             self.piece_bitboards[piece.as_u8() as usize].remove(sq);
         }
 
         // update new piece bitboard
         if let Some(piece) = contents {
             hash = hash.toggle_piece(piece, sq);
+            self.polyglot_key = self.polyglot_key.toggle_piece(piece, sq);
             self.piece_bitboards[piece.as_u8() as usize].insert(sq);
         }
 
@@ -70,4 +75,9 @@ impl PieceStorage {
     pub fn piece_bitboard(&self, piece: Piece) -> Bitboard {
         self.piece_bitboards[piece.as_u8() as usize]
     }
+
+    /// The Polyglot key of the piece placement alone.
+    pub const fn polyglot_key(&self) -> PolyglotKey {
+        self.polyglot_key
+    }
 }
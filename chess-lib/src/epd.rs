@@ -0,0 +1,460 @@
+//! https://www.chessprogramming.org/Extended_Position_Description
+//! Reading and writing of EPD records, as used by test suites such as WAC, STS and Bratko-Kopec.
+//! A record is the first four FEN fields followed by `;` terminated operations, each an opcode
+//! and its operands. Move operands are SAN, and are resolved to `Move`s when read.
+
+use crate::board::{Board, FenError, Move};
+use crate::san::{SanError, move_to_san, parse_san};
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EpdError {
+    /// The position fields are missing or invalid.
+    InvalidFen(FenError),
+    /// An operation is malformed, e.g. an unterminated string.
+    Syntax(String),
+    /// A move operand of `opcode` isn't legal in the position.
+    IllegalMove { opcode: String, error: SanError },
+    /// An operand of `opcode` has the wrong type, e.g. a non-numeric `acd`.
+    InvalidOperand { opcode: String, operand: String },
+}
+
+impl Display for EpdError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpdError::InvalidFen(e) => write!(f, "invalid position - {e}"),
+            EpdError::Syntax(message) => write!(f, "{message}"),
+            EpdError::IllegalMove { opcode, error } => write!(f, "{opcode}: {error}"),
+            EpdError::InvalidOperand { opcode, operand } => {
+                write!(f, "{opcode}: invalid operand '{operand}'")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+impl From<FenError> for EpdError {
+    fn from(e: FenError) -> Self {
+        EpdError::InvalidFen(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Epd {
+    /// The position, with clocks from the `hmvc` and `fmvn` operations if present.
+    pub board: Board,
+    /// `bm` - any of these moves solves the position.
+    pub best_moves: Vec<Move>,
+    /// `am` - none of these moves may be played.
+    pub avoid_moves: Vec<Move>,
+    /// `id` - the name of the position, e.g. "WAC.001".
+    pub id: Option<String>,
+    /// `pv` - the expected principal variation, from the position.
+    pub pv: Vec<Move>,
+    /// `ce` - centipawn evaluation, from the point of view of the side to move.
+    pub centipawn_eval: Option<i32>,
+    /// `acd` - depth of the analysis that produced the record.
+    pub analysis_depth: Option<u32>,
+    /// `c0` to `c9`.
+    pub comments: [Option<String>; 10],
+    /// Any other operations, in the order they were read.
+    pub other: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    pub fn new(board: Board) -> Epd {
+        Epd {
+            board,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            id: None,
+            pv: Vec::new(),
+            centipawn_eval: None,
+            analysis_depth: None,
+            comments: Default::default(),
+            other: Vec::new(),
+        }
+    }
+
+    /// Parse a single EPD record. For convenience, the halfmove and fullmove clocks of a FEN may
+    /// also appear after the four position fields.
+    pub fn from_epd(epd: &str) -> Result<Epd, EpdError> {
+        let mut fields = Vec::new();
+        let mut rest = epd.trim();
+        while fields.len() < 4 {
+            let Some((field, remaining)) = next_field(rest) else {
+                return Err(FenError::FieldCount(fields.len()).into());
+            };
+            fields.push(field);
+            rest = remaining;
+        }
+
+        let mut clocks = Vec::new();
+        while clocks.len() < 2 {
+            match next_field(rest) {
+                Some((field, remaining)) if field.parse::<u32>().is_ok() => {
+                    clocks.push(field);
+                    rest = remaining;
+                }
+                _ => break,
+            }
+        }
+
+        let operations = split_operations(rest)?;
+
+        // The hmvc and fmvn operations take the place of the FEN clocks
+        let operand = |opcode: &str| {
+            operations
+                .iter()
+                .find(|(o, _)| o == opcode)
+                .and_then(|(_, operands)| operands.first())
+                .map(String::as_str)
+        };
+        if clocks.is_empty() {
+            if let Some(hmvc) = operand("hmvc") {
+                clocks.push(hmvc);
+            }
+            if let Some(fmvn) = operand("fmvn") {
+                if clocks.is_empty() {
+                    clocks.push("0");
+                }
+                clocks.push(fmvn);
+            }
+        }
+        fields.extend(clocks);
+
+        let mut epd = Epd::new(Board::from_fen_lenient(&fields.join(" "))?);
+
+        for (opcode, operands) in operations {
+            let single_operand = || match operands.as_slice() {
+                [operand] => Ok(operand.clone()),
+                _ => Err(EpdError::InvalidOperand {
+                    opcode: opcode.clone(),
+                    operand: operands.join(" "),
+                }),
+            };
+
+            match opcode.as_str() {
+                "bm" => epd.best_moves = epd.parse_moves(&opcode, &operands, false)?,
+                "am" => epd.avoid_moves = epd.parse_moves(&opcode, &operands, false)?,
+                "pv" => epd.pv = epd.parse_moves(&opcode, &operands, true)?,
+                "id" => epd.id = Some(single_operand()?),
+                "ce" => epd.centipawn_eval = Some(parse_number(&opcode, single_operand()?)?),
+                "acd" => epd.analysis_depth = Some(parse_number(&opcode, single_operand()?)?),
+                "hmvc" | "fmvn" => {}
+                c if c.len() == 2 && c.starts_with('c') && c.as_bytes()[1].is_ascii_digit() => {
+                    let index = (c.as_bytes()[1] - b'0') as usize;
+                    epd.comments[index] = Some(operands.join(" "));
+                }
+                _ => epd.other.push((opcode, operands)),
+            }
+        }
+
+        Ok(epd)
+    }
+
+    /// Resolve SAN operands against the position. For a variation each move is played before the
+    /// next is resolved, otherwise every move is from the position itself.
+    fn parse_moves(
+        &self,
+        opcode: &str,
+        operands: &[String],
+        is_variation: bool,
+    ) -> Result<Vec<Move>, EpdError> {
+        let mut board = self.board.clone();
+        let mut moves = Vec::with_capacity(operands.len());
+
+        for san in operands {
+            let mv = parse_san(&board, san).map_err(|error| EpdError::IllegalMove {
+                opcode: opcode.to_string(),
+                error,
+            })?;
+            if is_variation {
+                board.make_move(mv);
+            }
+            moves.push(mv);
+        }
+
+        Ok(moves)
+    }
+
+    /// Whether `mv` solves the position: it must be one of the best moves if any are given, and
+    /// none of the moves to avoid.
+    pub fn is_solution(&self, mv: Move) -> bool {
+        let mv = self.board.normalise_move(mv).unwrap_or(mv);
+        (self.best_moves.is_empty() || self.best_moves.contains(&mv))
+            && !self.avoid_moves.contains(&mv)
+    }
+
+    pub fn to_epd(&self) -> String {
+        let fen = self.board.to_fen();
+        let mut output = fen.split(' ').take(4).collect::<Vec<_>>().join(" ");
+
+        let sans = |moves: &[Move], is_variation: bool| {
+            let mut board = self.board.clone();
+            let mut sans = Vec::with_capacity(moves.len());
+            for &mv in moves {
+                sans.push(move_to_san(&mut board, mv));
+                if is_variation {
+                    board.make_move(mv);
+                }
+            }
+            sans
+        };
+
+        let mut operations = Vec::new();
+        if !self.best_moves.is_empty() {
+            operations.push(("bm".to_string(), sans(&self.best_moves, false)));
+        }
+        if !self.avoid_moves.is_empty() {
+            operations.push(("am".to_string(), sans(&self.avoid_moves, false)));
+        }
+        if !self.pv.is_empty() {
+            operations.push(("pv".to_string(), sans(&self.pv, true)));
+        }
+        if let Some(ce) = self.centipawn_eval {
+            operations.push(("ce".to_string(), vec![ce.to_string()]));
+        }
+        if let Some(acd) = self.analysis_depth {
+            operations.push(("acd".to_string(), vec![acd.to_string()]));
+        }
+        if self.board.halfmoves_since_event() != 0 || self.board.fullmoves() != 1 {
+            let hmvc = self.board.halfmoves_since_event().to_string();
+            operations.push(("hmvc".to_string(), vec![hmvc]));
+            operations.push(("fmvn".to_string(), vec![self.board.fullmoves().to_string()]));
+        }
+        operations.extend(self.other.iter().cloned());
+
+        for (opcode, operands) in operations {
+            output += &format!(" {opcode}");
+            for operand in operands {
+                output.push(' ');
+                output += &quote_if_needed(&operand);
+            }
+            output.push(';');
+        }
+
+        // Strings are always quoted
+        let strings = [("id".to_string(), &self.id)].into_iter().chain(
+            self.comments
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (format!("c{i}"), c)),
+        );
+        for (opcode, value) in strings {
+            if let Some(value) = value {
+                output += &format!(" {opcode} \"{}\";", escape_string(value));
+            }
+        }
+
+        output
+    }
+}
+
+impl Display for Epd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_epd())
+    }
+}
+
+/// Read one record per line, skipping blank lines and `#` comments. Each record is returned with
+/// its line number, so a bad record doesn't prevent reading the rest.
+pub fn read_epds(text: &str) -> Vec<(usize, Result<Epd, EpdError>)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| (i + 1, Epd::from_epd(line)))
+        .collect()
+}
+
+pub fn write_epds(epds: &[Epd]) -> String {
+    epds.iter().map(|epd| epd.to_epd() + "\n").collect()
+}
+
+fn parse_number<T: std::str::FromStr>(opcode: &str, operand: String) -> Result<T, EpdError> {
+    operand.parse().map_err(|_| EpdError::InvalidOperand {
+        opcode: opcode.to_string(),
+        operand,
+    })
+}
+
+/// Split off the next whitespace separated field.
+fn next_field(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    if s.is_empty() {
+        return None;
+    }
+
+    Some(s.split_once(char::is_whitespace).unwrap_or((s, "")))
+}
+
+/// Split the operations into opcodes and operands. Operands are separated by whitespace, except
+/// within double quoted strings.
+fn split_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut operations = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut token: Option<String> = None;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.extend(chars.next()),
+                        Some(c) => string.push(c),
+                        None => {
+                            return Err(EpdError::Syntax(format!(
+                                "unterminated string \"{string}"
+                            )));
+                        }
+                    }
+                }
+                tokens.extend(token.take());
+                tokens.push(string);
+            }
+            ';' => {
+                tokens.extend(token.take());
+                finish_operation(&mut tokens, &mut operations)?;
+            }
+            c if c.is_whitespace() => tokens.extend(token.take()),
+            c => token.get_or_insert_default().push(c),
+        }
+    }
+
+    // Tolerate a missing final ';'
+    tokens.extend(token.take());
+    finish_operation(&mut tokens, &mut operations)?;
+
+    Ok(operations)
+}
+
+fn finish_operation(
+    tokens: &mut Vec<String>,
+    operations: &mut Vec<(String, Vec<String>)>,
+) -> Result<(), EpdError> {
+    if tokens.is_empty() {
+        return Ok(());
+    }
+
+    let mut operands = std::mem::take(tokens);
+    let opcode = operands.remove(0);
+    if !opcode.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Err(EpdError::Syntax(format!("invalid opcode '{opcode}'")));
+    }
+
+    operations.push((opcode, operands));
+    Ok(())
+}
+
+fn escape_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn quote_if_needed(operand: &str) -> String {
+    if operand.is_empty() || operand.contains(|c: char| c.is_whitespace() || c == ';' || c == '"') {
+        format!("\"{}\"", escape_string(operand))
+    } else {
+        operand.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAC_001: &str =
+        r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";"#;
+
+    #[test]
+    fn test_read_epd() {
+        let epd = Epd::from_epd(WAC_001).unwrap();
+        assert_eq!(
+            epd.board.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(epd.best_moves, [Move::from_uci("g3g6").unwrap()]);
+        assert_eq!(epd.id.as_deref(), Some("WAC.001"));
+        assert!(epd.is_solution(Move::from_uci("g3g6").unwrap()));
+        assert!(!epd.is_solution(Move::from_uci("f6h5").unwrap()));
+
+        let epd = Epd::from_epd(
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - am O-O-O Kd1; pv O-O O-O-O; ce -15; acd 12; \
+             c0 \"semi;colon \\\"quoted\\\"\"; hmvc 3; fmvn 20; dm 4;",
+        )
+        .unwrap();
+        assert_eq!(
+            epd.avoid_moves,
+            [
+                Move::from_uci("e1c1").unwrap(),
+                Move::from_uci("e1d1").unwrap()
+            ]
+        );
+        assert_eq!(
+            epd.pv,
+            [
+                Move::from_uci("e1g1").unwrap(),
+                Move::from_uci("e8c8").unwrap()
+            ]
+        );
+        assert_eq!(epd.centipawn_eval, Some(-15));
+        assert_eq!(epd.analysis_depth, Some(12));
+        assert_eq!(epd.comments[0].as_deref(), Some("semi;colon \"quoted\""));
+        assert_eq!(epd.board.halfmoves_since_event(), 3);
+        assert_eq!(epd.board.fullmoves(), 20);
+        assert_eq!(epd.other, [("dm".to_string(), vec!["4".to_string()])]);
+        assert!(epd.is_solution(Move::from_uci("e1g1").unwrap()));
+        assert!(!epd.is_solution(Move::from_uci("e1c1").unwrap()));
+
+        // FEN clocks are accepted in place of hmvc and fmvn
+        let epd = Epd::from_epd("4k3/8/8/8/8/8/8/4K2R w K - 5 40 bm O-O+;").unwrap();
+        assert_eq!(epd.board.fullmoves(), 40);
+        assert_eq!(epd.best_moves, [Move::from_uci("e1g1").unwrap()]);
+    }
+
+    #[test]
+    fn test_write_read_round_trip() {
+        assert_eq!(Epd::from_epd(WAC_001).unwrap().to_epd(), WAC_001);
+
+        const EPD: &str = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - bm O-O; am Kd1 Kf1; pv O-O O-O-O; \
+                           ce 20; acd 8; hmvc 3; fmvn 20; dm 4; id \"test \\\"1\\\"\"; c2 \"a;b\";";
+        let epd = Epd::from_epd(EPD).unwrap();
+        assert_eq!(epd.to_epd(), EPD);
+        assert_eq!(Epd::from_epd(&epd.to_epd()).unwrap(), epd);
+    }
+
+    #[test]
+    fn test_epd_errors() {
+        assert_eq!(
+            Epd::from_epd("8/8/8/8 w -"),
+            Err(EpdError::InvalidFen(FenError::FieldCount(3)))
+        );
+        assert!(matches!(
+            Epd::from_epd("4k3/8/8/8/8/8/8/4K3 w - - bm Qg6;"),
+            Err(EpdError::IllegalMove { .. })
+        ));
+        assert_eq!(
+            Epd::from_epd("4k3/8/8/8/8/8/8/4K3 w - - acd deep;"),
+            Err(EpdError::InvalidOperand {
+                opcode: "acd".to_string(),
+                operand: "deep".to_string()
+            })
+        );
+        assert!(matches!(
+            Epd::from_epd("4k3/8/8/8/8/8/8/4K3 w - - id \"WAC.001;"),
+            Err(EpdError::Syntax(_))
+        ));
+
+        let records = read_epds(&format!(
+            "# WAC\n\n{WAC_001}\n4k3/8/8/8/8/8/8/4K3 w - - bm Qg6;\n"
+        ));
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].0, 3);
+        assert!(records[0].1.is_ok());
+        assert_eq!(records[1].0, 4);
+        assert!(records[1].1.is_err());
+    }
+}
@@ -2,7 +2,10 @@
 #![allow(unused_variables)]
 #![allow(unused_assignments)]
 pub mod board;
+pub mod epd;
 pub mod movegen;
+pub mod pgn;
+pub mod san;
 pub mod util;
 
 #[cfg(test)]
@@ -263,47 +263,74 @@ pub fn compute_legal_moves(moves: &mut MoveList, board: &Board) -> bool {
     }
 
     // Consider castling.
-    let castling_rights = board.castling_rights();
-    let back_rank = board.color_to_move().back_rank();
-
-    // Squares that must be empty and safe in order to kingside castle.
-    let kingside_castle_clear_mask = Bitboard::empty()
-        .with_inserted(Square::at(BoardFile::F, back_rank))
-        .with_inserted(Square::at(BoardFile::G, back_rank));
-    // Squares that must be empty in order to queenside castle.
-    let queenside_castle_clear_mask = Bitboard::empty()
-        .with_inserted(Square::at(BoardFile::B, back_rank))
-        .with_inserted(Square::at(BoardFile::C, back_rank))
-        .with_inserted(Square::at(BoardFile::D, back_rank));
-    // Squares that must be safe in order to queenside castle.
-    let queenside_castle_danger_mask = Bitboard::empty()
-        .with_inserted(Square::at(BoardFile::C, back_rank))
-        .with_inserted(Square::at(BoardFile::D, back_rank));
-
-    if castling_rights.kingside(board.color_to_move())
-        && !kingside_castle_clear_mask
-            .intersects(all_pieces_bitboard | checks_analysis.king_danger_mask)
-        && !is_check
-        && board.pieces().get(Square::at(BoardFile::H, back_rank))
-            == Some(Piece::new(PieceKind::Rook, board.color_to_move()))
-    {
-        moves.push(Move {
-            source: Square::at(BoardFile::E, back_rank),
-            destination: Square::at(BoardFile::G, back_rank),
-            promotion: None,
-        })
-    }
+    // Generalised for Chess960: every square the king or rook passes over (or lands on) must be
+    // empty apart from the two castling pieces, and every square the king passes over must be
+    // safe.
+    let color = board.color_to_move();
+    let back_rank = color.back_rank();
+    let friendly_rook = Piece::new(PieceKind::Rook, color);
+
+    for kingside in [true, false] {
+        let Some(rook_square) = board.castling_rights().rook_square(color, kingside) else {
+            continue;
+        };
+        if is_check
+            || king_square.rank() != back_rank
+            || board.pieces().get(rook_square) != Some(friendly_rook)
+        {
+            continue;
+        }
+
+        let (king_destination, rook_destination) = if kingside {
+            (
+                Square::at(BoardFile::G, back_rank),
+                Square::at(BoardFile::F, back_rank),
+            )
+        } else {
+            (
+                Square::at(BoardFile::C, back_rank),
+                Square::at(BoardFile::D, back_rank),
+            )
+        };
+
+        let king_path = rank_span_bitboard(king_square, king_destination);
+        let must_be_empty = (king_path | rank_span_bitboard(rook_square, rook_destination))
+            .with_removed(king_square)
+            .with_removed(rook_square);
+
+        if must_be_empty.intersects(all_pieces_bitboard)
+            || king_path.intersects(checks_analysis.king_danger_mask)
+        {
+            continue;
+        }
+
+        // The castling rook itself may have been blocking an attack along the back rank on the
+        // king's destination.
+        let occupancy_after = all_pieces_bitboard
+            .with_removed(king_square)
+            .with_removed(rook_square)
+            .with_inserted(king_destination)
+            .with_inserted(rook_destination);
+        let enemy_rank_attackers = board
+            .pieces()
+            .piece_bitboard(Piece::new(PieceKind::Rook, !color))
+            | board
+                .pieces()
+                .piece_bitboard(Piece::new(PieceKind::Queen, !color));
+        if ROOK_ATTACK_TABLE
+            .get_attack_set(king_destination, occupancy_after)
+            .intersects(enemy_rank_attackers)
+        {
+            continue;
+        }
 
-    if castling_rights.queenside(board.color_to_move())
-        && !queenside_castle_clear_mask.intersects(all_pieces_bitboard)
-        && !queenside_castle_danger_mask.intersects(checks_analysis.king_danger_mask)
-        && !is_check
-        && board.pieces().get(Square::at(BoardFile::A, back_rank))
-            == Some(Piece::new(PieceKind::Rook, board.color_to_move()))
-    {
         moves.push(Move {
-            source: Square::at(BoardFile::E, back_rank),
-            destination: Square::at(BoardFile::C, back_rank),
+            source: king_square,
+            destination: if board.is_chess960() {
+                rook_square
+            } else {
+                king_destination
+            },
             promotion: None,
         })
     }
@@ -511,6 +538,20 @@ fn ray_bitboard(origin: Square, occupancy_bitboard: Bitboard, offset: (i32, i32)
     }
     result
 }
+/// Returns the bitboard of squares between two squares on the same rank, inclusive.
+fn rank_span_bitboard(a: Square, b: Square) -> Bitboard {
+    let (low, high) = if a.file() <= b.file() { (a, b) } else { (b, a) };
+    let mut result = Bitboard::single(low);
+    let mut sq = low;
+    while sq != high {
+        sq = sq
+            .translated_by((1, 0))
+            .expect("Squares should be on the same rank");
+        result.insert(sq);
+    }
+    result
+}
+
 fn ray_bitboard_empty(origin: Square, offset: (i32, i32)) -> Bitboard {
     // TODO: Same optimization as ray_bitboard
     let mut current_sq = origin;
@@ -532,7 +573,7 @@ mod tests {
     use itertools::Itertools;
 
     use super::*;
-    use crate::board::Board;
+    use crate::board::{Board, CastlingRights};
 
     fn check_includes_moves(board_fen: &str, moves_uci: &[&str]) {
         let board = Board::from_fen(board_fen).unwrap();
@@ -624,6 +665,23 @@ mod tests {
         check_includes_moves("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", &["e8g8", "e8c8"]);
     }
 
+    #[test]
+    fn test_chess960_castling() {
+        // Castling is encoded as the king capturing its own rook
+        check_includes_moves("1r2k1r1/8/8/8/8/8/8/R1K3R1 w AGbg - 0 1", &["c1a1"]);
+        check_includes_moves("1r2k1r1/8/8/8/8/8/8/R1K3R1 b AGbg - 0 1", &["e8b8"]);
+
+        // The king's destination is only attacked once the castling rook has moved off it
+        check_excludes_moves("1r2k1r1/8/8/8/8/8/8/R1K3R1 w AGbg - 0 1", &["c1g1"]);
+
+        // Castling rook shields the king's destination
+        check_excludes_moves("4k3/8/8/8/8/8/8/rRK4R w BH - 0 1", &["c1b1"]);
+        check_includes_moves("4k3/8/8/8/8/8/8/rRK4R w BH - 0 1", &["c1h1"]);
+
+        // Rook must pass over empty squares, even if the king doesn't
+        check_excludes_moves("4k3/8/8/8/8/8/8/RNK5 w A - 0 1", &["c1a1"]);
+    }
+
     #[test]
     fn test_no_castling_through_check() {
         check_excludes_moves("3rkr2/8/8/8/8/8/8/R3K2R w KQ - 0 1", &["e1g1", "e1c1"]);
@@ -631,8 +689,27 @@ mod tests {
 
     #[test]
     fn test_no_castling_without_rook() {
-        // Can't castle if rooks are missing.
-        check_excludes_moves("4k3/8/8/8/8/8/8/4K3 w KQ - 0 1", &["e1g1", "e1c1"]);
+        // Can't castle if rooks are missing. FEN parsing rejects such rights, so build the board
+        // directly.
+        let mut pieces = [None; 64];
+        pieces[Square::E1.as_u8() as usize] = Some(Piece::WhiteKing);
+        pieces[Square::E8.as_u8() as usize] = Some(Piece::BlackKing);
+        let board = Board::new(
+            &pieces,
+            Color::White,
+            None,
+            CastlingRights::new(true, true, false, false),
+            0,
+            1,
+        );
+
+        let mut moves = MoveList::new();
+        compute_legal_moves(&mut moves, &board);
+        assert!(
+            !moves
+                .iter()
+                .any(|m| m.as_uci() == "e1g1" || m.as_uci() == "e1c1")
+        );
     }
 
     #[test]
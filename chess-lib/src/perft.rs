@@ -95,3 +95,69 @@ fn test_perft_position_6() {
         &[46, 2_079, 89_890, 3_894_594, 164_075_551],
     );
 }
+
+// Chess960 positions and results are from https://www.chessprogramming.org/Chess960_Perft_Results.
+
+#[test]
+fn test_perft_chess960() {
+    test_perft(
+        "chess960 position 1",
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        &[21, 528, 12_189, 326_672],
+    );
+    test_perft(
+        "chess960 position 2",
+        "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        &[21, 807, 18_002, 667_366],
+    );
+    test_perft(
+        "chess960 position 3",
+        "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+        &[20, 479, 10_471, 273_318],
+    );
+    test_perft(
+        "chess960 position 4",
+        "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+        &[22, 593, 13_440, 382_958],
+    );
+    test_perft(
+        "chess960 position 5",
+        "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+        &[28, 1_120, 31_058, 1_171_749],
+    );
+    test_perft(
+        "chess960 position 6",
+        "bnqbnr1r/p1p1ppkp/3p4/1p4p1/P7/3NP2P/1PPP1PP1/BNQB1RKR w HF - 0 9",
+        &[26, 889, 24_353, 832_956],
+    );
+}
+
+#[test]
+fn test_perft_chess960_castling() {
+    // Castling rook shielding the king's destination from an enemy rook
+    test_perft(
+        "chess960 shielding rook",
+        "4k3/8/8/8/8/8/8/rRK4R w BH - 0 1",
+        &[17, 185, 4_077, 57_884],
+    );
+    // King already on its destination square when castling queenside
+    test_perft(
+        "chess960 stationary king",
+        "1r2k1r1/8/8/8/8/8/8/R1K3R1 w AGbg - 0 1",
+        &[23, 491, 10_980, 247_955],
+    );
+}
+
+#[test]
+fn test_perft_chess960_starting_positions() {
+    for index in [0, 959] {
+        let mut board = Board::starting_chess960(index).unwrap();
+        for (depth, expected_result) in (1..).zip([20, 400, 9_006, 201_143]) {
+            let result = perft(&mut board, depth);
+            assert_eq!(
+                result, expected_result,
+                "perft chess960 starting position {index} depth {depth}"
+            );
+        }
+    }
+}
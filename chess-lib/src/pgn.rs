@@ -0,0 +1,930 @@
+//! https://www.chessprogramming.org/Portable_Game_Notation
+//! Reading and writing of PGN files.
+//! Games are replayed while they are read, so every move is checked for legality and stored as
+//! both a `Move` and its canonical SAN. Variations can either be skipped or retained.
+
+use crate::board::{Board, FenError, Move, UnmakeInfo};
+use crate::movegen::{MoveList, compute_legal_moves};
+use crate::san::{SanError, move_to_san, parse_san};
+use std::fmt::{Display, Formatter};
+use std::mem;
+
+/// Tags that must appear first, in this order, in exported PGN - with their default values.
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+/// Values of the Variant tag for which castling is read as Chess960.
+const CHESS960_VARIANTS: [&str; 3] = ["Chess960", "Fischerandom", "Chess 960"];
+
+/// Maximum length of a movetext line when writing.
+const MAX_LINE_LENGTH: usize = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameResult {
+    WhiteWin,
+    BlackWin,
+    Draw,
+    /// Game still in progress, abandoned or result unknown ("*").
+    Unknown,
+}
+
+impl GameResult {
+    pub fn from_pgn(s: &str) -> Option<GameResult> {
+        Some(match s {
+            "1-0" => GameResult::WhiteWin,
+            "0-1" => GameResult::BlackWin,
+            "1/2-1/2" => GameResult::Draw,
+            "*" => GameResult::Unknown,
+            _ => return None,
+        })
+    }
+
+    pub const fn as_pgn(self) -> &'static str {
+        match self {
+            GameResult::WhiteWin => "1-0",
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        }
+    }
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_pgn())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PgnError {
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    InvalidFen {
+        line: usize,
+        fen: String,
+        error: FenError,
+    },
+    IllegalMove {
+        line: usize,
+        move_number: u32,
+        error: SanError,
+    },
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            PgnError::InvalidFen { line, fen, error } => {
+                write!(f, "line {line}: invalid FEN tag '{fen}' - {error}")
+            }
+            PgnError::IllegalMove {
+                line,
+                move_number,
+                error,
+            } => write!(f, "line {line}: move {move_number}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnMove {
+    pub mv: Move,
+    pub san: String,
+    /// Numeric annotation glyphs, including those converted from suffixes such as "!?".
+    pub nags: Vec<u8>,
+    /// Comments appearing before this move, only possible at the start of a game or variation.
+    pub comments_before: Vec<String>,
+    pub comments: Vec<String>,
+    /// Alternatives to this move, each starting from the position before it was played.
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(mv: Move, san: String) -> PgnMove {
+        PgnMove {
+            mv,
+            san,
+            nags: Vec::new(),
+            comments_before: Vec::new(),
+            comments: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnGame {
+    /// Tag pairs in the order they were read or added.
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<PgnMove>,
+    pub result: GameResult,
+}
+
+impl PgnGame {
+    /// Build a game from a list of moves played from `initial`. FEN and SetUp tags are added
+    /// if `initial` is not the standard starting position.
+    pub fn from_moves(
+        tags: Vec<(String, String)>,
+        initial: &Board,
+        moves: &[Move],
+        result: GameResult,
+    ) -> Result<PgnGame, SanError> {
+        let mut game = PgnGame {
+            tags,
+            moves: Vec::with_capacity(moves.len()),
+            result,
+        };
+
+        let fen = initial.to_fen();
+        if fen != Board::starting().to_fen() {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+        if initial.is_chess960() {
+            game.set_tag("Variant", "Chess960");
+        }
+        game.set_tag("Result", result.as_pgn());
+
+        let mut board = initial.clone();
+        let mut legal_moves = MoveList::new();
+        for &mv in moves {
+            legal_moves.clear();
+            compute_legal_moves(&mut legal_moves, &board);
+            if !legal_moves.contains(&mv) {
+                return Err(SanError::Illegal(mv.as_uci()));
+            }
+
+            game.moves
+                .push(PgnMove::new(mv, move_to_san(&mut board, mv)));
+            board.make_move(mv);
+        }
+
+        Ok(game)
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Set a tag, replacing its value if already present.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        if let Some((_, v)) = self.tags.iter_mut().find(|(n, _)| n == name) {
+            *v = value.to_string();
+        } else {
+            self.tags.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    /// The position the game starts from, taken from the FEN tag if present.
+    pub fn initial_board(&self) -> Result<Board, FenError> {
+        let mut board = match self.tag("FEN") {
+            Some(fen) => Board::from_fen(fen)?,
+            None => Board::starting(),
+        };
+
+        if self
+            .tag("Variant")
+            .is_some_and(|v| CHESS960_VARIANTS.iter().any(|c| c.eq_ignore_ascii_case(v)))
+        {
+            board.set_chess960(true);
+        }
+
+        Ok(board)
+    }
+
+    pub fn mainline(&self) -> impl Iterator<Item = Move> + '_ {
+        self.moves.iter().map(|m| m.mv)
+    }
+
+    /// The position at the end of the main line.
+    pub fn replay(&self) -> Result<Board, FenError> {
+        let mut board = self.initial_board()?;
+        for mv in self.mainline() {
+            board.make_move(mv);
+        }
+        Ok(board)
+    }
+
+    /// Every position of the main line, from the initial position to the final position.
+    pub fn positions(&self) -> Result<Vec<Board>, FenError> {
+        let mut board = self.initial_board()?;
+        let mut positions = Vec::with_capacity(self.moves.len() + 1);
+        positions.push(board.clone());
+        for mv in self.mainline() {
+            board.make_move(mv);
+            positions.push(board.clone());
+        }
+        Ok(positions)
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut output = String::new();
+
+        for (name, default) in SEVEN_TAG_ROSTER {
+            let value = if name == "Result" {
+                self.result.as_pgn()
+            } else {
+                self.tag(name).unwrap_or(default)
+            };
+            output += &format!("[{name} \"{}\"]\n", escape_tag_value(value));
+        }
+        for (name, value) in &self.tags {
+            if SEVEN_TAG_ROSTER.iter().all(|(n, _)| n != name) {
+                output += &format!("[{name} \"{}\"]\n", escape_tag_value(value));
+            }
+        }
+        output.push('\n');
+
+        let initial = self.initial_board().unwrap_or_else(|_| Board::starting());
+        let mut tokens = Vec::new();
+        write_line(
+            &self.moves,
+            initial.color_to_move().is_white(),
+            initial.fullmoves(),
+            &mut tokens,
+        );
+        tokens.push(self.result.as_pgn().to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            // No spaces inside variation parentheses
+            let attach = line.ends_with('(') || token == ")";
+            if !line.is_empty() && !attach && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                output += &line;
+                output.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() && !attach {
+                line.push(' ');
+            }
+            line += &token;
+        }
+        output += &line;
+        output.push('\n');
+
+        output
+    }
+}
+
+impl Display for PgnGame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_pgn())
+    }
+}
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn write_line(moves: &[PgnMove], white_to_move: bool, fullmove: u32, tokens: &mut Vec<String>) {
+    let mut white_to_move = white_to_move;
+    let mut fullmove = fullmove;
+    // Black moves need a "N..." number after anything that interrupts the movetext
+    let mut needs_number = true;
+
+    for m in moves {
+        for comment in &m.comments_before {
+            tokens.push(format!("{{{comment}}}"));
+            needs_number = true;
+        }
+
+        if white_to_move {
+            tokens.push(format!("{fullmove}."));
+        } else if needs_number {
+            tokens.push(format!("{fullmove}..."));
+        }
+        tokens.push(m.san.clone());
+        needs_number = false;
+
+        for nag in &m.nags {
+            tokens.push(format!("${nag}"));
+        }
+        for comment in &m.comments {
+            tokens.push(format!("{{{comment}}}"));
+            needs_number = true;
+        }
+        for variation in &m.variations {
+            tokens.push("(".to_string());
+            write_line(variation, white_to_move, fullmove, tokens);
+            tokens.push(")".to_string());
+            needs_number = true;
+        }
+
+        if !white_to_move {
+            fullmove += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+}
+
+/// Write several games, separated by blank lines.
+pub fn write_games(games: &[PgnGame]) -> String {
+    games
+        .iter()
+        .map(PgnGame::to_pgn)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Read every game in `text`, failing on the first invalid game. Variations are skipped.
+pub fn read_games(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    PgnReader::new(text).collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+    San(String),
+}
+
+type LexResult = Result<(Token, usize), PgnError>;
+
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+    /// Suffix annotation split off the previous SAN token.
+    queued: Option<Token>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str) -> Lexer<'a> {
+        Lexer {
+            text,
+            pos: 0,
+            line: 1,
+            queued: None,
+        }
+    }
+
+    fn syntax_error<T>(&self, message: impl Into<String>) -> Result<T, PgnError> {
+        Err(PgnError::Syntax {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    /// Advance until `byte` (exclusive), returning the skipped text, or None if not found.
+    fn take_until(&mut self, byte: u8) -> Option<&'a str> {
+        let start = self.pos;
+        let end = start
+            + self.text.as_bytes()[start..]
+                .iter()
+                .position(|&b| b == byte)?;
+        self.line += self.text[start..end].matches('\n').count();
+        self.pos = end;
+        Some(&self.text[start..end])
+    }
+
+    fn next_token(&mut self) -> Option<LexResult> {
+        if let Some(token) = self.queued.take() {
+            return Some(Ok((token, self.line)));
+        }
+
+        loop {
+            let bytes = self.text.as_bytes();
+
+            // Whitespace and escaped lines
+            let &b = bytes.get(self.pos)?;
+            match b {
+                b'\n' => {
+                    self.line += 1;
+                    self.pos += 1;
+                    continue;
+                }
+                b if b.is_ascii_whitespace() => {
+                    self.pos += 1;
+                    continue;
+                }
+                b'%' if self.pos == 0 || bytes[self.pos - 1] == b'\n' => {
+                    if self.take_until(b'\n').is_none() {
+                        self.pos = bytes.len();
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            let line = self.line;
+            self.pos += 1;
+            let token = match b {
+                b'{' => {
+                    let Some(comment) = self.take_until(b'}') else {
+                        self.pos = bytes.len();
+                        return Some(self.syntax_error("unterminated comment"));
+                    };
+                    self.pos += 1;
+                    Token::Comment(comment.trim().to_string())
+                }
+                b';' => {
+                    let comment = self.take_until(b'\n').unwrap_or_else(|| {
+                        let rest = &self.text[self.pos..];
+                        self.pos = bytes.len();
+                        rest
+                    });
+                    Token::Comment(comment.trim().to_string())
+                }
+                b'[' => match self.lex_tag() {
+                    Ok(token) => token,
+                    Err(e) => return Some(Err(e)),
+                },
+                b'(' => Token::VariationStart,
+                b')' => Token::VariationEnd,
+                b'$' => {
+                    let start = self.pos;
+                    while bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                        self.pos += 1;
+                    }
+                    match self.text[start..self.pos].parse() {
+                        Ok(nag) => Token::Nag(nag),
+                        Err(_) => return Some(self.syntax_error("invalid NAG")),
+                    }
+                }
+                _ => {
+                    let start = self.pos - 1;
+                    while bytes
+                        .get(self.pos)
+                        .is_some_and(|b| !b.is_ascii_whitespace() && !b"{}()[];$".contains(b))
+                    {
+                        self.pos += 1;
+                    }
+                    match self.lex_symbol(&self.text[start..self.pos]) {
+                        Ok(Some(token)) => token,
+                        // Move number
+                        Ok(None) => continue,
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+            };
+
+            return Some(Ok((token, line)));
+        }
+    }
+
+    fn lex_tag(&mut self) -> Result<Token, PgnError> {
+        let bytes = self.text.as_bytes();
+        let skip_spaces = |lexer: &mut Self| {
+            while bytes
+                .get(lexer.pos)
+                .is_some_and(|&b| b == b' ' || b == b'\t')
+            {
+                lexer.pos += 1;
+            }
+        };
+
+        skip_spaces(self);
+        let start = self.pos;
+        while bytes
+            .get(self.pos)
+            .is_some_and(|&b| b.is_ascii_alphanumeric() || b == b'_')
+        {
+            self.pos += 1;
+        }
+        let name = self.text[start..self.pos].to_string();
+        if name.is_empty() {
+            return self.syntax_error("missing tag name");
+        }
+
+        skip_spaces(self);
+        if bytes.get(self.pos) != Some(&b'"') {
+            return self.syntax_error(format!("missing value for tag '{name}'"));
+        }
+        self.pos += 1;
+
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.text[self.pos..].chars().next() else {
+                return self.syntax_error(format!("unterminated value for tag '{name}'"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => break,
+                '\\' => {
+                    if let Some(escaped) = self.text[self.pos..].chars().next() {
+                        self.pos += escaped.len_utf8();
+                        value.push(escaped);
+                    }
+                }
+                '\n' => return self.syntax_error(format!("unterminated value for tag '{name}'")),
+                c => value.push(c),
+            }
+        }
+
+        skip_spaces(self);
+        if bytes.get(self.pos) != Some(&b']') {
+            return self.syntax_error(format!("missing ']' after tag '{name}'"));
+        }
+        self.pos += 1;
+
+        Ok(Token::Tag(name, value))
+    }
+
+    /// Returns None for bare move numbers, which carry no information.
+    fn lex_symbol(&mut self, symbol: &str) -> Result<Option<Token>, PgnError> {
+        if let Some(result) = GameResult::from_pgn(symbol) {
+            return Ok(Some(Token::Result(result)));
+        }
+
+        let (san, suffix) = symbol.split_at(symbol.trim_end_matches(['!', '?']).len());
+
+        // Move numbers, possibly attached to the move ("12." / "12..." / "12.e4")
+        let san = if san.starts_with(|c: char| c.is_ascii_digit()) && !san.starts_with("0-0") {
+            let rest = san.trim_start_matches(|c: char| c.is_ascii_digit());
+            if !rest.starts_with('.') {
+                return self.syntax_error(format!("unexpected symbol '{symbol}'"));
+            }
+            rest.trim_start_matches('.')
+        } else {
+            san
+        };
+
+        let nag = match suffix {
+            "" => None,
+            "!" => Some(1),
+            "?" => Some(2),
+            "!!" => Some(3),
+            "??" => Some(4),
+            "!?" => Some(5),
+            "?!" => Some(6),
+            _ => return self.syntax_error(format!("unknown annotation '{suffix}'")),
+        };
+
+        if san.is_empty() {
+            return Ok(nag.map(Token::Nag));
+        }
+        self.queued = nag.map(Token::Nag);
+
+        Ok(Some(Token::San(san.to_string())))
+    }
+}
+
+/// Iterator over the games in a PGN file. After an invalid game, reading resumes at the next
+/// game.
+pub struct PgnReader<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<LexResult>,
+    keep_variations: bool,
+}
+
+impl<'a> PgnReader<'a> {
+    pub fn new(text: &'a str) -> PgnReader<'a> {
+        PgnReader {
+            lexer: Lexer::new(text),
+            peeked: None,
+            keep_variations: false,
+        }
+    }
+
+    /// Whether to store variations in `PgnMove::variations`, or skip over them (default).
+    pub fn keep_variations(mut self, keep_variations: bool) -> PgnReader<'a> {
+        self.keep_variations = keep_variations;
+        self
+    }
+
+    fn next_token(&mut self) -> Option<LexResult> {
+        self.peeked.take().or_else(|| self.lexer.next_token())
+    }
+
+    fn peek_token(&mut self) -> Option<&LexResult> {
+        if self.peeked.is_none() {
+            self.peeked = self.lexer.next_token();
+        }
+        self.peeked.as_ref()
+    }
+
+    fn peek_is_tag(&mut self) -> bool {
+        matches!(self.peek_token(), Some(Ok((Token::Tag(..), _))))
+    }
+
+    fn read_game(&mut self) -> Result<PgnGame, PgnError> {
+        let mut tags = Vec::new();
+        let mut line = self.lexer.line;
+        while self.peek_is_tag() {
+            if let Some(Ok((Token::Tag(name, value), tag_line))) = self.next_token() {
+                tags.push((name, value));
+                line = tag_line;
+            }
+        }
+
+        let mut game = PgnGame {
+            tags,
+            moves: Vec::new(),
+            result: GameResult::Unknown,
+        };
+
+        let mut board = game.initial_board().map_err(|error| PgnError::InvalidFen {
+            line,
+            fen: game.tag("FEN").unwrap_or_default().to_string(),
+            error,
+        })?;
+
+        (game.moves, game.result) = self.read_line(&mut board, 0)?;
+
+        Ok(game)
+    }
+
+    /// Read a sequence of moves - the main line at depth 0, otherwise a variation. Moves are
+    /// made on `board`, and unmade again at the end of a variation.
+    fn read_line(
+        &mut self,
+        board: &mut Board,
+        depth: usize,
+    ) -> Result<(Vec<PgnMove>, GameResult), PgnError> {
+        let mut moves: Vec<PgnMove> = Vec::new();
+        let mut unmakes: Vec<UnmakeInfo> = Vec::new();
+        let mut pending_comments = Vec::new();
+
+        let result = loop {
+            // The next game's tags end a game with no termination marker
+            if depth == 0 && self.peek_is_tag() {
+                break GameResult::Unknown;
+            }
+
+            let Some(token) = self.next_token() else {
+                if depth > 0 {
+                    return self.lexer.syntax_error("unterminated variation");
+                }
+                break GameResult::Unknown;
+            };
+            let (token, line) = token?;
+            let syntax_error = |message: &str| {
+                Err(PgnError::Syntax {
+                    line,
+                    message: message.to_string(),
+                })
+            };
+
+            match token {
+                Token::San(san) => {
+                    let mv = parse_san(board, &san).map_err(|error| PgnError::IllegalMove {
+                        line,
+                        move_number: board.fullmoves(),
+                        error,
+                    })?;
+                    let mut pgn_move = PgnMove::new(mv, move_to_san(board, mv));
+                    pgn_move.comments_before = mem::take(&mut pending_comments);
+                    unmakes.push(board.make_move(mv));
+                    moves.push(pgn_move);
+                }
+                Token::Nag(nag) => {
+                    if let Some(last) = moves.last_mut() {
+                        last.nags.push(nag);
+                    }
+                }
+                Token::Comment(comment) => match moves.last_mut() {
+                    Some(last) => last.comments.push(comment),
+                    None => pending_comments.push(comment),
+                },
+                Token::VariationStart => {
+                    if !self.keep_variations {
+                        self.skip_variation()?;
+                        continue;
+                    }
+
+                    let (Some(last), Some(um)) = (moves.last_mut(), unmakes.pop()) else {
+                        return syntax_error("variation before any move");
+                    };
+
+                    // The variation replaces the last move
+                    board.unmake_last_move(um);
+                    let (variation, _) = self.read_line(board, depth + 1)?;
+                    unmakes.push(board.make_move(last.mv));
+                    last.variations.push(variation);
+                }
+                Token::VariationEnd => {
+                    if depth == 0 {
+                        return syntax_error("unexpected ')'");
+                    }
+                    break GameResult::Unknown;
+                }
+                Token::Result(result) => {
+                    if depth > 0 {
+                        return syntax_error("game result inside variation");
+                    }
+                    break result;
+                }
+                Token::Tag(..) => return syntax_error("unexpected tag inside variation"),
+            }
+        };
+
+        if depth > 0 {
+            for um in unmakes.into_iter().rev() {
+                board.unmake_last_move(um);
+            }
+        }
+
+        Ok((moves, result))
+    }
+
+    /// Skip to the end of a variation whose opening '(' has been consumed.
+    fn skip_variation(&mut self) -> Result<(), PgnError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next_token() {
+                Some(Ok((Token::VariationStart, _))) => depth += 1,
+                Some(Ok((Token::VariationEnd, _))) => depth -= 1,
+                Some(Ok((Token::Result(_) | Token::Tag(..), line))) => {
+                    return Err(PgnError::Syntax {
+                        line,
+                        message: "unterminated variation".to_string(),
+                    });
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e),
+                None => return self.lexer.syntax_error("unterminated variation"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Skip the rest of an invalid game.
+    fn skip_game(&mut self) {
+        while !self.peek_is_tag() {
+            match self.next_token() {
+                Some(Ok((Token::Result(_), _))) | None => return,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Iterator for PgnReader<'_> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.peek_token()?;
+
+        let game = self.read_game();
+        if game.is_err() {
+            self.skip_game();
+        }
+        Some(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMES: &str = r#"[Event "Test \"quoted\""]
+[Site "?"]
+[White "A"]
+[Black "B"]
+[Result "0-1"]
+
+1. f3 {Weak} e5 2. g4?? Qh4# 0-1
+
+[Event "Second"]
+[Result "1/2-1/2"]
+
+1. e4 e5 (1... c5 2. Nf3 (2. Nc3) d6) 2. Nf3 $1 Nc6 ; line comment
+3.Bb5 a6 4. Ba4 Nf6 5. O-O Be7 1/2-1/2
+"#;
+
+    #[test]
+    fn test_read_games() {
+        let games = read_games(GAMES).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let game = &games[0];
+        assert_eq!(game.tag("Event"), Some("Test \"quoted\""));
+        assert_eq!(game.tag("White"), Some("A"));
+        assert_eq!(game.result, GameResult::BlackWin);
+        assert_eq!(
+            game.moves
+                .iter()
+                .map(|m| m.san.as_str())
+                .collect::<Vec<_>>(),
+            ["f3", "e5", "g4", "Qh4#"]
+        );
+        assert_eq!(game.moves[0].comments, ["Weak"]);
+        assert_eq!(game.moves[2].nags, [4]);
+        assert_eq!(
+            game.replay().unwrap().to_fen(),
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+        );
+
+        let game = &games[1];
+        assert_eq!(game.result, GameResult::Draw);
+        assert_eq!(game.moves.len(), 10);
+        assert!(game.moves[1].variations.is_empty());
+        assert_eq!(game.moves[2].nags, [1]);
+        assert_eq!(game.moves[3].comments, ["line comment"]);
+        assert_eq!(game.moves[8].san, "O-O");
+        assert_eq!(game.positions().unwrap().len(), 11);
+    }
+
+    #[test]
+    fn test_read_variations() {
+        let games = PgnReader::new(GAMES)
+            .keep_variations(true)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let game = &games[1];
+
+        assert_eq!(game.moves.len(), 10);
+        let variation = &game.moves[1].variations[0];
+        assert_eq!(
+            variation.iter().map(|m| m.san.as_str()).collect::<Vec<_>>(),
+            ["c5", "Nf3", "d6"]
+        );
+        assert_eq!(variation[1].variations[0][0].san, "Nc3");
+    }
+
+    #[test]
+    fn test_write_read_round_trip() {
+        let game = PgnReader::new(GAMES)
+            .keep_variations(true)
+            .nth(1)
+            .unwrap()
+            .unwrap();
+
+        let written = game.to_pgn();
+        assert!(written.contains("1. e4 e5 (1... c5 2. Nf3 (2. Nc3) 2... d6) 2. Nf3 $1 Nc6"));
+
+        let reread = PgnReader::new(&written)
+            .keep_variations(true)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(reread.moves, game.moves);
+        assert_eq!(reread.result, game.result);
+        assert_eq!(reread.tag("Site"), Some("?"));
+        assert_eq!(reread.to_pgn(), written);
+    }
+
+    #[test]
+    fn test_from_moves() {
+        let initial =
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        let moves = [
+            Move::from_uci("e7e5").unwrap(),
+            Move::from_uci("g1f3").unwrap(),
+        ];
+        let game = PgnGame::from_moves(
+            vec![("White".to_string(), "Beans".to_string())],
+            &initial,
+            &moves,
+            GameResult::Unknown,
+        )
+        .unwrap();
+
+        let written = game.to_pgn();
+        assert!(written.contains("[White \"Beans\"]"));
+        assert!(written.contains("[SetUp \"1\"]"));
+        assert!(written.ends_with("\n1... e5 2. Nf3 *\n"));
+
+        let reread = read_games(&written).unwrap();
+        assert_eq!(reread[0].mainline().collect::<Vec<_>>(), moves);
+
+        assert_eq!(
+            PgnGame::from_moves(vec![], &initial, &moves[1..], GameResult::Unknown),
+            Err(SanError::Illegal("g1f3".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_game_recovery() {
+        let text = "1. e4 e5 2. Ke3 1-0\n\n[Event \"Next\"]\n1. d4 {unfinished} *";
+        let games = PgnReader::new(text).collect::<Vec<_>>();
+
+        assert_eq!(games.len(), 2);
+        assert_eq!(
+            games[0],
+            Err(PgnError::IllegalMove {
+                line: 1,
+                move_number: 2,
+                error: SanError::Illegal("Ke3".to_string()),
+            })
+        );
+        let game = games[1].as_ref().unwrap();
+        assert_eq!(game.tag("Event"), Some("Next"));
+        assert_eq!(game.moves.len(), 1);
+        assert_eq!(game.result, GameResult::Unknown);
+
+        assert!(matches!(
+            read_games("1. e4 {never closed"),
+            Err(PgnError::Syntax { .. })
+        ));
+    }
+}
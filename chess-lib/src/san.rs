@@ -0,0 +1,280 @@
+//! https://www.chessprogramming.org/Algebraic_Chess_Notation#Standard_Algebraic_Notation_.28SAN.29
+//! Standard Algebraic Notation, as used by PGN and EPD.
+//! Parsing is tolerant of check/mate markers, annotation suffixes and `0-0` style castling,
+//! writing always produces the canonical form.
+
+use crate::board::{Board, BoardFile, BoardRank, Move, PieceKind, Square};
+use crate::movegen::{MoveList, compute_legal_moves};
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    /// The string is not syntactically valid SAN.
+    Invalid(String),
+    /// No legal move in the position matches the SAN.
+    Illegal(String),
+    /// More than one legal move in the position matches the SAN.
+    Ambiguous(String),
+}
+
+impl Display for SanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanError::Invalid(san) => write!(f, "invalid SAN '{san}'"),
+            SanError::Illegal(san) => write!(f, "illegal move '{san}'"),
+            SanError::Ambiguous(san) => write!(f, "ambiguous move '{san}'"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+/// Returns true for kingside castling, false for queenside, or None if the move isn't castling.
+fn castling_side(board: &Board, mv: Move) -> Option<bool> {
+    board.castling_rook(mv).map(|(_, kingside)| kingside)
+}
+
+/// Parse a move in SAN, e.g. "Nbd7", "exd6", "e8=Q+" or "O-O", resolving it against the legal
+/// moves in the position.
+pub fn parse_san(board: &Board, san: &str) -> Result<Move, SanError> {
+    let invalid = || SanError::Invalid(san.to_string());
+    let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+
+    let mut legal_moves = MoveList::new();
+    compute_legal_moves(&mut legal_moves, board);
+
+    let castle = match text {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    };
+    if let Some(kingside) = castle {
+        return legal_moves
+            .iter()
+            .copied()
+            .find(|&mv| castling_side(board, mv) == Some(kingside))
+            .ok_or_else(|| SanError::Illegal(san.to_string()));
+    }
+
+    let mut chars = text.chars().collect::<Vec<char>>();
+
+    let kind = match chars.first() {
+        Some(&c) if c.is_ascii_uppercase() => {
+            chars.remove(0);
+            PieceKind::from_char(c).ok_or_else(invalid)?
+        }
+        Some(_) => PieceKind::Pawn,
+        None => return Err(invalid()),
+    };
+
+    // Promotion, with or without '='
+    let explicit_promotion = chars.len() >= 2 && chars[chars.len() - 2] == '=';
+    let promotion = match chars.last() {
+        Some(&c) if c.is_ascii_uppercase() || (explicit_promotion && c.is_ascii_alphabetic()) => {
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+            match PieceKind::from_char(c) {
+                Some(PieceKind::Pawn | PieceKind::King) | None => return Err(invalid()),
+                promotion => promotion,
+            }
+        }
+        _ => None,
+    };
+
+    if chars.len() < 2 {
+        return Err(invalid());
+    }
+    let destination_chars = chars.split_off(chars.len() - 2);
+    let destination =
+        Square::from_name(&destination_chars.iter().collect::<String>()).ok_or_else(invalid)?;
+
+    // What remains is disambiguation and an optional capture marker
+    let mut source_file = None;
+    let mut source_rank = None;
+    for c in chars {
+        match c {
+            'x' | ':' => {}
+            c if BoardFile::from_char(c).is_some() => source_file = BoardFile::from_char(c),
+            c if BoardRank::from_char(c).is_some() => source_rank = BoardRank::from_char(c),
+            _ => return Err(invalid()),
+        }
+    }
+
+    let mut candidates = legal_moves.iter().copied().filter(|&mv| {
+        board.pieces().get(mv.source).map(|p| p.kind()) == Some(kind)
+            && castling_side(board, mv).is_none()
+            && mv.destination == destination
+            && mv.promotion == promotion
+            && source_file.is_none_or(|f| mv.source.file() == f)
+            && source_rank.is_none_or(|r| mv.source.rank() == r)
+    });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(mv), None) => Ok(mv),
+        (Some(_), Some(_)) => Err(SanError::Ambiguous(san.to_string())),
+        (None, _) => Err(SanError::Illegal(san.to_string())),
+    }
+}
+
+/// Convert a legal move to SAN, including the check or checkmate marker.
+pub fn move_to_san(board: &mut Board, mv: Move) -> String {
+    let piece = board
+        .pieces()
+        .get(mv.source)
+        .expect("There should always be a piece at the source square");
+
+    let mut legal_moves = MoveList::new();
+    compute_legal_moves(&mut legal_moves, board);
+
+    let mut san = String::new();
+
+    if let Some(kingside) = castling_side(board, mv) {
+        san.push_str(if kingside { "O-O" } else { "O-O-O" });
+    } else {
+        let is_capture = board.pieces().get(mv.destination).is_some()
+            || (piece.kind() == PieceKind::Pawn && mv.source.file() != mv.destination.file());
+
+        if piece.kind() == PieceKind::Pawn {
+            if is_capture {
+                san.push(mv.source.file().as_char());
+            }
+        } else {
+            san.push(piece.kind().as_char().to_ascii_uppercase());
+
+            let others = legal_moves
+                .iter()
+                .filter(|other| {
+                    other.source != mv.source
+                        && other.destination == mv.destination
+                        && board.pieces().get(other.source) == Some(piece)
+                })
+                .collect::<Vec<_>>();
+
+            if !others.is_empty() {
+                if others.iter().all(|o| o.source.file() != mv.source.file()) {
+                    san.push(mv.source.file().as_char());
+                } else if others.iter().all(|o| o.source.rank() != mv.source.rank()) {
+                    san.push(mv.source.rank().as_char());
+                } else {
+                    san.push_str(&mv.source.name());
+                }
+            }
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&mv.destination.name());
+
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(promotion.as_char().to_ascii_uppercase());
+        }
+    }
+
+    let um = board.make_move(mv);
+    legal_moves.clear();
+    let is_check = compute_legal_moves(&mut legal_moves, board);
+    if is_check {
+        san.push(if legal_moves.is_empty() { '#' } else { '+' });
+    }
+    board.unmake_last_move(um);
+
+    san
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_san(fen: &str, san: &str, uci: &str) {
+        let mut board = Board::from_fen(fen).unwrap();
+        let mv = Move::from_uci(uci).unwrap();
+        assert_eq!(parse_san(&board, san), Ok(mv), "parsing {san}");
+        assert_eq!(move_to_san(&mut board, mv), san, "writing {uci}");
+    }
+
+    #[test]
+    fn test_san_pieces_and_pawns() {
+        const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        check_san(START, "e4", "e2e4");
+        check_san(START, "Nf3", "g1f3");
+        check_san(
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+            "exd5",
+            "e4d5",
+        );
+        check_san(
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+            "exd6",
+            "e5d6",
+        );
+    }
+
+    #[test]
+    fn test_san_disambiguation() {
+        // Knights on b1 and f1 can both reach d2
+        check_san("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "Nbd2", "b1d2");
+        // Rooks on a1 and a5 can both reach a3
+        check_san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "R1a3", "a1a3");
+        // Queens on a1, a3 and c1 can all reach b2
+        check_san("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "Qa1b2", "a1b2");
+        assert_eq!(
+            parse_san(
+                &Board::from_fen("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1").unwrap(),
+                "Nd2"
+            ),
+            Err(SanError::Ambiguous("Nd2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_san_promotion_and_check() {
+        check_san("8/P7/8/8/8/8/8/K5k1 w - - 0 1", "a8=Q", "a7a8q");
+        check_san("7k/P7/8/8/8/8/8/K7 w - - 0 1", "a8=R+", "a7a8r");
+        assert_eq!(
+            parse_san(
+                &Board::from_fen("8/P7/8/8/8/8/8/K5k1 w - - 0 1").unwrap(),
+                "a8N"
+            ),
+            Ok(Move::from_uci("a7a8n").unwrap())
+        );
+        check_san(
+            "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+            "Qh4#",
+            "d8h4",
+        );
+    }
+
+    #[test]
+    fn test_san_castling() {
+        check_san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "O-O", "e1g1");
+        check_san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "O-O-O", "e8c8");
+        assert_eq!(
+            parse_san(
+                &Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap(),
+                "0-0-0"
+            ),
+            Ok(Move::from_uci("e1c1").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_san_errors() {
+        let board = Board::starting();
+        assert_eq!(
+            parse_san(&board, "e5"),
+            Err(SanError::Illegal("e5".to_string()))
+        );
+        assert_eq!(
+            parse_san(&board, "Ze4"),
+            Err(SanError::Invalid("Ze4".to_string()))
+        );
+        assert_eq!(
+            parse_san(&board, "O-O"),
+            Err(SanError::Illegal("O-O".to_string()))
+        );
+    }
+}
@@ -0,0 +1,733 @@
+//! A match runner built on `chess_lib`, so matches can be played without fastchess. Two UCI engine
+//! processes are driven per concurrent game, clocks are kept by the arbiter, and games are
+//! adjudicated with `Board::status`. Games are played in pairs from the same opening with colours
+//! swapped, and written to the PGN in the format fastchess uses, so `results` reads them the same
+//! way.
+
+mod engine;
+
+use crate::setup::{ChessBot, OpeningOrder};
+use chess_lib::board::{Board, Color, Move};
+use chess_lib::epd::read_epds;
+use chess_lib::pgn::{GameResult, PgnGame, PgnReader};
+use color_print::{cformat, cprintln};
+use engine::{EngineError, UciEngine};
+use rand::seq::SliceRandom;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Sender, channel};
+use std::thread;
+use std::time::Duration;
+
+/// How far past its clock an engine may go before losing on time, to allow for process overhead.
+const TIME_MARGIN: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    /// `base+increment` in seconds, as fastchess takes them, e.g. `10+0.1`. The increment may be
+    /// left out.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, increment) = s.split_once('+').unwrap_or((s, "0"));
+        let seconds = |s: &str| {
+            s.parse::<f64>()
+                .ok()
+                .filter(|s| s.is_finite() && *s >= 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| format!("invalid time control '{s}'"))
+        };
+        Ok(TimeControl {
+            base: seconds(base)?,
+            increment: seconds(increment)?,
+        })
+    }
+}
+
+/// Start `bot` with its options, and `UCI_Chess960` if the openings need it.
+fn start_engine(bot: &ChessBot, chess960: bool) -> Result<UciEngine, EngineError> {
+    let mut options = bot.options.clone();
+    if chess960 {
+        options.push(("UCI_Chess960".to_string(), "true".to_string()));
+    }
+    UciEngine::start(&bot.name, bot.command(), &options)
+}
+
+/// Check `bot` starts and completes a UCI handshake with its options set.
+pub fn check_engine(bot: &ChessBot) -> Result<(), EngineError> {
+    start_engine(bot, false).map(|_| ())
+}
+
+pub struct ArbiterOptions {
+    pub time_control: TimeControl,
+    /// Number of games played at once.
+    pub concurrency: usize,
+    /// EPD or PGN file of starting positions.
+    pub openings: Option<String>,
+    pub opening_order: OpeningOrder,
+    pub pgn_out: String,
+}
+
+impl ArbiterOptions {
+    /// Panics if `time_control` isn't valid.
+    pub fn new(
+        time_control: &str,
+        concurrency: usize,
+        openings: Option<String>,
+        opening_order: OpeningOrder,
+        pgn_out: &str,
+    ) -> ArbiterOptions {
+        let time_control = TimeControl::from_str(time_control)
+            .unwrap_or_else(|e| panic!("{}", cformat!("<r,bold>{}</>", e)));
+        ArbiterOptions {
+            time_control,
+            concurrency,
+            openings,
+            opening_order,
+            pgn_out: pgn_out.to_string(),
+        }
+    }
+}
+
+/// A position games start from, and the moves that reached it.
+#[derive(Debug, Clone)]
+pub struct Opening {
+    pub board: Board,
+    pub moves: Vec<Move>,
+}
+
+impl Opening {
+    fn starting() -> Opening {
+        Opening {
+            board: Board::starting(),
+            moves: Vec::new(),
+        }
+    }
+
+    /// The arguments of the UCI `position` command after `moves`.
+    fn position(&self, moves: &[Move]) -> String {
+        let mut position = if self.board == Board::starting() {
+            "startpos".to_string()
+        } else {
+            format!("fen {}", self.board.to_fen())
+        };
+        if !moves.is_empty() {
+            position += " moves";
+            for mv in moves {
+                position += " ";
+                position += &mv.as_uci();
+            }
+        }
+        position
+    }
+}
+
+/// Openings from an EPD file, or the main lines of the games of a PGN file.
+pub fn load_openings(path: &str) -> Result<Vec<Opening>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+
+    if path.to_ascii_lowercase().ends_with(".pgn") {
+        PgnReader::new(&text)
+            .map(|game| {
+                let game = game.map_err(|e| format!("{path}: {e}"))?;
+                Ok(Opening {
+                    board: game.initial_board().map_err(|e| format!("{path}: {e}"))?,
+                    moves: game.mainline().collect(),
+                })
+            })
+            .collect()
+    } else {
+        read_epds(&text)
+            .into_iter()
+            .map(|(line, epd)| {
+                epd.map(|epd| Opening {
+                    board: epd.board,
+                    moves: Vec::new(),
+                })
+                .map_err(|e| format!("{path}:{line}: {e}"))
+            })
+            .collect()
+    }
+}
+
+pub struct FinishedGame {
+    /// From 1, in the order games were started.
+    pub number: usize,
+    pub game: PgnGame,
+    /// e.g. "White mates".
+    pub reason: String,
+}
+
+impl FinishedGame {
+    pub fn white(&self) -> &str {
+        self.game.tag("White").unwrap_or("?")
+    }
+}
+
+fn colour_name(color: Color) -> &'static str {
+    if color.is_white() { "White" } else { "Black" }
+}
+
+/// Result, reason and PGN termination of a game lost by `loser` other than on the board.
+fn forfeit(
+    loser: Color,
+    reason: &str,
+    termination: &'static str,
+) -> (GameResult, String, &'static str) {
+    let result = if loser.is_white() {
+        GameResult::BlackWin
+    } else {
+        GameResult::WhiteWin
+    };
+    (
+        result,
+        format!("{} {reason}", colour_name(loser)),
+        termination,
+    )
+}
+
+/// The eval and time of a move, in the format fastchess uses, e.g. `+0.31/12 0.512s`.
+fn move_comment(eval: Option<String>, depth: Option<u32>, time: Duration) -> String {
+    match (eval, depth) {
+        (Some(eval), Some(depth)) => format!("{eval}/{depth} {:.3}s", time.as_secs_f64()),
+        _ => format!("{:.3}s", time.as_secs_f64()),
+    }
+}
+
+/// Play a game between `white` and `black`. Returns None if the match was stopped before the
+/// game ended.
+fn play_game(
+    white: &mut UciEngine,
+    black: &mut UciEngine,
+    opening: &Opening,
+    time_control: TimeControl,
+    round: usize,
+    stop: &AtomicBool,
+) -> Option<(PgnGame, String)> {
+    let mut board = opening.board.clone();
+    for &mv in &opening.moves {
+        board.make_move(mv);
+    }
+    let mut moves = opening.moves.clone();
+    let mut comments = vec!["book".to_string(); moves.len()];
+    let mut clocks = [time_control.base; 2];
+
+    let new_game = [white.new_game(), black.new_game()];
+    let engines = [white, black];
+
+    let (result, reason, termination) = 'game: {
+        for (color, ready) in [Color::White, Color::Black].into_iter().zip(new_game) {
+            if ready.is_err() {
+                break 'game forfeit(color, "disconnects", "abandoned");
+            }
+        }
+
+        loop {
+            let status = board.status();
+            if status.is_over() {
+                break 'game (status.result(), status.description().to_string(), "normal");
+            }
+            if stop.load(Ordering::SeqCst) {
+                return None;
+            }
+
+            let color = board.color_to_move();
+            let side = !color.is_white() as usize;
+            let go = format!(
+                "wtime {} btime {} winc {} binc {}",
+                clocks[0].as_millis(),
+                clocks[1].as_millis(),
+                time_control.increment.as_millis(),
+                time_control.increment.as_millis()
+            );
+
+            let engine_move = match engines[side].go(
+                &opening.position(&moves),
+                &go,
+                clocks[side] + TIME_MARGIN,
+            ) {
+                Ok(engine_move) if engine_move.time <= clocks[side] + TIME_MARGIN => engine_move,
+                Ok(_) => break 'game forfeit(color, "loses on time", "time forfeit"),
+                Err(EngineError::Timeout) => {
+                    engines[side].stop();
+                    break 'game forfeit(color, "loses on time", "time forfeit");
+                }
+                Err(EngineError::Disconnected) => {
+                    break 'game forfeit(color, "disconnects", "abandoned");
+                }
+                Err(EngineError::InvalidMove(_)) => {
+                    break 'game forfeit(color, "makes an illegal move", "rules infraction");
+                }
+            };
+
+            let Some(mv) = board.normalise_move(engine_move.mv) else {
+                break 'game forfeit(color, "makes an illegal move", "rules infraction");
+            };
+
+            clocks[side] = clocks[side].saturating_sub(engine_move.time) + time_control.increment;
+            comments.push(move_comment(
+                engine_move.eval.map(|eval| eval.to_string()),
+                engine_move.depth,
+                engine_move.time,
+            ));
+            moves.push(mv);
+            board.make_move(mv);
+        }
+    };
+
+    let tags = vec![
+        ("Event".to_string(), "chess-runner match".to_string()),
+        ("Round".to_string(), round.to_string()),
+        ("White".to_string(), engines[0].name.clone()),
+        ("Black".to_string(), engines[1].name.clone()),
+        (
+            "TimeControl".to_string(),
+            format!(
+                "{}+{}",
+                time_control.base.as_secs_f64(),
+                time_control.increment.as_secs_f64()
+            ),
+        ),
+        ("Termination".to_string(), termination.to_string()),
+    ];
+    let mut game = PgnGame::from_moves(tags, &opening.board, &moves, result)
+        .expect("Moves were checked to be legal");
+    let moves_count = game.moves.len();
+    for (i, (m, comment)) in game.moves.iter_mut().zip(comments).enumerate() {
+        m.comments = if i + 1 == moves_count {
+            vec![format!("{comment}, {reason}")]
+        } else {
+            vec![comment]
+        };
+    }
+
+    Some((game, reason))
+}
+
+/// Plays games from a shared counter with its own pair of engine processes, until every game
+/// has been started or the match is stopped.
+fn worker(
+    bots: &[ChessBot; 2],
+    openings: &[Opening],
+    games: usize,
+    time_control: TimeControl,
+    next_game: &AtomicUsize,
+    stop: &AtomicBool,
+    sender: Sender<FinishedGame>,
+) {
+    let chess960 = openings.iter().any(|o| o.board.is_chess960());
+    let mut processes: [Option<UciEngine>; 2] = [None, None];
+
+    loop {
+        let index = next_game.fetch_add(1, Ordering::SeqCst);
+        if index >= games || stop.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // Engines that crashed or timed out in the last game are restarted
+        for (process, bot) in processes.iter_mut().zip(bots) {
+            if process.as_mut().is_none_or(|p| p.is_ready().is_err()) {
+                match start_engine(bot, chess960) {
+                    Ok(started) => *process = Some(started),
+                    Err(e) => {
+                        cprintln!("<r,bold>Failed to start {}: {}</>", bot.name, e);
+                        stop.store(true, Ordering::SeqCst);
+                        return;
+                    }
+                }
+            }
+        }
+
+        let pair = index / 2;
+        let opening = &openings[pair % openings.len()];
+        let [first, second] = &mut processes;
+        let (first, second) = (first.as_mut().unwrap(), second.as_mut().unwrap());
+        let (white, black) = if index.is_multiple_of(2) {
+            (first, second)
+        } else {
+            (second, first)
+        };
+
+        let Some((game, reason)) = play_game(white, black, opening, time_control, pair + 1, stop)
+        else {
+            return;
+        };
+        let finished = FinishedGame {
+            number: index + 1,
+            game,
+            reason,
+        };
+        if sender.send(finished).is_err() {
+            return;
+        }
+    }
+}
+
+/// Play `games` games between `bots`, appending them to the PGN as they finish. `on_game` is
+/// called after each game, and may stop the match, after which it isn't called again. Games
+/// that finish while the other workers are stopping are still recorded, and games in progress
+/// are abandoned.
+pub fn play_match(
+    bots: [ChessBot; 2],
+    games: usize,
+    options: &ArbiterOptions,
+    mut on_game: impl FnMut(&FinishedGame) -> ControlFlow<()>,
+) {
+    let mut openings = match &options.openings {
+        Some(path) => match load_openings(path) {
+            Ok(openings) if !openings.is_empty() => openings,
+            Ok(_) => panic!("{}", cformat!("<r,bold>No openings in {}</>", path)),
+            Err(e) => panic!("{}", cformat!("<r,bold>Failed to load openings: {}</>", e)),
+        },
+        None => vec![Opening::starting()],
+    };
+    if options.opening_order == OpeningOrder::Random {
+        openings.shuffle(&mut rand::rng());
+    }
+
+    cprintln!(
+        "<c>Playing {} games of {} vs {} at {}+{}, concurrency {}</>",
+        games,
+        bots[0].name,
+        bots[1].name,
+        options.time_control.base.as_secs_f64(),
+        options.time_control.increment.as_secs_f64(),
+        options.concurrency
+    );
+
+    let next_game = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = channel();
+
+    thread::scope(|s| {
+        for _ in 0..options.concurrency.max(1) {
+            let sender = sender.clone();
+            s.spawn(|| {
+                worker(
+                    &bots,
+                    &openings,
+                    games,
+                    options.time_control,
+                    &next_game,
+                    &stop,
+                    sender,
+                )
+            });
+        }
+        drop(sender);
+
+        for finished in receiver {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&options.pgn_out)
+                .and_then(|mut file| writeln!(file, "{}", finished.game.to_pgn()));
+            if let Err(e) = written {
+                cprintln!("<r,bold>Failed to write to {}: {}</>", options.pgn_out, e);
+            }
+
+            println!(
+                "Finished game {} ({} vs {}): {} {{{}}}",
+                finished.number,
+                finished.white(),
+                finished.game.tag("Black").unwrap_or("?"),
+                finished.game.result,
+                finished.reason
+            );
+
+            if !stop.load(Ordering::SeqCst) && on_game(&finished).is_break() {
+                stop.store(true, Ordering::SeqCst);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::LazyLock;
+
+    /// A scripted UCI engine. `play <moves>` answers each `go` with the move for the ply,
+    /// `late` only answers once it's sent `stop` (or, like an engine that never received one,
+    /// the next `go`), and `crash` exits on `go`.
+    const FAKE_ENGINE: &str = r#"
+mode=$1
+shift
+pending=
+while read -r command rest; do
+    case $command in
+        uci) echo "id name Fake"; echo "uciok" ;;
+        isready) echo "readyok" ;;
+        position)
+            case "$rest" in
+                *" moves "*) ply=$(echo ${rest#* moves } | wc -w) ;;
+                *) ply=0 ;;
+            esac ;;
+        go)
+            case $mode in
+                play) eval "echo bestmove \${$((ply + 1))}" ;;
+                late)
+                    if [ -n "$pending" ]; then echo "bestmove e2e4"; fi
+                    pending=1 ;;
+                crash) exit 0 ;;
+            esac ;;
+        stop)
+            if [ -n "$pending" ]; then echo "bestmove e2e4"; pending=; fi ;;
+        quit) exit 0 ;;
+    esac
+done
+"#;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("chess_runner_arbiter_{name}"));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    static FAKE_ENGINE_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+        let path = temp_path("engine.sh");
+        std::fs::write(&path, FAKE_ENGINE).unwrap();
+        path
+    });
+
+    fn fake_engine(name: &str, args: &[&str]) -> ChessBot {
+        let script = &*FAKE_ENGINE_PATH;
+        ChessBot {
+            name: name.to_string(),
+            path: "sh".to_string(),
+            args: [script.display().to_string()]
+                .into_iter()
+                .chain(args.iter().map(|a| a.to_string()))
+                .collect(),
+            dir: None,
+            options: Vec::new(),
+        }
+    }
+
+    /// Number, White, result and reason of a finished game.
+    type Finished = (usize, String, GameResult, String);
+
+    /// Play `games` games, returning the number, White, result and reason of each, and the
+    /// games written to the PGN.
+    fn play(
+        name: &str,
+        bots: [ChessBot; 2],
+        games: usize,
+        openings: Option<&str>,
+    ) -> (Vec<Finished>, Vec<PgnGame>) {
+        let openings = openings.map(|epd| {
+            let path = temp_path(&format!("{name}.epd"));
+            std::fs::write(&path, epd).unwrap();
+            path.display().to_string()
+        });
+        let pgn_out = temp_path(&format!("{name}.pgn"));
+        let options = ArbiterOptions::new(
+            "0.2+0",
+            1,
+            openings,
+            OpeningOrder::Sequential,
+            &pgn_out.display().to_string(),
+        );
+
+        let mut finished_games = Vec::new();
+        play_match(bots, games, &options, |finished| {
+            finished_games.push((
+                finished.number,
+                finished.white().to_string(),
+                finished.game.result,
+                finished.reason.clone(),
+            ));
+            ControlFlow::Continue(())
+        });
+
+        let pgn = std::fs::read_to_string(&pgn_out).unwrap();
+        std::fs::remove_file(&pgn_out).unwrap();
+        let games = PgnReader::new(&pgn).map(Result::unwrap).collect();
+        (finished_games, games)
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_adjudication() {
+        let fools_mate = ["play", "f2f3", "e7e5", "g2g4", "d8h4"];
+        let (finished, games) = play(
+            "mate",
+            [fake_engine("A", &fools_mate), fake_engine("B", &fools_mate)],
+            4,
+            None,
+        );
+        assert_eq!(
+            finished
+                .iter()
+                .map(|(number, white, _, _)| (*number, white.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "A"), (2, "B"), (3, "A"), (4, "B")]
+        );
+        assert!(finished.iter().all(|(_, _, result, reason)| {
+            *result == GameResult::BlackWin && reason == "Black mates"
+        }));
+
+        assert_eq!(games.len(), 4);
+        let tags = |game: &PgnGame| {
+            ["Round", "White", "Black", "Termination"].map(|tag| game.tag(tag).unwrap().to_string())
+        };
+        assert_eq!(tags(&games[0]), ["1", "A", "B", "normal"]);
+        assert_eq!(tags(&games[1]), ["1", "B", "A", "normal"]);
+        assert_eq!(tags(&games[2]), ["2", "A", "B", "normal"]);
+        assert_eq!(games[0].moves.len(), 4);
+        assert_eq!(games[0].result, GameResult::BlackWin);
+
+        // The queen move stalemates
+        let stalemate = ["play", "g1g6"];
+        let (finished, games) = play(
+            "stalemate",
+            [fake_engine("A", &stalemate), fake_engine("B", &stalemate)],
+            2,
+            Some("7k/8/7K/8/8/8/8/6Q1 w - -\n"),
+        );
+        assert!(
+            finished
+                .iter()
+                .all(|(_, _, result, _)| *result == GameResult::Draw)
+        );
+        assert_eq!(games[1].tag("White"), Some("B"));
+        assert_eq!(games[1].moves.len(), 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_stop() {
+        let fools_mate = ["play", "f2f3", "e7e5", "g2g4", "d8h4"];
+        let pgn_out = temp_path("stop.pgn");
+        let options = ArbiterOptions::new(
+            "1+0",
+            4,
+            None,
+            OpeningOrder::Sequential,
+            &pgn_out.display().to_string(),
+        );
+
+        // Games finishing while the other workers stop mustn't be passed on, or a verdict
+        // reached on the first could be overwritten
+        let mut calls = 0;
+        play_match(
+            [fake_engine("A", &fools_mate), fake_engine("B", &fools_mate)],
+            100,
+            &options,
+            |_| {
+                calls += 1;
+                ControlFlow::Break(())
+            },
+        );
+        let _ = std::fs::remove_file(&pgn_out);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_forfeits() {
+        let reasons = |finished: &[Finished]| {
+            finished
+                .iter()
+                .map(|(_, _, result, reason)| (*result, reason.clone()))
+                .collect::<Vec<_>>()
+        };
+        let opening = ["play", "f2f3", "e7e5", "g2g4", "d8h4"];
+
+        // A search that overruns is stopped, so its late move isn't taken as the next reply
+        let (finished, games) = play(
+            "time",
+            [fake_engine("A", &["late"]), fake_engine("B", &opening)],
+            2,
+            None,
+        );
+        assert_eq!(
+            reasons(&finished),
+            vec![
+                (GameResult::BlackWin, "White loses on time".to_string()),
+                (GameResult::WhiteWin, "Black loses on time".to_string())
+            ]
+        );
+        assert_eq!(games[0].tag("Termination"), Some("time forfeit"));
+
+        let (finished, _) = play(
+            "illegal",
+            [
+                fake_engine("A", &["play", "e2e5"]),
+                fake_engine("B", &opening),
+            ],
+            2,
+            None,
+        );
+        assert_eq!(
+            reasons(&finished),
+            vec![
+                (
+                    GameResult::BlackWin,
+                    "White makes an illegal move".to_string()
+                ),
+                (
+                    GameResult::WhiteWin,
+                    "Black makes an illegal move".to_string()
+                )
+            ]
+        );
+
+        // The crashed engine is restarted for the second game
+        let (finished, games) = play(
+            "crash",
+            [fake_engine("A", &["crash"]), fake_engine("B", &opening)],
+            2,
+            None,
+        );
+        assert_eq!(
+            reasons(&finished),
+            vec![
+                (GameResult::BlackWin, "White disconnects".to_string()),
+                (GameResult::WhiteWin, "Black disconnects".to_string())
+            ]
+        );
+        assert_eq!(games[1].tag("Termination"), Some("abandoned"));
+    }
+
+    #[test]
+    fn test_time_control() {
+        let tc = TimeControl::from_str("10+0.1").unwrap();
+        assert_eq!(tc.base, Duration::from_secs(10));
+        assert_eq!(tc.increment, Duration::from_millis(100));
+
+        let tc = TimeControl::from_str("30").unwrap();
+        assert_eq!(tc.base, Duration::from_secs(30));
+        assert_eq!(tc.increment, Duration::ZERO);
+
+        assert!(TimeControl::from_str("10+").is_err());
+        assert!(TimeControl::from_str("-1+0").is_err());
+        assert!(TimeControl::from_str("fast").is_err());
+    }
+
+    #[test]
+    fn test_position() {
+        let e4 = Move::from_uci("e2e4").unwrap();
+        let e5 = Move::from_uci("e7e5").unwrap();
+
+        let opening = Opening::starting();
+        assert_eq!(opening.position(&[]), "startpos");
+        assert_eq!(opening.position(&[e4, e5]), "startpos moves e2e4 e7e5");
+
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let opening = Opening {
+            board: Board::from_fen(fen).unwrap(),
+            moves: Vec::new(),
+        };
+        assert_eq!(opening.position(&[e5]), format!("fen {fen} moves e7e5"));
+    }
+}
@@ -0,0 +1,209 @@
+//! A UCI engine running as a child process. Its output is read on a separate thread, so the
+//! arbiter can stop waiting for a move once the engine's clock runs out.
+
+use crate::results::Eval;
+use chess_lib::board::Move;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an engine may take to answer `uci` and `isready`.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long an engine may take to send its `bestmove` after `stop`.
+const STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    /// The engine didn't reply in time.
+    Timeout,
+    /// The engine exited, or its pipes were closed.
+    Disconnected,
+    /// The engine sent a `bestmove` that isn't a move.
+    InvalidMove(String),
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineError::Timeout => write!(f, "timed out"),
+            EngineError::Disconnected => write!(f, "disconnected"),
+            EngineError::InvalidMove(mv) => write!(f, "invalid move '{mv}'"),
+        }
+    }
+}
+
+/// A move and the last search info sent before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineMove {
+    pub mv: Move,
+    /// From the point of view of the engine.
+    pub eval: Option<Eval>,
+    pub depth: Option<u32>,
+    pub time: Duration,
+}
+
+pub struct UciEngine {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl UciEngine {
+    /// Start the engine, set `options`, and wait until it's ready.
+    pub fn start(
+        name: &str,
+        mut command: Command,
+        options: &[(String, String)],
+    ) -> Result<UciEngine, EngineError> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|_| EngineError::Disconnected)?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            name: name.to_string(),
+            child,
+            stdin,
+            lines,
+        };
+
+        engine.send("uci")?;
+        engine.wait_for("uciok", STARTUP_TIMEOUT)?;
+        for (name, value) in options {
+            engine.send(&format!("setoption name {name} value {value}"))?;
+        }
+        engine.is_ready()?;
+
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), EngineError> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| EngineError::Disconnected)
+    }
+
+    /// Lines read until one starting with `token`, which is returned last.
+    fn wait_for(&mut self, token: &str, timeout: Duration) -> Result<Vec<String>, EngineError> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let line = self.lines.recv_timeout(remaining).map_err(|e| match e {
+                RecvTimeoutError::Timeout => EngineError::Timeout,
+                RecvTimeoutError::Disconnected => EngineError::Disconnected,
+            })?;
+
+            let done = line.split_whitespace().next() == Some(token);
+            lines.push(line);
+            if done {
+                return Ok(lines);
+            }
+        }
+    }
+
+    pub fn is_ready(&mut self) -> Result<(), EngineError> {
+        self.send("isready")?;
+        self.wait_for("readyok", STARTUP_TIMEOUT).map(|_| ())
+    }
+
+    /// Stop a search that ran out of time, reading its late `bestmove` so it isn't taken as the
+    /// reply to the next `go`. An engine that doesn't stop is killed, so it's restarted.
+    pub fn stop(&mut self) {
+        let stopped = self
+            .send("stop")
+            .and_then(|_| self.wait_for("bestmove", STOP_TIMEOUT));
+        if stopped.is_err() {
+            let _ = self.child.kill();
+        }
+    }
+
+    pub fn new_game(&mut self) -> Result<(), EngineError> {
+        self.send("ucinewgame")?;
+        self.is_ready()
+    }
+
+    /// Search the position given by `position` (the arguments of a UCI `position` command),
+    /// waiting at most `timeout` for the move.
+    pub fn go(
+        &mut self,
+        position: &str,
+        go: &str,
+        timeout: Duration,
+    ) -> Result<EngineMove, EngineError> {
+        self.send(&format!("position {position}"))?;
+        self.send(&format!("go {go}"))?;
+
+        let start = Instant::now();
+        let lines = self.wait_for("bestmove", timeout)?;
+        let time = start.elapsed();
+
+        let bestmove = lines.last().unwrap();
+        let mv = bestmove.split_whitespace().nth(1).unwrap_or("");
+        let mut engine_move = EngineMove {
+            mv: Move::from_uci(mv).ok_or_else(|| EngineError::InvalidMove(mv.to_string()))?,
+            eval: None,
+            depth: None,
+            time,
+        };
+
+        for line in lines.iter().filter(|l| l.starts_with("info")) {
+            let tokens = line.split_whitespace().collect::<Vec<_>>();
+            for (i, token) in tokens.iter().enumerate() {
+                let next = tokens.get(i + 1);
+                let value = tokens.get(i + 2).and_then(|v| v.parse::<i32>().ok());
+                match (*token, next) {
+                    ("depth", Some(depth)) => {
+                        engine_move.depth = depth.parse().ok().or(engine_move.depth)
+                    }
+                    ("score", Some(&"cp")) => {
+                        engine_move.eval = value.map(Eval::Centipawns).or(engine_move.eval)
+                    }
+                    ("score", Some(&"mate")) => {
+                        engine_move.eval = value.map(Eval::Mate).or(engine_move.eval)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(engine_move)
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // Give the engine a moment to exit cleanly
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -1,17 +1,17 @@
+use crate::arbiter::check_engine;
+use crate::config::BotConfig;
+use crate::registry::{ENGINES_FILE, Registry};
 use crate::setup::{ChessBot, LocalBot};
 use crate::util::add_exe_on_windows;
 use color_print::{cformat, cprint, cprintln};
-use either::Either;
 use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{LazyLock, Mutex};
 
-static VERSIONS_FETCHED: LazyLock<Mutex<HashSet<String>>> =
-    LazyLock::new(|| Mutex::new(HashSet::new()));
 static DIRECTORIES_BUILT: LazyLock<Mutex<HashSet<PathBuf>>> =
     LazyLock::new(|| Mutex::new(HashSet::new()));
 
@@ -141,6 +141,22 @@ pub fn get_stockfish() -> ChessBot {
     ChessBot {
         name: "Stockfish".to_string(),
         path,
+        args: Vec::new(),
+        dir: None,
+        options: Vec::new(),
+    }
+}
+
+/// Stockfish playing at `elo`, with `UCI_LimitStrength`.
+pub fn get_limited_stockfish(elo: u32) -> ChessBot {
+    let stockfish = get_stockfish();
+    ChessBot {
+        name: format!("{}_{}", stockfish.name, elo),
+        options: vec![
+            ("UCI_LimitStrength".to_string(), "true".to_string()),
+            ("UCI_Elo".to_string(), elo.to_string()),
+        ],
+        ..stockfish
     }
 }
 
@@ -152,6 +168,9 @@ pub fn resolve_local_bot(local_bot: LocalBot) -> ChessBot {
             path: compile_directory(PathBuf::from_str(".").unwrap())
                 .display()
                 .to_string(),
+            args: Vec::new(),
+            dir: None,
+            options: Vec::new(),
         },
         LocalBot::ChessCodingAdventure => ChessBot {
             name: "Chess-Coding-Adventure".to_string(),
@@ -159,89 +178,292 @@ pub fn resolve_local_bot(local_bot: LocalBot) -> ChessBot {
                 .unwrap()
                 .display()
                 .to_string(),
+            args: Vec::new(),
+            dir: None,
+            options: Vec::new(),
         },
     }
 }
 
-pub fn resolve_remote_bot(version: String) -> ChessBot {
-    if VERSIONS_FETCHED.lock().unwrap().contains(&version) {
-        cprintln!("<green,bold>{version} already fetched!</>");
+/// Remote repository that versions missing from the local one are fetched from.
+pub const REMOTE_REPOSITORY: &str = "https://github.com/grass-latte/beans-gambit.git";
+
+/// Trimmed stdout of a successful git command run in `repo`.
+fn git_output(repo: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(repo)
+        .args(args)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Commit hash of `version` in `repo` - a `versions/<version>` branch (local or fetched), or any
+/// other branch, tag or commit.
+pub fn resolve_commit(repo: &Path, version: &str) -> Option<String> {
+    [
+        format!("versions/{version}"),
+        format!("origin/versions/{version}"),
+        version.to_string(),
+    ]
+    .iter()
+    .find_map(|rev| {
+        git_output(
+            repo,
+            &[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{rev}^{{commit}}"),
+            ],
+        )
+    })
+}
+
+/// Versions in branches `versions/*` of `repo`, including ones fetched from `origin`.
+pub fn local_versions(repo: &Path) -> Vec<String> {
+    let Some(refs) = git_output(
+        repo,
+        &[
+            "for-each-ref",
+            "--format=%(refname)",
+            "refs/heads/versions",
+            "refs/remotes/origin/versions",
+        ],
+    ) else {
+        return Vec::new();
+    };
+
+    let mut versions = refs
+        .lines()
+        .filter_map(|ref_name| {
+            ref_name
+                .strip_prefix("refs/heads/versions/")
+                .or_else(|| ref_name.strip_prefix("refs/remotes/origin/versions/"))
+                .map(|v| v.to_string())
+        })
+        .collect::<Vec<_>>();
+    versions.sort();
+    versions.dedup();
+    versions
+}
+
+/// Fetch branch `versions/<version>` of the remote repository into `repo`.
+fn fetch_version(repo: &Path, version: &str) -> bool {
+    cprintln!("<c,bold>{version} not found locally - fetching from {REMOTE_REPOSITORY}...</>");
+    Command::new("git")
+        .current_dir(repo)
+        .arg("fetch")
+        .arg(REMOTE_REPOSITORY)
+        .arg(format!(
+            "refs/heads/versions/{version}:refs/heads/versions/{version}"
+        ))
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Where the binary built from `commit` is cached in `repo`.
+fn cached_binary(repo: &Path, commit: &str) -> PathBuf {
+    let profile = if cfg!(debug_assertions) {
+        "debug"
     } else {
-        cprintln!("<c,bold>Fetching {version}...</>");
+        "release"
+    };
+    repo.join(add_exe_on_windows(format!(
+        "versions/bin/{commit}-{profile}"
+    )))
+}
+
+/// Build `commit` of `repo` in a temporary worktree, unless it's already in the binary cache
+/// (`versions/bin`), and return the cached binary.
+fn build_commit(repo: &Path, commit: &str) -> PathBuf {
+    let binary = cached_binary(repo, commit);
+    if binary.is_file() {
+        cprintln!("<green,bold>{commit} already built!</>");
+        return binary;
+    }
+
+    let worktree = repo.join("versions").join("worktrees").join(commit);
+    if !worktree.exists() {
+        let Ok(status) = Command::new("git")
+            .current_dir(repo)
+            .args(["worktree", "add", "--detach"])
+            .arg(&worktree)
+            .arg(commit)
+            .status()
+        else {
+            panic!("{}", cformat!("<r,bold>Failed to run git command</>"));
+        };
 
-        if fs::create_dir_all("versions").is_err() {
+        if !status.success() {
             panic!(
                 "{}",
-                cformat!("<r,bold>Failed to create versions folder.</>")
+                cformat!("<r,bold>git worktree add of {commit} failed</>")
             );
         }
+    }
 
-        if fs::exists(format!("versions/{version}")).unwrap_or(false) {
-            cprintln!("<c,bold>Version folder already exists - git resetting...</>");
-            let mut git_reset = Command::new("git")
-                .current_dir(format!("versions/{version}"))
-                .arg("reset")
-                .arg("--hard")
-                .status()
-                .is_ok_and(|s| s.success());
-
-            if git_reset {
-                git_reset = Command::new("git")
-                    .current_dir(format!("versions/{version}"))
-                    .arg("pull")
-                    .status()
-                    .is_ok_and(|s| s.success());
-            }
-
-            if !git_reset {
-                cprintln!("<r,bold>git reset / pull failed. Deleting and redownloading...</>");
-
-                if fs::remove_dir_all(format!("versions/{version}")).is_err() {
-                    panic!(
-                        "{}",
-                        cformat!("<r,bold>Failed to remove versions/{version}</>")
-                    );
-                }
-
-                let Ok(status) = Command::new("git")
-                    .arg("clone")
-                    .arg("-b")
-                    .arg(format!("versions/{version}"))
-                    .arg("https://github.com/grass-latte/beans-gambit.git")
-                    .arg(format!("versions/{version}"))
-                    .status()
-                else {
-                    panic!("{}", cformat!("<r,bold>Failed to run git command</>"));
-                };
-
-                if !status.success() {
-                    panic!(
-                        "{}",
-                        cformat!("<r,bold>git clone of branch versions/{version} failed</>")
-                    );
-                }
-            }
-        }
+    let exe_path = compile_directory(worktree.clone());
+    if fs::create_dir_all(binary.parent().unwrap()).is_err()
+        || fs::copy(&exe_path, &binary).is_err()
+    {
+        panic!(
+            "{}",
+            cformat!(
+                "<r,bold>Failed to copy {} to the binary cache</>",
+                exe_path.display()
+            )
+        );
+    }
 
-        VERSIONS_FETCHED.lock().unwrap().insert(version.clone());
+    // The binary is cached, so the worktree and its build artifacts aren't needed again
+    let removed = Command::new("git")
+        .current_dir(repo)
+        .args(["worktree", "remove", "--force"])
+        .arg(&worktree)
+        .status()
+        .is_ok_and(|s| s.success());
+    if !removed {
+        cprintln!(
+            "<y,bold>Failed to remove worktree {}</>",
+            worktree.display()
+        );
     }
 
+    binary
+}
+
+/// Build a version of the bot from the local repository, fetching it first if it's missing.
+pub fn resolve_remote_bot(version: String) -> ChessBot {
+    let repo = Path::new(".");
+    let commit = resolve_commit(repo, &version)
+        .or_else(|| {
+            fetch_version(repo, &version)
+                .then(|| resolve_commit(repo, &version))
+                .flatten()
+        })
+        .unwrap_or_else(|| {
+            panic!(
+                "{}",
+                cformat!("<r,bold>{version} is not a version, branch, tag or commit</>")
+            )
+        });
+    cprintln!("<c,bold>Resolved {version} to {commit}</>");
+
     ChessBot {
         name: format!("Beans Gambit [{version}]"),
-        path: compile_directory(
-            PathBuf::from_str(".")
-                .unwrap()
-                .join("versions")
-                .join(version),
-        )
-        .display()
-        .to_string(),
+        path: build_commit(repo, &commit)
+            .canonicalize()
+            .unwrap()
+            .display()
+            .to_string(),
+        args: Vec::new(),
+        dir: None,
+        options: Vec::new(),
     }
 }
 
-pub fn resolve_bot(bot: Either<LocalBot, String>) -> ChessBot {
+/// An engine of the registry file, once it has completed a UCI handshake.
+pub fn resolve_registered_bot(name: String) -> ChessBot {
+    let bot = match Registry::load(ENGINES_FILE).and_then(|registry| registry.get(&name)?.to_bot())
+    {
+        Ok(bot) => bot,
+        Err(e) => panic!("{}", cformat!("<r,bold>Failed to resolve {name}: {e}</>")),
+    };
+
+    cprint!("<yellow,bold>Checking {name}...</>");
+    if let Err(e) = check_engine(&bot) {
+        panic!(
+            "{}",
+            cformat!("<r,bold>{name} failed the UCI handshake: {e}</>")
+        );
+    }
+    cprintln!("\r<green,bold>{name} is ready!</>    ");
+
+    bot
+}
+
+pub fn resolve_bot(bot: BotConfig) -> ChessBot {
     match bot {
-        Either::Left(local_bot) => resolve_local_bot(local_bot),
-        Either::Right(version) => resolve_remote_bot(version),
+        BotConfig::Local(local_bot) => resolve_local_bot(local_bot),
+        BotConfig::Version(version) => resolve_remote_bot(version),
+        BotConfig::StockfishElo(elo) => get_limited_stockfish(elo),
+        BotConfig::Registered(name) => resolve_registered_bot(name),
+        BotConfig::External { name, path } => ChessBot {
+            name,
+            path,
+            args: Vec::new(),
+            dir: None,
+            options: Vec::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(repo: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(repo)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// A repository with two commits, the first on branch `versions/1.0.0` and tagged `v1`.
+    fn temp_repo(name: &str) -> (PathBuf, String, String) {
+        let repo = std::env::temp_dir().join(format!("chess_runner_resolver_{name}"));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(&repo).unwrap();
+
+        git(&repo, &["init", "-q"]);
+        fs::write(repo.join("file"), "1").unwrap();
+        git(&repo, &["add", "file"]);
+        git(&repo, &["commit", "-qm", "first"]);
+        let first = git(&repo, &["rev-parse", "HEAD"]);
+        git(&repo, &["branch", "versions/1.0.0"]);
+        git(&repo, &["tag", "-a", "v1", "-m", "v1"]);
+
+        fs::write(repo.join("file"), "2").unwrap();
+        git(&repo, &["commit", "-qam", "second"]);
+        let second = git(&repo, &["rev-parse", "HEAD"]);
+
+        (repo, first, second)
+    }
+
+    #[test]
+    fn test_resolve_commit() {
+        let (repo, first, second) = temp_repo("resolve");
+
+        assert_eq!(resolve_commit(&repo, "1.0.0"), Some(first.clone()));
+        assert_eq!(resolve_commit(&repo, "versions/1.0.0"), Some(first.clone()));
+        assert_eq!(resolve_commit(&repo, "v1"), Some(first.clone()));
+        assert_eq!(resolve_commit(&repo, &second[..8]), Some(second.clone()));
+        assert_eq!(resolve_commit(&repo, "HEAD~1"), Some(first));
+        assert_eq!(resolve_commit(&repo, "2.0.0"), None);
+        assert_eq!(local_versions(&repo), vec!["1.0.0"]);
+
+        fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn test_build_commit_cached() {
+        let (repo, first, _) = temp_repo("cache");
+
+        // A cached binary is used without creating a worktree or building
+        let binary = cached_binary(&repo, &first);
+        fs::create_dir_all(binary.parent().unwrap()).unwrap();
+        fs::write(&binary, "").unwrap();
+        assert_eq!(build_commit(&repo, &first), binary);
+        assert!(!repo.join("versions").join("worktrees").exists());
+
+        fs::remove_dir_all(&repo).unwrap();
     }
 }
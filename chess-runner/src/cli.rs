@@ -0,0 +1,410 @@
+//! Command line interface, so matches can be run without a TTY (from CI or over SSH). Every option
+//! of the interactive menu can be given as flags. With no subcommand, the menu is shown instead.
+
+use crate::config::{BotConfig, ConfigError, MatchConfig};
+use crate::setup::{
+    BotVsBotOptions, ChessOptions, LocalBot, MatchSettings, MatchType, PerformanceOptions,
+};
+use crate::sprt::SprtOptions;
+use crate::stockfish_ladder::LadderOptions;
+use chess_lib::board::Board;
+use clap_derive::{Args, Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Parser)]
+#[command(version, about, long_about = Some("Builds and runs chess bots against each other."))]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<RunnerCommand>,
+}
+
+/// Bots are `local` (this checkout), `stockfish`, `stockfish:<elo>` (Stockfish limited to an Elo),
+/// `chess-coding-adventure`, `engine:<name>` (an engine of `engines.toml`), the path to a UCI
+/// engine, or a version, branch, tag or commit, e.g. `v0.4.0`.
+#[derive(Subcommand)]
+pub enum RunnerCommand {
+    /// Run the match saved in a config file (.toml or .json).
+    Run { config: PathBuf },
+    /// Play a match between two bots.
+    BotVsBot {
+        #[arg(value_parser = parse_bot)]
+        bot1: BotConfig,
+        #[arg(value_parser = parse_bot)]
+        bot2: BotConfig,
+        #[command(flatten)]
+        options: GamesArgs,
+    },
+    /// Play a tournament in which every bot plays `--games` games against every other bot.
+    RoundRobin {
+        #[arg(value_parser = parse_bot, num_args = 2.., required = true)]
+        bots: Vec<BotConfig>,
+        #[command(flatten)]
+        options: GamesArgs,
+    },
+    /// Play a tournament in which the first bot plays `--games` games against every other bot.
+    Gauntlet {
+        #[arg(value_parser = parse_bot)]
+        bot: BotConfig,
+        #[arg(value_parser = parse_bot, num_args = 1.., required = true)]
+        opponents: Vec<BotConfig>,
+        #[command(flatten)]
+        options: GamesArgs,
+    },
+    /// Test whether the first bot is stronger than the second with an SPRT.
+    Sprt {
+        #[arg(value_parser = parse_bot)]
+        bot: BotConfig,
+        #[arg(value_parser = parse_bot)]
+        baseline: BotConfig,
+        /// Elo difference of H0.
+        #[arg(long, default_value_t = SprtOptions::default().elo0, allow_negative_numbers = true)]
+        elo0: f64,
+        /// Elo difference of H1.
+        #[arg(long, default_value_t = SprtOptions::default().elo1, allow_negative_numbers = true)]
+        elo1: f64,
+        #[arg(long, default_value_t = SprtOptions::default().alpha)]
+        alpha: f64,
+        #[arg(long, default_value_t = SprtOptions::default().beta)]
+        beta: f64,
+        #[command(flatten)]
+        settings: MatchSettings,
+    },
+    /// Estimate a bot's Elo from game pairs against Stockfish at several strengths.
+    Ladder {
+        #[arg(value_parser = parse_bot)]
+        bot: BotConfig,
+        /// Stockfish Elo of the first games.
+        #[arg(long, default_value_t = LadderOptions::default().start_elo)]
+        start_elo: u32,
+        /// Game pairs played at each Elo.
+        #[arg(long, default_value_t = LadderOptions::default().pairs)]
+        pairs: usize,
+        /// Most Elo levels played.
+        #[arg(long, default_value_t = LadderOptions::default().levels)]
+        levels: usize,
+        /// Stop once the 95% confidence interval is this narrow either side.
+        #[arg(long, default_value_t = LadderOptions::default().max_error)]
+        max_error: f64,
+        #[command(flatten)]
+        settings: MatchSettings,
+    },
+    /// Check UCI compliance with fastchess.
+    Compliance {
+        #[arg(value_parser = parse_bot)]
+        bot: BotConfig,
+    },
+    /// Profile a single search with flamegraph.
+    Performance {
+        #[arg(value_parser = parse_bot)]
+        bot: BotConfig,
+        /// Position to search.
+        #[arg(long, value_parser = parse_fen, default_value_t = PerformanceOptions::default().fen)]
+        fen: String,
+    },
+    /// Build a bot and print its path.
+    Build {
+        #[arg(value_parser = parse_bot)]
+        bot: BotConfig,
+        /// Run the bot after building it.
+        #[arg(long)]
+        run: bool,
+    },
+}
+
+/// Options of the games between each pair of bots.
+#[derive(Args)]
+pub struct GamesArgs {
+    #[arg(long, default_value_t = BotVsBotOptions::default().games)]
+    games: usize,
+    #[command(flatten)]
+    settings: MatchSettings,
+}
+
+impl From<GamesArgs> for BotVsBotOptions {
+    fn from(args: GamesArgs) -> Self {
+        BotVsBotOptions {
+            games: args.games,
+            settings: args
+                .settings
+                .or_defaults(BotVsBotOptions::default().settings),
+        }
+    }
+}
+
+fn parse_bot(s: &str) -> Result<BotConfig, String> {
+    if let Some(elo) = s.to_ascii_lowercase().strip_prefix("stockfish:") {
+        return elo
+            .parse()
+            .map(BotConfig::StockfishElo)
+            .map_err(|_| format!("'{elo}' is not an Elo"));
+    }
+
+    if let Some(name) = s.strip_prefix("engine:") {
+        return Ok(BotConfig::Registered(name.to_string()));
+    }
+
+    let local = match s.to_ascii_lowercase().as_str() {
+        "local" | "beans-gambit" => Some(LocalBot::BeansGambitLocal),
+        "stockfish" => Some(LocalBot::Stockfish),
+        "chess-coding-adventure" => Some(LocalBot::ChessCodingAdventure),
+        _ => LocalBot::from_str(s).ok(),
+    };
+
+    let path = Path::new(s);
+    match local {
+        Some(bot) => Ok(BotConfig::Local(bot)),
+        None if path.is_file() => Ok(BotConfig::External {
+            name: path.file_stem().unwrap().to_string_lossy().to_string(),
+            path: s.to_string(),
+        }),
+        None if s.is_empty() || s.contains(char::is_whitespace) => {
+            Err(format!("'{s}' is not a bot, engine path or version name"))
+        }
+        None => Ok(BotConfig::Version(s.to_string())),
+    }
+}
+
+fn parse_fen(s: &str) -> Result<String, String> {
+    Board::from_fen(s)
+        .map(|_| s.to_string())
+        .map_err(|e| e.to_string())
+}
+
+impl RunnerCommand {
+    pub fn into_options(self) -> Result<(ChessOptions, Vec<BotConfig>), ConfigError> {
+        let (setup, bots) = match self {
+            RunnerCommand::Run { config } => return MatchConfig::load(config)?.into_options(),
+            RunnerCommand::BotVsBot {
+                bot1,
+                bot2,
+                options,
+            } => (MatchType::BotVsBot(options.into()), vec![bot1, bot2]),
+            RunnerCommand::RoundRobin { bots, options } => {
+                (MatchType::RoundRobin(options.into()), bots)
+            }
+            RunnerCommand::Gauntlet {
+                bot,
+                opponents,
+                options,
+            } => (
+                MatchType::Gauntlet(options.into()),
+                std::iter::once(bot).chain(opponents).collect(),
+            ),
+            RunnerCommand::Sprt {
+                bot,
+                baseline,
+                elo0,
+                elo1,
+                alpha,
+                beta,
+                settings,
+            } => {
+                let options = SprtOptions {
+                    elo0,
+                    elo1,
+                    alpha,
+                    beta,
+                    settings: settings.or_defaults(SprtOptions::default().settings),
+                };
+                if let Some(reason) = options.invalid_reason() {
+                    return Err(ConfigError::InvalidSprt(reason));
+                }
+                (MatchType::Sprt(options), vec![bot, baseline])
+            }
+            RunnerCommand::Ladder {
+                bot,
+                start_elo,
+                pairs,
+                levels,
+                max_error,
+                settings,
+            } => (
+                MatchType::StockfishLadder(LadderOptions {
+                    start_elo,
+                    pairs,
+                    levels,
+                    max_error,
+                    settings: settings.or_defaults(LadderOptions::default().settings),
+                }),
+                vec![bot],
+            ),
+            RunnerCommand::Compliance { bot } => (MatchType::Compliance, vec![bot]),
+            RunnerCommand::Performance { bot, fen } => (
+                MatchType::Performance(PerformanceOptions { fen }),
+                vec![bot],
+            ),
+            RunnerCommand::Build { bot, run: false } => (MatchType::BuildOnly, vec![bot]),
+            RunnerCommand::Build { bot, run: true } => (MatchType::BuildAndRun, vec![bot]),
+        };
+
+        Ok((ChessOptions::new(setup), bots))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::OpeningOrder;
+    use clap::Parser;
+
+    fn parse(args: &[&str]) -> Result<RunnerCommand, clap::Error> {
+        Cli::try_parse_from(std::iter::once("chess-runner").chain(args.iter().copied()))
+            .map(|cli| cli.command.unwrap())
+    }
+
+    #[test]
+    fn test_no_subcommand() {
+        assert!(
+            Cli::try_parse_from(["chess-runner"])
+                .unwrap()
+                .command
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let (options, bots) = parse(&["bot-vs-bot", "local", "v0.4.0", "--games", "12"])
+            .unwrap()
+            .into_options()
+            .unwrap();
+        assert!(matches!(
+            options.setup(),
+            MatchType::BotVsBot(BotVsBotOptions {
+                games: 12,
+                settings: MatchSettings {
+                    opening_order: OpeningOrder::Random,
+                    own_book: true,
+                    ..
+                },
+            })
+        ));
+        assert_eq!(
+            bots,
+            vec![
+                BotConfig::Local(LocalBot::BeansGambitLocal),
+                BotConfig::Version("v0.4.0".to_string())
+            ]
+        );
+
+        let (options, _) = parse(&[
+            "sprt",
+            "local",
+            "v0.4.0",
+            "--elo0",
+            "-2",
+            "--elo1",
+            "3",
+            "--opening-order",
+            "sequential",
+            "--no-own-book",
+        ])
+        .unwrap()
+        .into_options()
+        .unwrap();
+        let MatchType::Sprt(sprt) = options.setup() else {
+            panic!("expected an SPRT");
+        };
+        assert_eq!((sprt.elo0, sprt.elo1), (-2.0, 3.0));
+        assert_eq!(sprt.settings.opening_order, OpeningOrder::Sequential);
+        assert!(!sprt.settings.own_book);
+        // Settings left unset are the match type's own
+        assert_eq!(
+            sprt.settings.time_control(),
+            SprtOptions::default().settings.time_control()
+        );
+        assert_eq!(sprt.settings.pgn_out(), "sprt.pgn");
+
+        let (options, bots) = parse(&[
+            "gauntlet",
+            "local",
+            "v0.4.0",
+            "stockfish:1500",
+            "engine:Stockfish 2000",
+            "--games",
+            "4",
+        ])
+        .unwrap()
+        .into_options()
+        .unwrap();
+        assert!(matches!(
+            options.setup(),
+            MatchType::Gauntlet(BotVsBotOptions { games: 4, .. })
+        ));
+        assert_eq!(
+            bots,
+            vec![
+                BotConfig::Local(LocalBot::BeansGambitLocal),
+                BotConfig::Version("v0.4.0".to_string()),
+                BotConfig::StockfishElo(1500),
+                BotConfig::Registered("Stockfish 2000".to_string())
+            ]
+        );
+
+        let (options, bots) = parse(&["round-robin", "local", "v0.4.0", "v0.5.0"])
+            .unwrap()
+            .into_options()
+            .unwrap();
+        assert!(matches!(options.setup(), MatchType::RoundRobin(_)));
+        assert_eq!(bots.len(), 3);
+
+        let (options, _) = parse(&[
+            "ladder",
+            "local",
+            "--start-elo",
+            "2000",
+            "--pairs",
+            "2",
+            "--tc",
+            "5+0.05",
+        ])
+        .unwrap()
+        .into_options()
+        .unwrap();
+        let MatchType::StockfishLadder(ladder) = options.setup() else {
+            panic!("expected a Stockfish ladder");
+        };
+        assert_eq!((ladder.start_elo, ladder.pairs), (2000, 2));
+        assert_eq!(ladder.settings.time_control(), "5+0.05");
+        assert_eq!(ladder.settings.pgn_out(), "ladder.pgn");
+
+        let (options, _) = parse(&["build", "stockfish", "--run"])
+            .unwrap()
+            .into_options()
+            .unwrap();
+        assert!(matches!(options.setup(), MatchType::BuildAndRun));
+
+        let (options, _) = parse(&["performance", "local"])
+            .unwrap()
+            .into_options()
+            .unwrap();
+        assert!(matches!(
+            options.setup(),
+            MatchType::Performance(PerformanceOptions { fen }) if *fen == PerformanceOptions::default().fen
+        ));
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(parse(&["ladder"]).is_err());
+        assert!(parse(&["performance", "local", "--fen", "not a fen"]).is_err());
+        assert!(parse(&["bot-vs-bot", "local", "local", "--games", "x"]).is_err());
+        assert!(parse(&["round-robin", "local"]).is_err());
+        assert!(parse(&["gauntlet", "local"]).is_err());
+        assert!(parse(&["bot-vs-bot", "local", "stockfish:strong"]).is_err());
+
+        for args in [
+            ["--elo0", "5", "--elo1", "5"],
+            ["--elo0", "10", "--elo1", "0"],
+            ["--alpha", "0", "--beta", "0.05"],
+            ["--alpha", "0.05", "--beta", "1.5"],
+        ] {
+            let command = parse(&[&["sprt", "local", "v0.4.0"], &args[..]].concat()).unwrap();
+            assert!(matches!(
+                command.into_options(),
+                Err(ConfigError::InvalidSprt(_))
+            ));
+        }
+    }
+}
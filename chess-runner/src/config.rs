@@ -0,0 +1,471 @@
+//! Match configurations saved to TOML or JSON files, so recurring experiments can be rerun exactly.
+//! Every field except `bots` has a default, e.g.
+//!
+//! ```toml
+//! match_type = "BotVsBot"
+//! arbiter = "native"
+//! time_control = "10+0.1"
+//! games = 200
+//! concurrency = 4
+//! openings = "openings/8moves.epd"
+//! opening_order = "sequential"
+//! own_book = false
+//! pgn_out = "results/v0.4.0.pgn"
+//! elo0 = 0.0
+//! elo1 = 5.0
+//! bots = [
+//!     { local = "BeansGambitLocal" },
+//!     { version = "v0.4.0" },
+//!     { stockfish_elo = 1500 },
+//!     { registered = "Stockfish 2000" },
+//!     { external = { name = "Other", path = "/usr/bin/other-engine" } },
+//! ]
+//! ```
+
+use crate::setup::{
+    BotVsBotOptions, ChessOptions, LocalBot, MatchSettings, MatchType, PerformanceOptions,
+    SimpleMatchType,
+};
+use crate::sprt::SprtOptions;
+use crate::stockfish_ladder::LadderOptions;
+use chess_lib::board::{Board, FenError};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BotConfig {
+    Local(LocalBot),
+    /// A branch `versions/<version>`, or any branch, tag or commit of the local repository.
+    Version(String),
+    /// Stockfish limited to an Elo with `UCI_LimitStrength`.
+    StockfishElo(u32),
+    /// An engine of the registry file, by name.
+    Registered(String),
+    /// Any UCI engine, run as is.
+    External {
+        name: String,
+        path: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    /// The file isn't valid TOML or JSON, or doesn't describe a match.
+    Parse(String),
+    /// The extension is neither `.toml` nor `.json`.
+    UnknownFormat(PathBuf),
+    /// The match type needs a different number of bots.
+    BotCount {
+        expected: RangeInclusive<usize>,
+        found: usize,
+    },
+    InvalidFen(FenError),
+    /// The SPRT's Elo bounds or error probabilities can't give a verdict.
+    InvalidSprt(&'static str),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{e}"),
+            ConfigError::Parse(message) => write!(f, "{message}"),
+            ConfigError::UnknownFormat(path) => {
+                write!(f, "{} is neither a .toml nor a .json file", path.display())
+            }
+            ConfigError::BotCount { expected, found } if expected.start() == expected.end() => {
+                write!(f, "expected {} bots, found {found}", expected.start())
+            }
+            ConfigError::BotCount { expected, found } => {
+                write!(
+                    f,
+                    "expected at least {} bots, found {found}",
+                    expected.start()
+                )
+            }
+            ConfigError::InvalidFen(e) => write!(f, "invalid fen - {e}"),
+            ConfigError::InvalidSprt(reason) => write!(f, "invalid SPRT - {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    fn from_path(path: &Path) -> Result<ConfigFormat, ConfigError> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("toml") => Ok(ConfigFormat::Toml),
+            Some(e) if e.eq_ignore_ascii_case("json") => Ok(ConfigFormat::Json),
+            _ => Err(ConfigError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchConfig {
+    pub match_type: SimpleMatchType,
+    #[serde(flatten)]
+    pub settings: MatchSettings,
+    /// Games of a match, or of each pairing of a tournament.
+    pub games: usize,
+    /// SPRT Elo bounds and error probabilities.
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    /// Stockfish ladder level of the first games, game pairs per level, most levels, and the
+    /// error at which it stops.
+    pub start_elo: u32,
+    pub pairs: usize,
+    pub levels: usize,
+    pub max_error: f64,
+    /// Position searched by the performance test.
+    pub fen: String,
+    pub bots: Vec<BotConfig>,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        let sprt = SprtOptions::default();
+        let ladder = LadderOptions::default();
+        MatchConfig {
+            match_type: SimpleMatchType::default(),
+            settings: MatchSettings::default(),
+            games: BotVsBotOptions::default().games,
+            elo0: sprt.elo0,
+            elo1: sprt.elo1,
+            alpha: sprt.alpha,
+            beta: sprt.beta,
+            start_elo: ladder.start_elo,
+            pairs: ladder.pairs,
+            levels: ladder.levels,
+            max_error: ladder.max_error,
+            fen: PerformanceOptions::default().fen,
+            bots: Vec::new(),
+        }
+    }
+}
+
+impl MatchConfig {
+    /// The configuration of a match that has been set up. Options the match type doesn't use
+    /// keep their defaults.
+    pub fn new(options: &ChessOptions, bots: Vec<BotConfig>) -> MatchConfig {
+        let mut config = MatchConfig {
+            match_type: options.setup().simple(),
+            bots,
+            ..MatchConfig::default()
+        };
+
+        match options.setup() {
+            MatchType::BotVsBot(options)
+            | MatchType::RoundRobin(options)
+            | MatchType::Gauntlet(options) => {
+                config.games = options.games;
+                config.settings = options.settings.clone();
+            }
+            MatchType::Sprt(options) => {
+                config.elo0 = options.elo0;
+                config.elo1 = options.elo1;
+                config.alpha = options.alpha;
+                config.beta = options.beta;
+                config.settings = options.settings.clone();
+            }
+            MatchType::StockfishLadder(options) => {
+                config.start_elo = options.start_elo;
+                config.pairs = options.pairs;
+                config.levels = options.levels;
+                config.max_error = options.max_error;
+                config.settings = options.settings.clone();
+            }
+            MatchType::Performance(options) => config.fen = options.fen.clone(),
+            _ => {}
+        }
+
+        config
+    }
+
+    /// The format is chosen by the file's extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<MatchConfig, ConfigError> {
+        let path = path.as_ref();
+        let format = ConfigFormat::from_path(path)?;
+        let text = std::fs::read_to_string(path)?;
+
+        match format {
+            ConfigFormat::Toml => {
+                toml::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))
+            }
+            ConfigFormat::Json => {
+                serde_json::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))
+            }
+        }
+    }
+
+    /// The format is chosen by the file's extension.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        let text = match ConfigFormat::from_path(path)? {
+            ConfigFormat::Toml => {
+                toml::to_string_pretty(self).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+            ConfigFormat::Json => {
+                serde_json::to_string_pretty(self).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+        };
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    fn bot_vs_bot_options(&self) -> BotVsBotOptions {
+        BotVsBotOptions {
+            games: self.games,
+            settings: self
+                .settings
+                .clone()
+                .or_defaults(BotVsBotOptions::default().settings),
+        }
+    }
+
+    pub fn into_options(self) -> Result<(ChessOptions, Vec<BotConfig>), ConfigError> {
+        let expected = self.match_type.bots_required();
+        if !expected.contains(&self.bots.len()) {
+            return Err(ConfigError::BotCount {
+                expected,
+                found: self.bots.len(),
+            });
+        }
+
+        let setup = match self.match_type {
+            SimpleMatchType::BuildAndRunCutechess => MatchType::BuildAndRunCutechess,
+            SimpleMatchType::BotVsBot => MatchType::BotVsBot(self.bot_vs_bot_options()),
+            SimpleMatchType::RoundRobin => MatchType::RoundRobin(self.bot_vs_bot_options()),
+            SimpleMatchType::Gauntlet => MatchType::Gauntlet(self.bot_vs_bot_options()),
+            SimpleMatchType::Sprt => {
+                let options = SprtOptions {
+                    elo0: self.elo0,
+                    elo1: self.elo1,
+                    alpha: self.alpha,
+                    beta: self.beta,
+                    settings: self.settings.or_defaults(SprtOptions::default().settings),
+                };
+                if let Some(reason) = options.invalid_reason() {
+                    return Err(ConfigError::InvalidSprt(reason));
+                }
+                MatchType::Sprt(options)
+            }
+            SimpleMatchType::StockfishLadder => MatchType::StockfishLadder(LadderOptions {
+                start_elo: self.start_elo,
+                pairs: self.pairs,
+                levels: self.levels,
+                max_error: self.max_error,
+                settings: self.settings.or_defaults(LadderOptions::default().settings),
+            }),
+            SimpleMatchType::Compliance => MatchType::Compliance,
+            SimpleMatchType::Performance => {
+                Board::from_fen(&self.fen).map_err(ConfigError::InvalidFen)?;
+                MatchType::Performance(PerformanceOptions { fen: self.fen })
+            }
+            SimpleMatchType::BuildOnly => MatchType::BuildOnly,
+            SimpleMatchType::BuildAndRun => MatchType::BuildAndRun,
+        };
+
+        Ok((ChessOptions::new(setup), self.bots))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::OpeningOrder;
+
+    fn example() -> MatchConfig {
+        MatchConfig {
+            settings: MatchSettings {
+                time_control: Some("10+0.1".to_string()),
+                concurrency: 4,
+                openings: Some("openings/8moves.epd".to_string()),
+                opening_order: OpeningOrder::Sequential,
+                own_book: false,
+                pgn_out: Some("results/v0.4.0.pgn".to_string()),
+                ..MatchSettings::default()
+            },
+            games: 200,
+            bots: vec![
+                BotConfig::Local(LocalBot::BeansGambitLocal),
+                BotConfig::Version("v0.4.0".to_string()),
+            ],
+            ..MatchConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let config: MatchConfig = toml::from_str(
+            r#"
+            match_type = "BotVsBot"
+            games = 200
+            bots = [
+                { local = "Stockfish" },
+                { external = { name = "Other", path = "/usr/bin/other-engine" } },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.games, 200);
+        assert_eq!(config.settings.time_control, None);
+        assert_eq!(
+            config.bots,
+            vec![
+                BotConfig::Local(LocalBot::Stockfish),
+                BotConfig::External {
+                    name: "Other".to_string(),
+                    path: "/usr/bin/other-engine".to_string()
+                }
+            ]
+        );
+
+        assert!(toml::from_str::<MatchConfig>("gamez = 3").is_err());
+        assert!(serde_json::from_str::<MatchConfig>(r#"{"pgn_outt": "a.pgn"}"#).is_err());
+
+        // Settings shared by every match type are top level keys
+        let config: MatchConfig = toml::from_str("concurrency = 4\nown_book = false").unwrap();
+        assert_eq!(config.settings.concurrency, 4);
+        assert!(!config.settings.own_book);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir();
+        for name in ["chess_runner_config.toml", "chess_runner_config.json"] {
+            let path = dir.join(name);
+            example().save(&path).unwrap();
+            let loaded = MatchConfig::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded, example());
+        }
+
+        assert!(matches!(
+            example().save(dir.join("chess_runner_config.yaml")),
+            Err(ConfigError::UnknownFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_options_round_trip() {
+        let (options, bots) = example().into_options().unwrap();
+        assert_eq!(MatchConfig::new(&options, bots), example());
+
+        let config = MatchConfig {
+            bots: vec![BotConfig::Local(LocalBot::BeansGambitLocal)],
+            ..MatchConfig::default()
+        };
+        assert!(matches!(
+            config.into_options(),
+            Err(ConfigError::BotCount {
+                expected,
+                found: 1
+            }) if expected == (2..=2)
+        ));
+
+        let config = MatchConfig {
+            match_type: SimpleMatchType::RoundRobin,
+            bots: vec![
+                BotConfig::Local(LocalBot::BeansGambitLocal),
+                BotConfig::Version("v0.4.0".to_string()),
+                BotConfig::StockfishElo(1500),
+            ],
+            ..MatchConfig::default()
+        };
+        let (options, bots) = config.clone().into_options().unwrap();
+        assert!(matches!(options.setup(), MatchType::RoundRobin(_)));
+        assert_eq!(
+            MatchConfig::new(&options, bots),
+            MatchConfig {
+                settings: BotVsBotOptions::default().settings,
+                ..config
+            }
+        );
+
+        let config = MatchConfig {
+            match_type: SimpleMatchType::Performance,
+            fen: "not a fen".to_string(),
+            bots: vec![BotConfig::Local(LocalBot::BeansGambitLocal)],
+            ..MatchConfig::default()
+        };
+        assert!(matches!(
+            config.into_options(),
+            Err(ConfigError::InvalidFen(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_sprt() {
+        let sprt = |elo0, elo1, alpha, beta| MatchConfig {
+            match_type: SimpleMatchType::Sprt,
+            elo0,
+            elo1,
+            alpha,
+            beta,
+            bots: vec![BotConfig::Local(LocalBot::BeansGambitLocal); 2],
+            ..MatchConfig::default()
+        };
+
+        assert!(sprt(0.0, 10.0, 0.05, 0.05).into_options().is_ok());
+        assert!(sprt(-5.0, 0.0, 0.01, 0.2).into_options().is_ok());
+        for config in [
+            sprt(5.0, 5.0, 0.05, 0.05),
+            sprt(10.0, 0.0, 0.05, 0.05),
+            sprt(f64::NAN, 10.0, 0.05, 0.05),
+            sprt(0.0, 10.0, 0.0, 0.05),
+            sprt(0.0, 10.0, 0.05, 1.0),
+            sprt(0.0, 10.0, -0.5, 0.05),
+        ] {
+            assert!(matches!(
+                config.into_options(),
+                Err(ConfigError::InvalidSprt(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_match_type_defaults() {
+        let config = MatchConfig {
+            match_type: SimpleMatchType::Sprt,
+            bots: vec![BotConfig::Local(LocalBot::BeansGambitLocal); 2],
+            ..MatchConfig::default()
+        };
+        let (options, _) = config.into_options().unwrap();
+        let MatchType::Sprt(sprt) = options.setup() else {
+            panic!("expected an SPRT");
+        };
+        assert_eq!(sprt.settings, SprtOptions::default().settings);
+
+        let config = MatchConfig {
+            match_type: SimpleMatchType::StockfishLadder,
+            bots: vec![BotConfig::Local(LocalBot::BeansGambitLocal)],
+            ..MatchConfig::default()
+        };
+        let (options, _) = config.into_options().unwrap();
+        let MatchType::StockfishLadder(ladder) = options.setup() else {
+            panic!("expected a Stockfish ladder");
+        };
+        assert_eq!(ladder.settings, LadderOptions::default().settings);
+    }
+}
@@ -1,17 +1,32 @@
+mod arbiter;
 mod bot_resolver;
+mod cli;
+mod config;
+mod registry;
+mod results;
 mod run;
 pub mod setup;
+mod sprt;
 mod stockfish_ladder;
+mod tournament;
 pub mod util;
 
 use crate::bot_resolver::resolve_bot;
+use crate::cli::Cli;
 use crate::setup::select_options;
+use clap::Parser;
+use color_print::cformat;
 use itertools::Itertools;
 use run::run;
 use std::collections::HashSet;
 
 fn main() {
-    let (options, unresolved_bots) = select_options();
+    let (options, unresolved_bots) = match Cli::parse().command {
+        Some(command) => command
+            .into_options()
+            .unwrap_or_else(|e| panic!("{}", cformat!("<r,bold>Invalid match config: {}</>", e))),
+        None => select_options(),
+    };
 
     let mut bots = unresolved_bots.into_iter().map(resolve_bot).collect_vec();
 
@@ -62,7 +77,10 @@ mod tests {
             resolve_local_bot(LocalBot::BeansGambitLocal),
             resolve_local_bot(LocalBot::Stockfish),
         ];
-        let options = ChessOptions::new(MatchType::BotVsBot(BotVsBotOptions { games: 1 }));
+        let options = ChessOptions::new(MatchType::BotVsBot(BotVsBotOptions {
+            games: 1,
+            ..BotVsBotOptions::default()
+        }));
         run(options, bots);
     }
 }
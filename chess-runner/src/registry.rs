@@ -0,0 +1,220 @@
+//! Registry of external UCI engines, declared in `engines.toml` in the working directory, e.g.
+//!
+//! ```toml
+//! [[engine]]
+//! name = "Stockfish 2000"
+//! command = "stockfish"
+//! options = { UCI_LimitStrength = true, UCI_Elo = 2000, Threads = 1 }
+//!
+//! [[engine]]
+//! name = "Lc0"
+//! command = "/opt/lc0/lc0"
+//! args = ["--backend=eigen"]
+//! dir = "/opt/lc0"
+//! ```
+//!
+//! Commands are looked up on the `PATH`, or relative to the runner's working directory.
+
+use crate::setup::ChessBot;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::Path;
+
+pub const ENGINES_FILE: &str = "engines.toml";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisteredEngine {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Working directory of the engine, the runner's if missing.
+    pub dir: Option<String>,
+    /// UCI options set whenever the engine is started.
+    #[serde(default)]
+    pub options: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Registry {
+    #[serde(default, rename = "engine")]
+    pub engines: Vec<RegisteredEngine>,
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(io::Error),
+    /// The file isn't valid TOML, or doesn't describe engines.
+    Parse(String),
+    /// Two engines share a name.
+    DuplicateName(String),
+    /// No engine has the name.
+    NotFound(String),
+    /// The command is neither on the `PATH` nor an executable file.
+    CommandNotFound(String),
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryError::Io(e) => write!(f, "{e}"),
+            RegistryError::Parse(message) => write!(f, "{message}"),
+            RegistryError::DuplicateName(name) => {
+                write!(f, "more than one engine is named '{name}'")
+            }
+            RegistryError::NotFound(name) => {
+                write!(f, "no engine named '{name}' in {ENGINES_FILE}")
+            }
+            RegistryError::CommandNotFound(command) => {
+                write!(f, "engine command '{command}' not found")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<io::Error> for RegistryError {
+    fn from(e: io::Error) -> Self {
+        RegistryError::Io(e)
+    }
+}
+
+impl RegisteredEngine {
+    pub fn to_bot(&self) -> Result<ChessBot, RegistryError> {
+        let path = which::which(&self.command)
+            .map_err(|_| RegistryError::CommandNotFound(self.command.clone()))?;
+
+        Ok(ChessBot {
+            name: self.name.clone(),
+            path: path.display().to_string(),
+            args: self.args.clone(),
+            dir: self.dir.clone(),
+            options: self
+                .options
+                .iter()
+                .map(|(name, value)| {
+                    // Strings are sent without their quotes
+                    let value = match value {
+                        toml::Value::String(s) => s.clone(),
+                        value => value.to_string(),
+                    };
+                    (name.clone(), value)
+                })
+                .collect(),
+        })
+    }
+}
+
+impl Registry {
+    pub fn parse(text: &str) -> Result<Registry, RegistryError> {
+        let registry: Registry =
+            toml::from_str(text).map_err(|e| RegistryError::Parse(e.to_string()))?;
+
+        let mut names = HashSet::new();
+        if let Some(engine) = registry
+            .engines
+            .iter()
+            .find(|engine| !names.insert(&engine.name))
+        {
+            return Err(RegistryError::DuplicateName(engine.name.clone()));
+        }
+
+        Ok(registry)
+    }
+
+    /// A missing file is an empty registry.
+    pub fn load(path: impl AsRef<Path>) -> Result<Registry, RegistryError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Registry::default());
+        }
+        Registry::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&RegisteredEngine, RegistryError> {
+        self.engines
+            .iter()
+            .find(|engine| engine.name == name)
+            .ok_or_else(|| RegistryError::NotFound(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let registry = Registry::parse(
+            r#"
+            [[engine]]
+            name = "Stockfish 2000"
+            command = "stockfish"
+            options = { UCI_LimitStrength = true, UCI_Elo = 2000, SyzygyPath = "/tb" }
+
+            [[engine]]
+            name = "Other"
+            command = "/usr/bin/other-engine"
+            args = ["--uci", "-q"]
+            dir = "/tmp"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(registry.engines.len(), 2);
+        let other = registry.get("Other").unwrap();
+        assert_eq!(other.args, vec!["--uci", "-q"]);
+        assert_eq!(other.dir.as_deref(), Some("/tmp"));
+        assert!(registry.get("Stockfish 2000").unwrap().dir.is_none());
+        assert!(matches!(
+            registry.get("Missing"),
+            Err(RegistryError::NotFound(_))
+        ));
+
+        assert!(Registry::parse("").unwrap().engines.is_empty());
+        assert!(matches!(
+            Registry::parse("[[engine]]\nname = \"A\"\ncomand = \"a\""),
+            Err(RegistryError::Parse(_))
+        ));
+        assert!(matches!(
+            Registry::parse(
+                "[[engine]]\nname = \"A\"\ncommand = \"a\"\n[[engine]]\nname = \"A\"\ncommand = \"b\""
+            ),
+            Err(RegistryError::DuplicateName(name)) if name == "A"
+        ));
+    }
+
+    #[test]
+    fn test_to_bot() {
+        let mut engine = RegisteredEngine {
+            name: "Shell".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), "true".to_string()],
+            dir: None,
+            options: toml::from_str("Threads = 4\nPonder = false\nEvalFile = \"nn.bin\"").unwrap(),
+        };
+
+        let bot = engine.to_bot().unwrap();
+        assert!(Path::new(&bot.path).is_absolute());
+        assert_eq!(bot.args, engine.args);
+        assert_eq!(
+            bot.options,
+            vec![
+                ("EvalFile".to_string(), "nn.bin".to_string()),
+                ("Ponder".to_string(), "false".to_string()),
+                ("Threads".to_string(), "4".to_string()),
+            ]
+        );
+
+        engine.command = "no-such-engine-command".to_string();
+        assert!(matches!(
+            engine.to_bot(),
+            Err(RegistryError::CommandNotFound(_))
+        ));
+    }
+}
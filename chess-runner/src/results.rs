@@ -0,0 +1,486 @@
+//! Results of matches, read from the PGN fastchess writes. Each game becomes a `GameRecord` with
+//! the evals and times fastchess puts in move comments (`{+0.31/12 0.512s}`), and a
+//! `MatchSummary` aggregates the records of one player into W/D/L counts, an Elo difference and
+//! breakdowns by colour and opening.
+
+use chess_lib::board::Move;
+use chess_lib::pgn::{GameResult, PgnError, PgnGame, PgnReader};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::ops::AddAssign;
+use std::path::Path;
+
+/// z-score of the 95% confidence interval printed with Elo estimates.
+pub const Z_95: f64 = 1.959964;
+
+pub fn logistic_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// `None` for a score of 0 or 1, which no finite Elo difference gives.
+pub fn logistic_elo(score: f64) -> Option<f64> {
+    (score > 0.0 && score < 1.0).then(|| 400.0 * (score / (1.0 - score)).log10())
+}
+
+/// Bound on the ends of a confidence interval, which are infinite once they reach a score of 0
+/// or 1.
+const MAX_INTERVAL_ELO: f64 = 2000.0;
+
+/// Elo difference and the half-width of its 95% confidence interval, given the mean score per
+/// game and the variance of that mean. `None` if every game was won or every game was lost.
+pub fn elo_with_error(mean: f64, variance: f64) -> Option<(f64, f64)> {
+    let elo = logistic_elo(mean)?;
+    let error = Z_95 * variance.sqrt();
+    let bounded = |score: f64| {
+        logistic_elo(score).map_or(MAX_INTERVAL_ELO.copysign(score - 0.5), |elo| {
+            elo.clamp(-MAX_INTERVAL_ELO, MAX_INTERVAL_ELO)
+        })
+    };
+    Some((elo, (bounded(mean + error) - bounded(mean - error)) / 2.0))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eval {
+    Centipawns(i32),
+    /// Moves to mate, negative if the engine is being mated.
+    Mate(i32),
+}
+
+impl Display for Eval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Eval::Centipawns(cp) => write!(f, "{:+.2}", *cp as f64 / 100.0),
+            Eval::Mate(moves) if *moves < 0 => write!(f, "-M{}", -moves),
+            Eval::Mate(moves) => write!(f, "+M{moves}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveRecord {
+    pub mv: Move,
+    /// From the point of view of the side that played the move.
+    pub eval: Option<Eval>,
+    pub depth: Option<u32>,
+    /// Seconds spent on the move.
+    pub time: Option<f64>,
+    /// Played from fastchess's opening book rather than by the engine.
+    pub book: bool,
+}
+
+/// `eval/depth time` as written by fastchess and cutechess, e.g. `+0.31/12 0.512s` or
+/// `-M3/20 0.104s`, or `book`. fastchess adds the reason the game ended to the comment of the
+/// last move, after a comma, which is returned as well.
+fn parse_move_comment(mv: Move, comment: &str) -> (MoveRecord, Option<String>) {
+    let mut record = MoveRecord {
+        mv,
+        eval: None,
+        depth: None,
+        time: None,
+        book: false,
+    };
+
+    let (stats, termination) = match comment.split_once(',') {
+        Some((stats, termination)) => (stats, Some(termination.trim().to_string())),
+        None => (comment, None),
+    };
+
+    for token in stats.split_whitespace() {
+        if token == "book" {
+            record.book = true;
+        } else if let Some(seconds) = token.strip_suffix('s') {
+            record.time = seconds.parse().ok();
+        } else if let Some((eval, depth)) = token.split_once('/') {
+            record.depth = depth.parse().ok();
+            record.eval = if let Some(mate) = eval.strip_prefix("+M") {
+                mate.parse().ok().map(Eval::Mate)
+            } else if let Some(mate) = eval.strip_prefix("-M") {
+                mate.parse::<i32>().ok().map(|m| Eval::Mate(-m))
+            } else {
+                eval.parse::<f64>()
+                    .ok()
+                    .map(|pawns| Eval::Centipawns((pawns * 100.0).round() as i32))
+            };
+        }
+    }
+
+    (record, termination)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl Outcome {
+    /// From 0 to 1.
+    pub fn score(self) -> f64 {
+        match self {
+            Outcome::Win => 1.0,
+            Outcome::Draw => 0.5,
+            Outcome::Loss => 0.0,
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Win => write!(f, "Win"),
+            Outcome::Draw => write!(f, "Draw"),
+            Outcome::Loss => write!(f, "Loss"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    pub white: String,
+    pub black: String,
+    pub result: GameResult,
+    /// Why the game ended, e.g. "White mates" or "time forfeit".
+    pub termination: Option<String>,
+    /// ECO code and name, or the FEN the game started from.
+    pub opening: String,
+    pub moves: Vec<MoveRecord>,
+}
+
+impl GameRecord {
+    pub fn from_pgn(game: &PgnGame) -> GameRecord {
+        let mut termination = game.tag("Termination").map(str::to_string);
+        let moves = game
+            .moves
+            .iter()
+            .map(|m| {
+                let (record, comment_termination) = parse_move_comment(m.mv, &m.comments.join(" "));
+                // The comment is more specific than the tag, e.g. "White mates" over "normal"
+                termination = comment_termination.or(termination.take());
+                record
+            })
+            .collect();
+
+        let opening = match (game.tag("ECO"), game.tag("Opening"), game.tag("FEN")) {
+            (Some(eco), Some(name), _) => format!("{eco} {name}"),
+            (None, Some(name), _) => name.to_string(),
+            (Some(eco), None, _) => eco.to_string(),
+            (None, None, Some(fen)) => fen.to_string(),
+            (None, None, None) => "Starting position".to_string(),
+        };
+
+        GameRecord {
+            white: game.tag("White").unwrap_or("?").to_string(),
+            black: game.tag("Black").unwrap_or("?").to_string(),
+            result: game.result,
+            termination,
+            opening,
+            moves,
+        }
+    }
+
+    pub fn plies(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// `None` if `player` didn't play in the game or it has no result.
+    pub fn outcome(&self, player: &str) -> Option<Outcome> {
+        let is_white = if self.white == player {
+            true
+        } else if self.black == player {
+            false
+        } else {
+            return None;
+        };
+
+        match (self.result, is_white) {
+            (GameResult::WhiteWin, true) | (GameResult::BlackWin, false) => Some(Outcome::Win),
+            (GameResult::WhiteWin, false) | (GameResult::BlackWin, true) => Some(Outcome::Loss),
+            (GameResult::Draw, _) => Some(Outcome::Draw),
+            (GameResult::Unknown, _) => None,
+        }
+    }
+}
+
+pub fn read_records(pgn: &str) -> Result<Vec<GameRecord>, PgnError> {
+    PgnReader::new(pgn)
+        .map(|game| game.map(|game| GameRecord::from_pgn(&game)))
+        .collect()
+}
+
+/// A missing file has no games, as fastchess only creates it once the first game finishes.
+pub fn read_records_file(path: impl AsRef<Path>) -> io::Result<Vec<GameRecord>> {
+    match std::fs::read_to_string(path) {
+        Ok(pgn) => read_records(&pgn).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Wdl {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Wdl {
+    pub fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Draw => self.draws += 1,
+            Outcome::Loss => self.losses += 1,
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// A point per win and half a point per draw.
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+
+    /// Points per game, from 0 to 1.
+    pub fn score(&self) -> Option<f64> {
+        (self.games() > 0).then(|| self.points() / self.games() as f64)
+    }
+
+    /// Elo difference and the half-width of its 95% confidence interval, `None` without games or
+    /// if every game had the same decisive result.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let mean = self.score()?;
+        let n = self.games() as f64;
+        let variance = (self.wins as f64 * (1.0 - mean).powi(2)
+            + self.draws as f64 * (0.5 - mean).powi(2)
+            + self.losses as f64 * mean.powi(2))
+            / n;
+        elo_with_error(mean, variance / n)
+    }
+}
+
+impl AddAssign for Wdl {
+    fn add_assign(&mut self, other: Wdl) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+    }
+}
+
+impl Display for Wdl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)?;
+        if let Some(score) = self.score() {
+            write!(f, " ({:.1}%", score * 100.0)?;
+            if let Some((elo, error)) = self.elo() {
+                write!(f, ", Elo {elo:.1} ± {error:.1}")?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Results of every game one player took part in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchSummary {
+    pub player: String,
+    pub total: Wdl,
+    pub as_white: Wdl,
+    pub as_black: Wdl,
+    pub by_opening: BTreeMap<String, Wdl>,
+    pub by_termination: BTreeMap<String, u32>,
+    /// Mean game length in plies.
+    pub average_plies: f64,
+}
+
+impl MatchSummary {
+    pub fn new(records: &[GameRecord], player: &str) -> MatchSummary {
+        let mut summary = MatchSummary {
+            player: player.to_string(),
+            ..MatchSummary::default()
+        };
+
+        let mut plies = 0;
+        for record in records {
+            let Some(outcome) = record.outcome(player) else {
+                continue;
+            };
+
+            summary.total.add(outcome);
+            if record.white == player {
+                summary.as_white.add(outcome);
+            } else {
+                summary.as_black.add(outcome);
+            }
+            summary
+                .by_opening
+                .entry(record.opening.clone())
+                .or_default()
+                .add(outcome);
+            *summary
+                .by_termination
+                .entry(record.termination.clone().unwrap_or("unknown".to_string()))
+                .or_default() += 1;
+            plies += record.plies();
+        }
+
+        if summary.total.games() > 0 {
+            summary.average_plies = plies as f64 / summary.total.games() as f64;
+        }
+        summary
+    }
+}
+
+impl Display for MatchSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}: {}", self.player, self.total)?;
+        writeln!(f, "  As white: {}", self.as_white)?;
+        writeln!(f, "  As black: {}", self.as_black)?;
+        writeln!(f, "  Average length: {:.1} plies", self.average_plies)?;
+        writeln!(f, "  Terminations:")?;
+        for (termination, count) in &self.by_termination {
+            writeln!(f, "    {termination}: {count}")?;
+        }
+        writeln!(f, "  Openings:")?;
+        for (opening, wdl) in &self.by_opening {
+            writeln!(f, "    {opening}: {wdl}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: &str = r#"[Event "Fastchess Tournament"]
+[White "New"]
+[Black "Old"]
+[Result "1-0"]
+[ECO "C20"]
+[Opening "King's Pawn Game"]
+
+1. e4 {book} e5 {book} 2. Qh5 {+0.12/8 0.250s} Nc6 {-0.40/9 0.300s}
+3. Bc4 {+1.05/10 0.200s} Nf6 {-3.20/7 0.150s} 4. Qxf7# {+M1/1 0.010s, White mates} 1-0
+
+[Event "Fastchess Tournament"]
+[White "Old"]
+[Black "New"]
+[Result "1/2-1/2"]
+[ECO "C20"]
+[Opening "King's Pawn Game"]
+
+1. e4 {book} e5 {book} 2. Nf3 {+0.20/10 0.500s, Draw by adjudication} 1/2-1/2
+
+[Event "Fastchess Tournament"]
+[White "Old"]
+[Black "New"]
+[Result "1-0"]
+[Termination "time forfeit"]
+
+1. d4 {+0.10/10 1.000s} 1-0
+"#;
+
+    #[test]
+    fn test_read_records() {
+        let records = read_records(PGN).unwrap();
+        assert_eq!(records.len(), 3);
+
+        let game = &records[0];
+        assert_eq!(game.plies(), 7);
+        assert_eq!(game.opening, "C20 King's Pawn Game");
+        assert_eq!(game.termination.as_deref(), Some("White mates"));
+        assert!(game.moves[0].book);
+        assert_eq!(
+            game.moves[3],
+            MoveRecord {
+                mv: Move::from_uci("b8c6").unwrap(),
+                eval: Some(Eval::Centipawns(-40)),
+                depth: Some(9),
+                time: Some(0.3),
+                book: false,
+            }
+        );
+        assert_eq!(game.moves[6].eval, Some(Eval::Mate(1)));
+        assert_eq!(game.outcome("New"), Some(Outcome::Win));
+        assert_eq!(game.outcome("Old"), Some(Outcome::Loss));
+        assert_eq!(game.outcome("Other"), None);
+
+        assert_eq!(records[2].termination.as_deref(), Some("time forfeit"));
+        assert_eq!(records[2].opening, "Starting position");
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary = MatchSummary::new(&read_records(PGN).unwrap(), "New");
+        assert_eq!(
+            summary.total,
+            Wdl {
+                wins: 1,
+                draws: 1,
+                losses: 1
+            }
+        );
+        assert_eq!(summary.as_white.games(), 1);
+        assert_eq!(
+            summary.as_black,
+            Wdl {
+                wins: 0,
+                draws: 1,
+                losses: 1
+            }
+        );
+        assert_eq!(summary.by_opening["C20 King's Pawn Game"].games(), 2);
+        assert_eq!(summary.by_termination["Draw by adjudication"], 1);
+        assert!((summary.average_plies - 11.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_elo() {
+        assert_eq!(Wdl::default().elo(), None);
+
+        let (elo, error) = Wdl {
+            wins: 30,
+            draws: 40,
+            losses: 30,
+        }
+        .elo()
+        .unwrap();
+        assert_eq!(elo, 0.0);
+        assert!(error > 0.0);
+
+        // 75% is about 191 Elo
+        let (elo, _) = Wdl {
+            wins: 5,
+            draws: 5,
+            losses: 0,
+        }
+        .elo()
+        .unwrap();
+        assert!((elo - 190.85).abs() < 0.01);
+        assert!((logistic_score(elo) - 0.75).abs() < 1e-9);
+
+        // A perfect score has no finite Elo
+        let perfect = Wdl {
+            wins: 5,
+            draws: 0,
+            losses: 0,
+        };
+        assert_eq!(perfect.elo(), None);
+        assert_eq!(perfect.to_string(), "+5 =0 -0 (100.0%)");
+        assert_eq!(logistic_elo(0.0), None);
+
+        // Nor does the end of a confidence interval reaching it
+        let (elo, error) = Wdl {
+            wins: 9,
+            draws: 0,
+            losses: 1,
+        }
+        .elo()
+        .unwrap();
+        assert!(elo.is_finite() && error.is_finite());
+    }
+}
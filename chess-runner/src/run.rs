@@ -1,37 +1,123 @@
-use crate::setup::{BotVsBotOptions, ChessBot, ChessOptions, MatchType, PerformanceOptions};
+use crate::arbiter::play_match;
+use crate::results::{MatchSummary, read_records_file};
+use crate::setup::{
+    Arbiter, BotVsBotOptions, ChessBot, ChessOptions, MatchType, OpeningOrder, PerformanceOptions,
+};
+use crate::sprt::sprt;
 use crate::stockfish_ladder::stockfish_ladder;
+use crate::tournament::{gauntlet_pairings, round_robin_pairings, tournament};
 use crate::util::add_exe_on_windows;
 use color_print::{cformat, cprintln};
 use itertools::Itertools;
 use std::io::{BufRead, BufReader, Write};
+use std::ops::ControlFlow;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::str::FromStr;
 
+/// Add `bot` as a fastchess `-engine`, with its arguments, working directory and UCI options.
+pub fn add_engine(command: &mut Command, bot: &ChessBot) {
+    let dir = match &bot.dir {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from_str(".").unwrap(),
+    };
+    command
+        .arg("-engine")
+        .arg(format!("cmd={}", bot.path))
+        .arg(format!("name={}", bot.name))
+        .arg(format!("dir={}", dir.canonicalize().unwrap().display()));
+    if !bot.args.is_empty() {
+        command.arg(format!("args={}", bot.args.join(" ")));
+    }
+    for (name, value) in &bot.options {
+        command.arg(format!("option.{name}={value}"));
+    }
+}
+
+/// `bot`, sent `OwnBook=false` unless `own_book`, so results measure the search rather than
+/// the book.
+pub fn with_own_book(bot: &ChessBot, own_book: bool) -> ChessBot {
+    let mut bot = bot.clone();
+    if !own_book {
+        bot.options
+            .push(("OwnBook".to_string(), "false".to_string()));
+    }
+    bot
+}
+
+/// Start games from the positions of an EPD or PGN file, taken in `order`.
+pub fn add_openings(command: &mut Command, openings: &str, order: OpeningOrder) {
+    let format = if openings.to_ascii_lowercase().ends_with(".pgn") {
+        "pgn"
+    } else {
+        "epd"
+    };
+    command
+        .arg("-openings")
+        .arg(format!("file={openings}"))
+        .arg(format!("format={format}"))
+        .arg(format!("order={order}"));
+}
+
+/// Print `player`'s results in the games of `pgn_path` after the first `skip`, which fastchess
+/// appends to rather than overwriting.
+pub fn print_summary(pgn_path: &str, player: &str, skip: usize) {
+    match read_records_file(pgn_path) {
+        Ok(records) => print!(
+            "{}",
+            MatchSummary::new(&records[skip.min(records.len())..], player)
+        ),
+        Err(e) => cprintln!("<r,bold>Failed to read results from {}: {}</>", pgn_path, e),
+    }
+}
+
+/// Number of games already in `pgn_path`.
+pub fn count_games(pgn_path: &str) -> usize {
+    read_records_file(pgn_path).map_or(0, |records| records.len())
+}
+
 fn bot_vs_bot(bot1: ChessBot, bot2: ChessBot, options: &BotVsBotOptions) {
+    let previous_games = count_games(options.settings.pgn_out());
+    play_games(&bot1, &bot2, options);
+    print_summary(options.settings.pgn_out(), &bot1.name, previous_games);
+}
+
+/// Play the games of a match between two bots, appending them to the PGN.
+pub fn play_games(bot1: &ChessBot, bot2: &ChessBot, options: &BotVsBotOptions) {
+    let bot1 = &with_own_book(bot1, options.settings.own_book);
+    let bot2 = &with_own_book(bot2, options.settings.own_book);
+    match options.settings.arbiter {
+        Arbiter::Fastchess => fastchess_bot_vs_bot(bot1, bot2, options),
+        Arbiter::Native => play_match(
+            [bot1.clone(), bot2.clone()],
+            options.games.div_ceil(2) * 2,
+            &options.settings.arbiter_options(),
+            |_| ControlFlow::Continue(()),
+        ),
+    }
+}
+
+fn fastchess_bot_vs_bot(bot1: &ChessBot, bot2: &ChessBot, options: &BotVsBotOptions) {
     let mut command = Command::new("fastchess");
+    add_engine(&mut command, bot1);
+    add_engine(&mut command, bot2);
     let command = command
-        .arg("-engine")
-        .arg(format!("cmd={}", &bot1.path))
-        .arg(format!("name={}", &bot1.name))
-        .arg("-engine")
-        .arg(format!("cmd={}", &bot2.path))
-        .arg(format!("name={}", &bot2.name))
         .arg("-each")
-        .arg("tc=30+0")
+        .arg(format!("tc={}", options.settings.time_control()))
         .arg("proto=uci")
-        .arg(format!(
-            "dir={}",
-            PathBuf::from_str(".")
-                .unwrap()
-                .canonicalize()
-                .unwrap()
-                .display()
-        ))
+        .arg("-rounds")
+        .arg(options.games.div_ceil(2).to_string())
         .arg("-games")
-        .arg(options.games.to_string())
+        .arg("2")
+        .arg("-repeat")
+        .arg("-concurrency")
+        .arg(options.settings.concurrency.to_string())
         .arg("-pgnout")
-        .arg("file=game.pgn");
+        .arg(format!("file={}", options.settings.pgn_out()));
+
+    if let Some(openings) = &options.settings.openings {
+        add_openings(command, openings, options.settings.opening_order);
+    }
 
     cprintln!(
         "<c>Args: {}</>",
@@ -80,9 +166,17 @@ fn bot_vs_bot(bot1: ChessBot, bot2: ChessBot, options: &BotVsBotOptions) {
     }
 }
 
+/// fastchess's compliance check takes no UCI options, so only the bot's arguments and working
+/// directory are used.
 fn compliance(bot: ChessBot) {
     let mut command = Command::new("fastchess");
-    let command = command.arg("--compliance").arg(bot.path);
+    let command = command
+        .arg("--compliance")
+        .arg(absolute_path(&bot.path))
+        .args(&bot.args);
+    if let Some(dir) = &bot.dir {
+        command.current_dir(dir);
+    }
 
     cprintln!(
         "<c>Args: {}</>",
@@ -124,13 +218,25 @@ fn wait_for(reader: &mut BufReader<std::process::ChildStdout>, token: &str) {
     }
 }
 
+/// `path`, made absolute so it's still found from the bot's working directory.
+fn absolute_path(path: &str) -> String {
+    std::path::absolute(path).map_or(path.to_string(), |p| p.display().to_string())
+}
+
 fn performance(bot: ChessBot, options: &PerformanceOptions) {
+    let output = absolute_path("flamegraph.svg");
     let mut command = Command::new("flamegraph");
     let command = command
+        .arg("--output")
+        .arg(&output)
         .arg("--")
-        .arg(bot.path)
+        .arg(absolute_path(&bot.path))
+        .args(&bot.args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped());
+    if let Some(dir) = &bot.dir {
+        command.current_dir(dir);
+    }
 
     cprintln!(
         "<c>Flamegraph args: {}</>",
@@ -167,6 +273,10 @@ fn performance(bot: ChessBot, options: &PerformanceOptions) {
     stdin.flush().unwrap();
     wait_for(&mut reader, "uciok");
 
+    for (name, value) in &bot.options {
+        writeln!(stdin, "setoption name {name} value {value}").unwrap();
+    }
+
     print!("\r2: isready");
     writeln!(stdin, "isready").unwrap();
     stdin.flush().unwrap();
@@ -195,7 +305,7 @@ fn performance(bot: ChessBot, options: &PerformanceOptions) {
     let _ = child.wait();
 
     println!("Opening flamegraph...");
-    if let Err(e) = open::that("flamegraph.svg") {
+    if let Err(e) = open::that(&output) {
         cprintln!("<r,bold>Failed to open flamegraph: {e:?}</>")
     }
 }
@@ -220,12 +330,76 @@ pub fn run(options: ChessOptions, bots: Vec<ChessBot>) {
         MatchType::Compliance => compliance(bots[0].clone()),
         MatchType::Performance(options) => performance(bots[0].clone(), options),
         MatchType::BotVsBot(options) => bot_vs_bot(bots[0].clone(), bots[1].clone(), options),
-        MatchType::StockfishLadder => stockfish_ladder(bots[0].clone()),
+        MatchType::RoundRobin(options) => {
+            tournament(&bots, &round_robin_pairings(bots.len()), options)
+        }
+        MatchType::Gauntlet(options) => tournament(&bots, &gauntlet_pairings(bots.len()), options),
+        MatchType::Sprt(options) => sprt(bots[0].clone(), bots[1].clone(), options),
+        MatchType::StockfishLadder(options) => stockfish_ladder(bots[0].clone(), options),
         MatchType::BuildOnly => {
             println!("Bot {} at {}", &bots[0].name, &bots[0].path);
         }
         MatchType::BuildAndRun => {
-            Command::new(&bots[0].path).status().unwrap();
+            bots[0].command().status().unwrap();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|s| s.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_with_own_book() {
+        let bot = ChessBot {
+            name: "Bot".to_string(),
+            path: "bot".to_string(),
+            args: Vec::new(),
+            dir: None,
+            options: vec![("Threads".to_string(), "2".to_string())],
+        };
+
+        assert_eq!(with_own_book(&bot, true).options, bot.options);
+        assert_eq!(
+            with_own_book(&bot, false).options,
+            vec![
+                ("Threads".to_string(), "2".to_string()),
+                ("OwnBook".to_string(), "false".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_openings() {
+        let mut command = Command::new("fastchess");
+        add_openings(&mut command, "openings/8moves.epd", OpeningOrder::Random);
+        assert_eq!(
+            args(&command),
+            [
+                "-openings",
+                "file=openings/8moves.epd",
+                "format=epd",
+                "order=random"
+            ]
+        );
+
+        let mut command = Command::new("fastchess");
+        add_openings(&mut command, "openings/Games.PGN", OpeningOrder::Sequential);
+        assert_eq!(
+            args(&command),
+            [
+                "-openings",
+                "file=openings/Games.PGN",
+                "format=pgn",
+                "order=sequential"
+            ]
+        );
+    }
+}
@@ -1,26 +1,167 @@
+use crate::arbiter::ArbiterOptions;
+use crate::bot_resolver::{REMOTE_REPOSITORY, local_versions, resolve_commit};
+use crate::config::{BotConfig, MatchConfig};
+use crate::registry::{ENGINES_FILE, Registry};
+use crate::sprt::SprtOptions;
+use crate::stockfish_ladder::{LadderOptions, MAX_ELO, MIN_ELO};
 use crate::util::add_exe_on_windows;
 use chess_lib::board::Board;
+use clap::ArgAction;
+use clap_derive::{Args, ValueEnum};
 use color_print::cprintln;
 use derive_getters::Getters;
 use derive_new::new;
 use dialoguer::theme::ColorfulTheme;
-use dialoguer::{Input, Select};
-use either::Either;
+use dialoguer::{Confirm, Input, Select};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
 use std::process::Command;
 use std::str::FromStr;
 use std::sync::LazyLock;
-use strum::{EnumCount, IntoEnumIterator};
+use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumCount, EnumIter, EnumString};
 
+/// What plays the games of a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Arbiter {
+    Fastchess,
+    /// The built in arbiter, which only needs the engines themselves.
+    Native,
+}
+
+impl Default for Arbiter {
+    /// fastchess if it's installed.
+    fn default() -> Self {
+        if which::which(add_exe_on_windows("fastchess")).is_ok() {
+            Arbiter::Fastchess
+        } else {
+            Arbiter::Native
+        }
+    }
+}
+
+/// Order in which positions are taken from an opening suite. Each is played twice either way,
+/// with colours swapped.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Display,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum OpeningOrder {
+    #[default]
+    #[strum(serialize = "random")]
+    Random,
+    #[strum(serialize = "sequential")]
+    Sequential,
+}
+
+/// How the games of any type of match are played and where they are saved. Given as flags on the
+/// command line and as keys of a config file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Args)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchSettings {
+    /// What plays the games, fastchess if it's installed by default.
+    #[arg(long, value_enum, default_value_t = Arbiter::default())]
+    pub arbiter: Arbiter,
+    /// fastchess time control, e.g. `30+0` for 30 seconds a game without increment. Unset takes
+    /// the match type's own.
+    #[arg(long = "tc")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_control: Option<String>,
+    /// Number of games played at once.
+    #[arg(long, default_value_t = MatchSettings::default().concurrency)]
+    pub concurrency: usize,
+    /// EPD or PGN file of starting positions. Games start from the initial position without one.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openings: Option<String>,
+    /// Whether openings are taken at random or in the file's order.
+    #[arg(long, value_enum, default_value_t = OpeningOrder::default())]
+    pub opening_order: OpeningOrder,
+    /// Engines may use their own opening books, unless `--no-own-book` sends them `OwnBook=false`.
+    #[arg(long = "no-own-book", action = ArgAction::SetFalse)]
+    pub own_book: bool,
+    /// PGN file the games are appended to. Unset takes the match type's own.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pgn_out: Option<String>,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        MatchSettings {
+            arbiter: Arbiter::default(),
+            time_control: None,
+            concurrency: 1,
+            openings: None,
+            opening_order: OpeningOrder::default(),
+            own_book: true,
+            pgn_out: None,
+        }
+    }
+}
+
+impl MatchSettings {
+    /// The defaults of a match type, which plays at `time_control` and saves to `pgn_out`.
+    pub fn new(time_control: &str, pgn_out: &str) -> MatchSettings {
+        MatchSettings {
+            time_control: Some(time_control.to_string()),
+            pgn_out: Some(pgn_out.to_string()),
+            ..MatchSettings::default()
+        }
+    }
+
+    /// The time control and PGN file left unset taken from `defaults`, the match type's own.
+    pub fn or_defaults(self, defaults: MatchSettings) -> MatchSettings {
+        MatchSettings {
+            time_control: self.time_control.or(defaults.time_control),
+            pgn_out: self.pgn_out.or(defaults.pgn_out),
+            ..self
+        }
+    }
+
+    /// Panics if unset, which the options of every match type prevent.
+    pub fn time_control(&self) -> &str {
+        self.time_control
+            .as_deref()
+            .expect("the match type sets a time control")
+    }
+
+    /// Panics if unset, which the options of every match type prevent.
+    pub fn pgn_out(&self) -> &str {
+        self.pgn_out
+            .as_deref()
+            .expect("the match type sets a PGN file")
+    }
+
+    /// Panics if the time control isn't valid.
+    pub fn arbiter_options(&self) -> ArbiterOptions {
+        ArbiterOptions::new(
+            self.time_control(),
+            self.concurrency,
+            self.openings.clone(),
+            self.opening_order,
+            self.pgn_out(),
+        )
+    }
+}
+
+/// Options of a match between two bots, or of every pairing of a tournament.
 pub struct BotVsBotOptions {
+    /// Games are played in pairs from the same opening with colours swapped, so an odd number is
+    /// rounded up.
     pub games: usize,
+    pub settings: MatchSettings,
 }
 
 impl Default for BotVsBotOptions {
     fn default() -> Self {
-        BotVsBotOptions { games: 5 }
+        BotVsBotOptions {
+            games: 5,
+            settings: MatchSettings::new("30+0", "game.pgn"),
+        }
     }
 }
 
@@ -41,7 +182,12 @@ impl Default for PerformanceOptions {
 pub enum MatchType {
     BuildAndRunCutechess,
     BotVsBot(BotVsBotOptions),
-    StockfishLadder,
+    /// Every bot plays every other bot.
+    RoundRobin(BotVsBotOptions),
+    /// The first bot plays every other bot.
+    Gauntlet(BotVsBotOptions),
+    Sprt(SprtOptions),
+    StockfishLadder(LadderOptions),
     Compliance,
     Performance(PerformanceOptions),
     BuildOnly,
@@ -50,15 +196,29 @@ pub enum MatchType {
 
 impl MatchType {
     pub fn setup_bot_vs_bot() -> MatchType {
+        MatchType::BotVsBot(Self::setup_games("Configure Bot v Bot"))
+    }
+
+    pub fn setup_round_robin() -> MatchType {
+        MatchType::RoundRobin(Self::setup_games(
+            "Configure Round Robin (games per pairing)",
+        ))
+    }
+
+    pub fn setup_gauntlet() -> MatchType {
+        MatchType::Gauntlet(Self::setup_games("Configure Gauntlet (games per pairing)"))
+    }
+
+    fn setup_games(prompt: &str) -> BotVsBotOptions {
         let selection = Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("Configure Bot v Bot")
+            .with_prompt(prompt)
             .items(vec!["Default", "Custom"])
             .default(0)
             .interact()
             .unwrap();
 
         if selection == 0 {
-            MatchType::BotVsBot(BotVsBotOptions::default())
+            BotVsBotOptions::default()
         } else {
             let i = Input::with_theme(&ColorfulTheme::default())
                 .with_prompt("Enter number of games (1-1000)")
@@ -72,8 +232,86 @@ impl MatchType {
                 .interact()
                 .unwrap();
 
-            MatchType::BotVsBot(BotVsBotOptions {
+            BotVsBotOptions {
                 games: i.parse::<usize>().unwrap(),
+                ..BotVsBotOptions::default()
+            }
+        }
+    }
+
+    pub fn setup_sprt() -> MatchType {
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Configure SPRT")
+            .items(vec!["Default [0, 10]", "Custom Elo bounds"])
+            .default(0)
+            .interact()
+            .unwrap();
+
+        if selection == 0 {
+            MatchType::Sprt(SprtOptions::default())
+        } else {
+            let elo0: f64 = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Enter elo0 (H0: the first bot is this much stronger)")
+                .interact()
+                .unwrap();
+            let elo1: f64 = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Enter elo1 (H1: the first bot is this much stronger)")
+                .validate_with(|elo1: &f64| {
+                    if *elo1 > elo0 {
+                        Ok(())
+                    } else {
+                        Err("elo1 must be greater than elo0")
+                    }
+                })
+                .interact()
+                .unwrap();
+
+            MatchType::Sprt(SprtOptions {
+                elo0,
+                elo1,
+                ..SprtOptions::default()
+            })
+        }
+    }
+
+    pub fn setup_stockfish_ladder() -> MatchType {
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Configure Stockfish Ladder")
+            .items(vec!["Default", "Custom"])
+            .default(0)
+            .interact()
+            .unwrap();
+
+        if selection == 0 {
+            MatchType::StockfishLadder(LadderOptions::default())
+        } else {
+            let start_elo = Input::<u32>::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("Enter starting Elo ({MIN_ELO}-{MAX_ELO})"))
+                .validate_with(|elo: &u32| {
+                    if (MIN_ELO..=MAX_ELO).contains(elo) {
+                        Ok(())
+                    } else {
+                        Err("Stockfish only limits its strength to this range")
+                    }
+                })
+                .interact()
+                .unwrap();
+            let pairs = Input::<usize>::with_theme(&ColorfulTheme::default())
+                .with_prompt("Enter game pairs per level (1-100)")
+                .validate_with(|pairs: &usize| {
+                    if (1..=100).contains(pairs) {
+                        Ok(())
+                    } else {
+                        Err("Invalid number of game pairs")
+                    }
+                })
+                .interact()
+                .unwrap();
+
+            MatchType::StockfishLadder(LadderOptions {
+                start_elo,
+                pairs,
+                ..LadderOptions::default()
             })
         }
     }
@@ -98,14 +336,48 @@ impl MatchType {
             MatchType::Performance(PerformanceOptions { fen: i })
         }
     }
+
+    pub fn simple(&self) -> SimpleMatchType {
+        match self {
+            MatchType::BuildAndRunCutechess => SimpleMatchType::BuildAndRunCutechess,
+            MatchType::BotVsBot(_) => SimpleMatchType::BotVsBot,
+            MatchType::RoundRobin(_) => SimpleMatchType::RoundRobin,
+            MatchType::Gauntlet(_) => SimpleMatchType::Gauntlet,
+            MatchType::Sprt(_) => SimpleMatchType::Sprt,
+            MatchType::StockfishLadder(_) => SimpleMatchType::StockfishLadder,
+            MatchType::Compliance => SimpleMatchType::Compliance,
+            MatchType::Performance(_) => SimpleMatchType::Performance,
+            MatchType::BuildOnly => SimpleMatchType::BuildOnly,
+            MatchType::BuildAndRun => SimpleMatchType::BuildAndRun,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, EnumIter, EnumString, Display)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    Serialize,
+    Deserialize,
+    EnumIter,
+    EnumString,
+    Display,
+)]
 pub enum SimpleMatchType {
     #[strum(serialize = "Build and Run Cutechess")]
     BuildAndRunCutechess,
+    #[default]
     #[strum(serialize = "Bot v Bot")]
     BotVsBot,
+    #[strum(serialize = "Round Robin")]
+    RoundRobin,
+    #[strum(serialize = "Gauntlet")]
+    Gauntlet,
+    #[strum(serialize = "SPRT")]
+    Sprt,
     #[strum(serialize = "Stockfish Ladder")]
     StockfishLadder,
     #[strum(serialize = "Compliance")]
@@ -119,15 +391,18 @@ pub enum SimpleMatchType {
 }
 
 impl SimpleMatchType {
-    pub fn bots_required(&self) -> usize {
+    pub fn bots_required(&self) -> RangeInclusive<usize> {
         match &self {
-            SimpleMatchType::BuildAndRunCutechess => 2,
-            SimpleMatchType::BotVsBot => 2,
-            SimpleMatchType::StockfishLadder => 1,
-            SimpleMatchType::Compliance => 1,
-            SimpleMatchType::Performance => 1,
-            SimpleMatchType::BuildOnly => 1,
-            SimpleMatchType::BuildAndRun => 1,
+            SimpleMatchType::BuildAndRunCutechess => 2..=2,
+            SimpleMatchType::BotVsBot => 2..=2,
+            SimpleMatchType::RoundRobin => 2..=usize::MAX,
+            SimpleMatchType::Gauntlet => 2..=usize::MAX,
+            SimpleMatchType::Sprt => 2..=2,
+            SimpleMatchType::StockfishLadder => 1..=1,
+            SimpleMatchType::Compliance => 1..=1,
+            SimpleMatchType::Performance => 1..=1,
+            SimpleMatchType::BuildOnly => 1..=1,
+            SimpleMatchType::BuildAndRun => 1..=1,
         }
     }
 
@@ -135,7 +410,10 @@ impl SimpleMatchType {
         match &self {
             SimpleMatchType::BuildAndRunCutechess => MatchType::BuildAndRunCutechess,
             SimpleMatchType::BotVsBot => MatchType::setup_bot_vs_bot(),
-            SimpleMatchType::StockfishLadder => MatchType::StockfishLadder,
+            SimpleMatchType::RoundRobin => MatchType::setup_round_robin(),
+            SimpleMatchType::Gauntlet => MatchType::setup_gauntlet(),
+            SimpleMatchType::Sprt => MatchType::setup_sprt(),
+            SimpleMatchType::StockfishLadder => MatchType::setup_stockfish_ladder(),
             SimpleMatchType::Compliance => MatchType::Compliance,
             SimpleMatchType::Performance => MatchType::setup_performance(),
             SimpleMatchType::BuildOnly => MatchType::BuildOnly,
@@ -148,6 +426,23 @@ impl SimpleMatchType {
 pub struct ChessBot {
     pub name: String,
     pub path: String,
+    pub args: Vec<String>,
+    /// Working directory of the engine, the runner's if `None`.
+    pub dir: Option<String>,
+    /// UCI options set whenever the bot is started.
+    pub options: Vec<(String, String)>,
+}
+
+impl ChessBot {
+    /// Command starting the bot, with its arguments and working directory.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.path);
+        command.args(&self.args);
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        command
+    }
 }
 
 #[derive(new, Getters)]
@@ -160,7 +455,7 @@ fn get_remote_versions() -> Vec<String> {
         .args([
             "ls-remote",
             "--heads",
-            "https://github.com/grass-latte/beans-gambit.git",
+            REMOTE_REPOSITORY,
             "refs/heads/versions/*",
         ])
         .output()
@@ -189,9 +484,21 @@ fn get_remote_versions() -> Vec<String> {
     versions
 }
 
-static AVAILABLE_VERSIONS: LazyLock<Vec<String>> = LazyLock::new(get_remote_versions);
-
-#[derive(Debug, Serialize, Deserialize, EnumString, EnumIter, Display, EnumCount, Hash)]
+static AVAILABLE_VERSIONS: LazyLock<Vec<String>> = LazyLock::new(|| local_versions(Path::new(".")));
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    EnumString,
+    EnumIter,
+    Display,
+    EnumCount,
+    Hash,
+)]
 pub enum LocalBot {
     #[strum(serialize = "Beans Gambit [local]")]
     BeansGambitLocal,
@@ -223,13 +530,43 @@ impl LocalBot {
     }
 }
 
-fn select_bot(index: usize) -> Either<LocalBot, String> {
+/// Menu entry for Stockfish at a fixed Elo.
+const LIMITED_STOCKFISH: &str = "Stockfish [fixed Elo]";
+/// Menu entry for a build of any commit of the local repository.
+const COMMIT: &str = "Beans Gambit [branch, tag or commit]";
+/// Menu entry listing the remote's versions, which needs the network.
+const REMOTE_VERSION: &str = "Beans Gambit [remote version]";
+
+/// Names of the engines of the registry file.
+fn get_registered_engines() -> Vec<String> {
+    match Registry::load(ENGINES_FILE) {
+        Ok(registry) => registry.engines.into_iter().map(|e| e.name).collect(),
+        Err(e) => {
+            cprintln!("<yellow,bold>Failed to read {}: {}</>", ENGINES_FILE, e);
+            Vec::new()
+        }
+    }
+}
+
+fn select_bot(index: usize) -> BotConfig {
     let mut versions = LocalBot::get_available();
+    let local_count = versions.len();
 
-    let available_remote_versions: &Vec<String> = AVAILABLE_VERSIONS.as_ref();
+    if versions.contains(&LocalBot::Stockfish.to_string()) {
+        versions.push(LIMITED_STOCKFISH.to_string());
+    }
+    let first_registered = versions.len();
+
+    let registered = get_registered_engines();
+    versions.extend(registered.iter().map(|name| format!("{name} [registry]")));
+    versions.push(COMMIT.to_string());
+    versions.push(REMOTE_VERSION.to_string());
+    let first_version = versions.len();
+
+    let available_versions: &Vec<String> = AVAILABLE_VERSIONS.as_ref();
 
     versions.extend(
-        available_remote_versions
+        available_versions
             .iter()
             .map(|s| format!("Beans Gambit [{s}]")),
     );
@@ -241,15 +578,71 @@ fn select_bot(index: usize) -> Either<LocalBot, String> {
         .interact()
         .unwrap();
 
-    if selection < LocalBot::COUNT {
-        Either::Left(LocalBot::from_str(&versions[selection]).unwrap())
+    if selection < local_count {
+        BotConfig::Local(LocalBot::from_str(&versions[selection]).unwrap())
+    } else if selection < first_registered {
+        let elo = Input::<u32>::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Enter Stockfish Elo ({MIN_ELO}-{MAX_ELO})"))
+            .validate_with(|elo: &u32| {
+                if (MIN_ELO..=MAX_ELO).contains(elo) {
+                    Ok(())
+                } else {
+                    Err("Stockfish only limits its strength to this range")
+                }
+            })
+            .interact()
+            .unwrap();
+        BotConfig::StockfishElo(elo)
+    } else if selection < first_version - 2 {
+        BotConfig::Registered(registered[selection - first_registered].clone())
+    } else if selection == first_version - 2 {
+        let commit = Input::<String>::with_theme(&ColorfulTheme::default())
+            .with_prompt("Enter branch, tag or commit")
+            .validate_with(|commit: &String| {
+                resolve_commit(Path::new("."), commit)
+                    .map(|_| ())
+                    .ok_or("Not found in the local repository")
+            })
+            .interact()
+            .unwrap();
+        BotConfig::Version(commit)
+    } else if selection == first_version - 1 {
+        let remote_versions = get_remote_versions();
+        if remote_versions.is_empty() {
+            cprintln!("<yellow,bold>No remote versions found</>");
+            return select_bot(index);
+        }
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Select remote version for bot {index}"))
+            .items(&remote_versions)
+            .default(0)
+            .interact()
+            .unwrap();
+        BotConfig::Version(remote_versions[selection].clone())
     } else {
-        let selection = selection - LocalBot::COUNT;
-        Either::Right(AVAILABLE_VERSIONS[selection].clone())
+        BotConfig::Version(AVAILABLE_VERSIONS[selection - first_version].clone())
+    }
+}
+
+/// Offer to save the setup, so it can be rerun with `chess-runner run <file>`.
+fn save_config(config: &MatchConfig) {
+    let path = Input::<String>::with_theme(&ColorfulTheme::default())
+        .with_prompt("Save setup to (.toml / .json, empty to skip)")
+        .allow_empty(true)
+        .interact()
+        .unwrap();
+
+    if path.is_empty() {
+        return;
+    }
+
+    match config.save(&path) {
+        Ok(()) => cprintln!("<g,bold>Saved setup to {}</>", path),
+        Err(e) => cprintln!("<r,bold>Failed to save setup: {}</>", e),
     }
 }
 
-pub fn select_options() -> (ChessOptions, Vec<Either<LocalBot, String>>) {
+pub fn select_options() -> (ChessOptions, Vec<BotConfig>) {
     let items: Vec<_> = SimpleMatchType::iter().map(|a| a.to_string()).collect();
     let selection = Select::with_theme(&ColorfulTheme::default())
         .with_prompt("Select option")
@@ -260,11 +653,24 @@ pub fn select_options() -> (ChessOptions, Vec<Either<LocalBot, String>>) {
 
     let mut bots = Vec::new();
 
-    for i in 0..selection.bots_required() {
+    let required = selection.bots_required();
+    for i in 0..*required.start() {
         bots.push(select_bot(i));
     }
+    while bots.len() < *required.end()
+        && Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Add another bot?")
+            .default(false)
+            .interact()
+            .unwrap()
+    {
+        bots.push(select_bot(bots.len()));
+    }
+
+    let options = ChessOptions::new(selection.complete_setup());
+    save_config(&MatchConfig::new(&options, bots.clone()));
 
-    (ChessOptions::new(selection.complete_setup()), bots)
+    (options, bots)
 }
 
 #[cfg(test)]
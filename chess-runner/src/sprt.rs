@@ -0,0 +1,404 @@
+//! Sequential probability ratio test of one bot against another. Game pairs are played with
+//! fastchess, each pair from the same opening with colours swapped, until the log-likelihood
+//! ratio of H1 (the first bot is `elo1` stronger) against H0 (it is `elo0` stronger) crosses a
+//! bound. The LLR is the GSPRT approximation over pentanomial pair results, as used by fishtest
+//! and fastchess, with logistic Elo bounds.
+
+use crate::arbiter::play_match;
+use crate::results::{Outcome, elo_with_error, logistic_score};
+use crate::run::{add_engine, add_openings, count_games, print_summary, with_own_book};
+use crate::setup::{Arbiter, ChessBot, MatchSettings};
+use chess_lib::pgn::GameResult;
+use color_print::{cformat, cprintln};
+use itertools::Itertools;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader};
+use std::ops::ControlFlow;
+use std::process::{Command, Stdio};
+
+/// Empty pentanomial counts are replaced with this, so a handful of identical pairs can't give a
+/// zero variance and end the test early.
+const REGULARISATION: f64 = 1e-3;
+
+pub struct SprtOptions {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// Probability of accepting H1 when H0 is true.
+    pub alpha: f64,
+    /// Probability of accepting H0 when H1 is true.
+    pub beta: f64,
+    pub settings: MatchSettings,
+}
+
+impl Default for SprtOptions {
+    fn default() -> Self {
+        SprtOptions {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+            settings: MatchSettings::new("10+0.1", "sprt.pgn"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtVerdict {
+    /// The first bot isn't `elo1` stronger.
+    AcceptH0,
+    /// The first bot isn't only `elo0` stronger.
+    AcceptH1,
+}
+
+impl Display for SprtVerdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SprtVerdict::AcceptH0 => write!(f, "H0 accepted - fail"),
+            SprtVerdict::AcceptH1 => write!(f, "H1 accepted - pass"),
+        }
+    }
+}
+
+impl SprtOptions {
+    /// Why the test could never end or would give inverted verdicts, if it would.
+    pub fn invalid_reason(&self) -> Option<&'static str> {
+        if self.elo1.partial_cmp(&self.elo0) != Some(Ordering::Greater) {
+            Some("elo1 must be greater than elo0")
+        } else if !(self.alpha > 0.0 && self.alpha < 1.0) {
+            Some("alpha must be between 0 and 1")
+        } else if !(self.beta > 0.0 && self.beta < 1.0) {
+            Some("beta must be between 0 and 1")
+        } else {
+            None
+        }
+    }
+
+    /// Lower and upper LLR bounds.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn verdict(&self, llr: f64) -> Option<SprtVerdict> {
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            Some(SprtVerdict::AcceptH0)
+        } else if llr >= upper {
+            Some(SprtVerdict::AcceptH1)
+        } else {
+            None
+        }
+    }
+}
+
+/// Number of game pairs in which the first bot scored 0, 0.5, 1, 1.5 and 2 points.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pentanomial(pub [u32; 5]);
+
+impl Pentanomial {
+    /// Record a pair given the first bot's score in each game, from 0 to 1.
+    pub fn add_pair(&mut self, first: f64, second: f64) {
+        self.0[((first + second) * 2.0).round() as usize] += 1;
+    }
+
+    pub fn pairs(&self) -> u32 {
+        self.0.iter().sum()
+    }
+
+    /// Mean score per game, and the variance of that mean.
+    fn mean_and_variance(counts: [f64; 5]) -> (f64, f64) {
+        let n = counts.iter().sum::<f64>();
+        let score = |i: usize| i as f64 / 4.0;
+        let mean = (0..5).map(|i| counts[i] * score(i)).sum::<f64>() / n;
+        let variance = (0..5)
+            .map(|i| counts[i] * (score(i) - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        (mean, variance / n)
+    }
+
+    /// Logistic Elo difference and the half-width of its 95% confidence interval.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        if self.pairs() == 0 {
+            return None;
+        }
+
+        let (mean, variance) = Pentanomial::mean_and_variance(self.0.map(|c| c as f64));
+        elo_with_error(mean, variance)
+    }
+
+    /// Zero until the pairs have had at least two different scores, as the variance can't be
+    /// estimated before then.
+    pub fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        if self.0.iter().filter(|&&c| c > 0).count() < 2 {
+            return 0.0;
+        }
+
+        let counts = self
+            .0
+            .map(|c| if c == 0 { REGULARISATION } else { c as f64 });
+        let (mean, variance) = Pentanomial::mean_and_variance(counts);
+        let (score0, score1) = (logistic_score(elo0), logistic_score(elo1));
+        (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+}
+
+/// Game number, white player and result from a fastchess line like
+/// `Finished game 3 (White vs Black): 1-0 {White mates}`.
+fn parse_finished_game(line: &str) -> Option<(usize, &str, GameResult)> {
+    let line = line.trim().strip_prefix("Finished game ")?;
+    let (number, rest) = line.split_once(" (")?;
+    let (players, rest) = rest.split_once("): ")?;
+    let (white, _) = players.split_once(" vs ")?;
+    let result = GameResult::from_pgn(rest.split_whitespace().next()?)?;
+    Some((number.parse().ok()?, white, result))
+}
+
+/// Score of `bot_name`, from 0 to 1.
+fn score(white: &str, result: GameResult, bot_name: &str) -> f64 {
+    let outcome = match (result, white == bot_name) {
+        (GameResult::WhiteWin, true) | (GameResult::BlackWin, false) => Outcome::Win,
+        (GameResult::WhiteWin, false) | (GameResult::BlackWin, true) => Outcome::Loss,
+        _ => Outcome::Draw,
+    };
+    outcome.score()
+}
+
+/// Pentanomial results of the games finished so far.
+#[derive(Default)]
+struct SprtState {
+    pentanomial: Pentanomial,
+    /// Scores of pairs with one game finished, by pair number.
+    unfinished_pairs: HashMap<usize, f64>,
+}
+
+impl SprtState {
+    /// Record game `number` (from 1) and print the state of the test once its pair is complete.
+    fn add_game(
+        &mut self,
+        number: usize,
+        white: &str,
+        result: GameResult,
+        bot_name: &str,
+        options: &SprtOptions,
+    ) -> Option<SprtVerdict> {
+        let score = score(white, result, bot_name);
+        let pair = number.div_ceil(2);
+        let Some(first) = self.unfinished_pairs.remove(&pair) else {
+            self.unfinished_pairs.insert(pair, score);
+            return None;
+        };
+        self.pentanomial.add_pair(first, score);
+
+        let llr = self.pentanomial.llr(options.elo0, options.elo1);
+        let elo = self
+            .pentanomial
+            .elo()
+            .map_or("-".to_string(), |(elo, error)| {
+                format!("{elo:.1} ± {error:.1}")
+            });
+        let (lower, upper) = options.bounds();
+        println!(
+            "Pairs: {}, pentanomial {:?}, Elo: {}, LLR: {:.2} ({:.2}, {:.2})",
+            self.pentanomial.pairs(),
+            self.pentanomial.0,
+            elo,
+            llr,
+            lower,
+            upper
+        );
+
+        options.verdict(llr)
+    }
+}
+
+fn fastchess_sprt(
+    bot: &ChessBot,
+    baseline: &ChessBot,
+    options: &SprtOptions,
+    state: &mut SprtState,
+) -> Option<SprtVerdict> {
+    let mut command = Command::new("fastchess");
+    add_engine(&mut command, bot);
+    add_engine(&mut command, baseline);
+    let command = command
+        .arg("-each")
+        .arg(format!("tc={}", options.settings.time_control()))
+        .arg("proto=uci")
+        // Stopped once the test concludes
+        .arg("-rounds")
+        .arg(u32::MAX.to_string())
+        .arg("-games")
+        .arg("2")
+        .arg("-repeat")
+        .arg("-concurrency")
+        .arg(options.settings.concurrency.to_string())
+        .arg("-pgnout")
+        .arg(format!("file={}", options.settings.pgn_out()));
+
+    if let Some(openings) = &options.settings.openings {
+        add_openings(command, openings, options.settings.opening_order);
+    }
+
+    cprintln!(
+        "<c>Args: {}</>",
+        command
+            .get_args()
+            .map(|s| s.to_string_lossy())
+            .collect_vec()
+            .join(" ")
+    );
+
+    let Ok(mut child) = command.stdout(Stdio::piped()).spawn() else {
+        panic!("{}", cformat!("<r,bold>Failed to run fastchess</>"));
+    };
+    let reader = BufReader::new(child.stdout.take().unwrap());
+
+    let mut verdict = None;
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let Some((number, white, result)) = parse_finished_game(&line) else {
+            continue;
+        };
+
+        verdict = state.add_game(number, white, result, &bot.name, options);
+        if verdict.is_some() {
+            break;
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+    verdict
+}
+
+/// Test `bot` against `baseline` until the SPRT reaches a verdict.
+pub fn sprt(bot: ChessBot, baseline: ChessBot, options: &SprtOptions) {
+    let bot = with_own_book(&bot, options.settings.own_book);
+    let baseline = with_own_book(&baseline, options.settings.own_book);
+    let previous_games = count_games(options.settings.pgn_out());
+    let (lower, upper) = options.bounds();
+    cprintln!(
+        "<c,bold>SPRT [{}, {}], alpha {}, beta {}, LLR bounds ({:.2}, {:.2})</>",
+        options.elo0,
+        options.elo1,
+        options.alpha,
+        options.beta,
+        lower,
+        upper
+    );
+
+    let mut state = SprtState::default();
+    let verdict = match options.settings.arbiter {
+        Arbiter::Fastchess => fastchess_sprt(&bot, &baseline, options, &mut state),
+        Arbiter::Native => {
+            let mut verdict = None;
+            play_match(
+                [bot.clone(), baseline],
+                usize::MAX,
+                &options.settings.arbiter_options(),
+                |finished| {
+                    verdict = state.add_game(
+                        finished.number,
+                        finished.white(),
+                        finished.game.result,
+                        &bot.name,
+                        options,
+                    );
+                    match verdict {
+                        Some(_) => ControlFlow::Break(()),
+                        None => ControlFlow::Continue(()),
+                    }
+                },
+            );
+            verdict
+        }
+    };
+
+    match verdict {
+        Some(SprtVerdict::AcceptH1) => {
+            cprintln!("<g,bold>{}: {}</>", bot.name, SprtVerdict::AcceptH1)
+        }
+        Some(SprtVerdict::AcceptH0) => {
+            cprintln!("<r,bold>{}: {}</>", bot.name, SprtVerdict::AcceptH0)
+        }
+        None => cprintln!("<y,bold>The match stopped before the SPRT reached a verdict</>"),
+    }
+
+    print_summary(options.settings.pgn_out(), &bot.name, previous_games);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounds() {
+        let (lower, upper) = SprtOptions::default().bounds();
+        assert!((lower + 2.944).abs() < 1e-3);
+        assert!((upper - 2.944).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_elo() {
+        assert_eq!(Pentanomial::default().elo(), None);
+
+        let (elo, error) = Pentanomial([0, 0, 10, 0, 0]).elo().unwrap();
+        assert_eq!((elo, error), (0.0, 0.0));
+
+        // Every pair scores 1.5 / 2
+        let (elo, _) = Pentanomial([0, 0, 0, 10, 0]).elo().unwrap();
+        assert!((elo - 190.85).abs() < 0.01);
+
+        // Winning every pair has no finite Elo
+        assert_eq!(Pentanomial([0, 0, 0, 0, 3]).elo(), None);
+
+        let (elo, error) = Pentanomial([5, 20, 50, 30, 10]).elo().unwrap();
+        let (reversed_elo, reversed_error) = Pentanomial([10, 30, 50, 20, 5]).elo().unwrap();
+        assert!(elo > 0.0 && error > 0.0);
+        assert!((elo + reversed_elo).abs() < 1e-9);
+        assert!((error - reversed_error).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_llr() {
+        let options = SprtOptions::default();
+        assert_eq!(Pentanomial::default().llr(options.elo0, options.elo1), 0.0);
+        assert_eq!(
+            Pentanomial([0, 0, 0, 0, 3]).llr(options.elo0, options.elo1),
+            0.0
+        );
+
+        // A couple of won pairs isn't enough to conclude anything
+        let llr = Pentanomial([0, 0, 0, 1, 1]).llr(options.elo0, options.elo1);
+        assert_eq!(options.verdict(llr), None);
+
+        // Evenly matched, so H1 is rejected eventually
+        let llr = Pentanomial([100, 400, 1000, 400, 100]).llr(options.elo0, options.elo1);
+        assert_eq!(options.verdict(llr), Some(SprtVerdict::AcceptH0));
+
+        let llr = Pentanomial([50, 300, 800, 500, 150]).llr(options.elo0, options.elo1);
+        assert_eq!(options.verdict(llr), Some(SprtVerdict::AcceptH1));
+    }
+
+    #[test]
+    fn test_parse_finished_game() {
+        assert_eq!(
+            parse_finished_game(
+                "Finished game 3 (Beans Gambit [Local] vs Beans Gambit [v0.4.0]): 1/2-1/2 {Draw by 3-fold repetition}"
+            ),
+            Some((3, "Beans Gambit [Local]", GameResult::Draw))
+        );
+        assert_eq!(parse_finished_game("Started game 4 (A vs B)"), None);
+
+        assert_eq!(score("A", GameResult::WhiteWin, "A"), 1.0);
+        assert_eq!(score("A", GameResult::WhiteWin, "B"), 0.0);
+        assert_eq!(score("A", GameResult::BlackWin, "B"), 1.0);
+    }
+}
@@ -1,138 +1,285 @@
-use crate::bot_resolver::get_stockfish;
-use crate::setup::ChessBot;
-use color_print::{cprintln, cwrite};
+//! Rating estimation against Stockfish. Game pairs are played at `UCI_Elo` levels, and the bot's
+//! rating on Stockfish's scale is the maximum likelihood fit of a logistic performance curve to
+//! every result so far. Each level is the current estimate, so games are played where they tell
+//! the most. Until the bot has both gained and dropped points the estimate doesn't exist, and the
+//! level moves by `STEP` in the direction of the results instead.
+
+use crate::arbiter::play_match;
+use crate::bot_resolver::get_limited_stockfish;
+use crate::results::{Wdl, Z_95, logistic_score, read_records_file};
+use crate::run::{add_engine, add_openings, count_games, with_own_book};
+use crate::setup::{Arbiter, ChessBot, MatchSettings};
+use color_print::{cformat, cprintln};
+use itertools::Itertools;
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
+use std::ops::ControlFlow;
 use std::process::Command;
-use std::str::FromStr;
 
-pub fn stockfish_ladder(bot: ChessBot) {
-    let stockfish = get_stockfish();
-    let mut current_elo = 1350u32;
-    // let mut games_played = 0u32;
+/// Range of `UCI_Elo` Stockfish accepts.
+pub const MIN_ELO: u32 = 1320;
+pub const MAX_ELO: u32 = 3190;
 
-    loop {
-        let stockfish = ChessBot {
-            name: format!("{}_{}", stockfish.name.clone(), current_elo),
-            path: stockfish.path.clone(),
-        };
+/// How far the level moves while the bot has only won or only lost.
+const STEP: u32 = 200;
 
-        println!("Playing games at elo {}", current_elo);
+pub struct LadderOptions {
+    /// Level of the first games.
+    pub start_elo: u32,
+    /// Game pairs played at each level, each pair from the same opening with colours swapped.
+    pub pairs: usize,
+    /// Most levels played before giving up on `max_error`.
+    pub levels: usize,
+    /// Stop once the 95% confidence interval is at most this wide either side.
+    pub max_error: f64,
+    pub settings: MatchSettings,
+}
 
-        let result = run_match(bot.clone(), &stockfish, current_elo);
-        // games_played += result.len() as u32;
+impl Default for LadderOptions {
+    fn default() -> Self {
+        LadderOptions {
+            start_elo: 1500,
+            pairs: 4,
+            levels: 8,
+            max_error: 50.0,
+            settings: MatchSettings::new("60+0.6", "ladder.pgn"),
+        }
+    }
+}
 
-        // Check for two consecutive losses
-        let consecutive_losses = result
-            .windows(2)
-            .any(|w| w[0] == GameResult::Loss && w[1] == GameResult::Loss);
+/// What the results so far say about the bot's rating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RatingFit {
+    /// Elo and the half-width of its 95% confidence interval.
+    Estimate(f64, f64),
+    /// Every game was won, up to this level.
+    Above(u32),
+    /// Every game was lost, down to this level.
+    Below(u32),
+}
 
-        if consecutive_losses {
-            cprintln!("<r,bold>Lost both games</>");
-            cprintln!("<g,bold>Final elo: {}</>", current_elo - 50);
-            return;
-        } else {
-            let last_two = result.windows(2).last().unwrap();
-            cprintln!(
-                "<c,bold>Elo {}: {} {}",
-                current_elo,
-                last_two[0],
-                last_two[1]
-            );
+impl Display for RatingFit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RatingFit::Estimate(elo, error) => write!(f, "{elo:.0} ± {error:.0}"),
+            RatingFit::Above(elo) => write!(f, "above {elo}"),
+            RatingFit::Below(elo) => write!(f, "below {elo}"),
         }
+    }
+}
+
+/// Fit a rating to the results at each level. Draws count as half a win and half a loss.
+pub fn fit_rating(results: &BTreeMap<u32, Wdl>) -> Option<RatingFit> {
+    let played = results
+        .iter()
+        .filter(|(_, wdl)| wdl.games() > 0)
+        .collect_vec();
+    let points = played.iter().map(|(_, wdl)| wdl.points()).sum::<f64>();
+    let games = played.iter().map(|(_, wdl)| wdl.games()).sum::<u32>() as f64;
 
-        current_elo += 50;
+    if played.is_empty() {
+        return None;
     }
+    if points == games {
+        return Some(RatingFit::Above(*played.last().unwrap().0));
+    }
+    if points == 0.0 {
+        return Some(RatingFit::Below(*played[0].0));
+    }
+
+    // Points minus expected points, which falls as the rating rises, so its root is bisected
+    let excess = |elo: f64| {
+        played
+            .iter()
+            .map(|&(&level, wdl)| {
+                wdl.points() - wdl.games() as f64 * logistic_score(elo - level as f64)
+            })
+            .sum::<f64>()
+    };
+    let (mut low, mut high) = (MIN_ELO as f64 - 2000.0, MAX_ELO as f64 + 2000.0);
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if excess(mid) > 0.0 {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    let elo = (low + high) / 2.0;
+
+    // Fisher information of the fit, treating each game as a win or a loss
+    let slope = 10f64.ln() / 400.0;
+    let information = played
+        .iter()
+        .map(|&(&level, wdl)| {
+            let p = logistic_score(elo - level as f64);
+            wdl.games() as f64 * p * (1.0 - p) * slope * slope
+        })
+        .sum::<f64>();
+
+    Some(RatingFit::Estimate(elo, Z_95 / information.sqrt()))
 }
 
-fn run_match(bot: ChessBot, stockfish: &ChessBot, elo: u32) -> Vec<GameResult> {
-    let pgn_path = format!("game_{}.pgn", elo);
-
-    let dir = PathBuf::from_str(".").unwrap().canonicalize().unwrap();
-
-    let output = Command::new("fastchess")
-        .arg("-engine")
-        .arg(format!("cmd={}", bot.path))
-        .arg(format!("name={}", bot.name))
-        .arg("-engine")
-        .arg(format!("cmd={}", stockfish.path))
-        .arg(format!("name={}", stockfish.name))
-        .arg("option.UCI_LimitStrength=true")
-        .arg(format!("option.UCI_Elo={}", elo))
-        .arg("-each")
-        .arg("tc=300+0")
-        .arg("proto=uci")
-        .arg(format!("dir={}", dir.display()))
-        .arg("-games")
-        .arg("1")
-        .arg("-pgnout")
-        .arg(&pgn_path)
-        .output()
-        .expect("Failed to run fastchess");
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    println!("stdout: {}", stdout);
-    parse_results(&stdout, &bot.name)
+/// Level of the next games, or `None` if the bot is out of Stockfish's range.
+pub fn next_level(fit: RatingFit) -> Option<u32> {
+    match fit {
+        RatingFit::Estimate(elo, _) => {
+            Some(((elo / 10.0).round() * 10.0).clamp(MIN_ELO as f64, MAX_ELO as f64) as u32)
+        }
+        RatingFit::Above(elo) => (elo < MAX_ELO).then(|| (elo + STEP).min(MAX_ELO)),
+        RatingFit::Below(elo) => (elo > MIN_ELO).then(|| elo.saturating_sub(STEP).max(MIN_ELO)),
+    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-enum GameResult {
-    Win,
-    Loss,
-    Draw,
+pub fn stockfish_ladder(bot: ChessBot, options: &LadderOptions) {
+    let bot = with_own_book(&bot, options.settings.own_book);
+
+    let mut results = BTreeMap::<u32, Wdl>::new();
+    let mut level = options.start_elo.clamp(MIN_ELO, MAX_ELO);
+    let mut fit = None;
+
+    for _ in 0..options.levels {
+        cprintln!(
+            "<c,bold>Playing {} game pairs at Elo {}</>",
+            options.pairs,
+            level
+        );
+
+        let stockfish = get_limited_stockfish(level);
+        let previous_games = count_games(options.settings.pgn_out());
+        play_level(&bot, &stockfish, options);
+
+        let wdl = results.entry(level).or_default();
+        match read_records_file(options.settings.pgn_out()) {
+            Ok(records) => records
+                .iter()
+                .skip(previous_games)
+                .filter_map(|record| record.outcome(&bot.name))
+                .for_each(|outcome| wdl.add(outcome)),
+            Err(e) => cprintln!(
+                "<r>Couldn't read results from {}: {}</>",
+                options.settings.pgn_out(),
+                e
+            ),
+        }
+        cprintln!("<c>Elo {}: {}</>", level, wdl);
+
+        fit = fit_rating(&results);
+        let Some(current) = fit else {
+            cprintln!("<r,bold>No games finished at Elo {}</>", level);
+            return;
+        };
+        cprintln!("<c,bold>Estimate: {}</>", current);
+
+        if matches!(current, RatingFit::Estimate(_, error) if error <= options.max_error) {
+            break;
+        }
+        let Some(next) = next_level(current) else {
+            break;
+        };
+        level = next;
+    }
+
+    println!("Results:");
+    for (level, wdl) in &results {
+        println!("  Stockfish {level}: {wdl}");
+    }
+    if let Some(fit) = fit {
+        cprintln!("<g,bold>Elo: {}</>", fit);
+    }
 }
 
-impl Display for GameResult {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            GameResult::Win => {
-                cwrite!(f, "<g,bold>Win</>")
-            }
-            GameResult::Loss => {
-                cwrite!(f, "<y,bold>Loss</>")
+/// Play `options.pairs` game pairs against `stockfish`, appending them to the PGN.
+fn play_level(bot: &ChessBot, stockfish: &ChessBot, options: &LadderOptions) {
+    match options.settings.arbiter {
+        Arbiter::Fastchess => {
+            let mut command = Command::new("fastchess");
+            add_engine(&mut command, bot);
+            add_engine(&mut command, stockfish);
+            let command = command
+                .arg("-each")
+                .arg(format!("tc={}", options.settings.time_control()))
+                .arg("proto=uci")
+                .arg("-rounds")
+                .arg(options.pairs.to_string())
+                .arg("-games")
+                .arg("2")
+                .arg("-repeat")
+                .arg("-concurrency")
+                .arg(options.settings.concurrency.to_string())
+                .arg("-pgnout")
+                .arg(format!("file={}", options.settings.pgn_out()));
+
+            if let Some(openings) = &options.settings.openings {
+                add_openings(command, openings, options.settings.opening_order);
             }
-            GameResult::Draw => {
-                cwrite!(f, "<r,bold>Loss</>")
+
+            let Ok(status) = command.status() else {
+                panic!("{}", cformat!("<r,bold>Failed to run fastchess</>"));
+            };
+            if !status.success() {
+                panic!(
+                    "{}",
+                    cformat!("<r,bold>fastchess exited with code: {:?}</>", status.code())
+                );
             }
         }
+        Arbiter::Native => play_match(
+            [bot.clone(), stockfish.clone()],
+            options.pairs * 2,
+            &options.settings.arbiter_options(),
+            |_| ControlFlow::Continue(()),
+        ),
     }
 }
 
-fn parse_results(output: &str, bot_name: &str) -> Vec<GameResult> {
-    let mut results = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::logistic_elo;
 
-    for line in output.lines() {
-        if !line.contains("Finished game") {
-            continue;
+    fn wdl(wins: u32, draws: u32, losses: u32) -> Wdl {
+        Wdl {
+            wins,
+            draws,
+            losses,
         }
+    }
 
-        // Determine which side our bot was on
-        // Format: "Finished game N (White vs Black): SCORE {reason}"
-        let bot_is_white = line
-            .split('(')
-            .nth(1)
-            .and_then(|s| s.split(')').next())
-            .map(|players| {
-                let white = players.split(" vs ").next().unwrap_or("").trim();
-                white == bot_name
-            })
-            .unwrap_or(false);
-
-        // Extract score like "1-0", "0-1", "1/2-1/2"
-        let score = line
-            .split("): ")
-            .nth(1)
-            .and_then(|s| s.split_whitespace().next())
-            .unwrap_or("");
-
-        let result = match (score, bot_is_white) {
-            ("1-0", true) | ("0-1", false) => GameResult::Win,
-            ("0-1", true) | ("1-0", false) => GameResult::Loss,
-            _ => GameResult::Draw,
-        };
+    #[test]
+    fn test_fit_one_sided() {
+        let mut results = BTreeMap::new();
+        assert_eq!(fit_rating(&results), None);
+
+        results.insert(1500, wdl(4, 0, 0));
+        results.insert(1700, wdl(2, 0, 0));
+        assert_eq!(fit_rating(&results), Some(RatingFit::Above(1700)));
+        assert_eq!(next_level(RatingFit::Above(1700)), Some(1900));
+        assert_eq!(next_level(RatingFit::Above(MAX_ELO)), None);
 
-        results.push(result);
+        let results = BTreeMap::from([(1500, wdl(0, 0, 8))]);
+        assert_eq!(fit_rating(&results), Some(RatingFit::Below(1500)));
+        assert_eq!(next_level(RatingFit::Below(1500)), Some(MIN_ELO));
+        assert_eq!(next_level(RatingFit::Below(MIN_ELO)), None);
     }
 
-    results
+    #[test]
+    fn test_fit_estimate() {
+        // At a single level the fit is the performance rating
+        let results = BTreeMap::from([(1500, wdl(5, 2, 1))]);
+        let Some(RatingFit::Estimate(elo, error)) = fit_rating(&results) else {
+            panic!("expected an estimate");
+        };
+        assert!((elo - (1500.0 + logistic_elo(0.75).unwrap())).abs() < 0.01);
+        assert!(error > 0.0);
+        assert_eq!(next_level(RatingFit::Estimate(elo, error)), Some(1690));
+
+        // More games narrow the interval
+        let more = BTreeMap::from([(1500, wdl(5, 2, 1)), (1700, wdl(3, 2, 3))]);
+        let Some(RatingFit::Estimate(more_elo, more_error)) = fit_rating(&more) else {
+            panic!("expected an estimate");
+        };
+        assert!(more_error < error);
+        assert!(more_elo > 1600.0 && more_elo < 1700.0);
+    }
 }
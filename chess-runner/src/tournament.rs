@@ -0,0 +1,248 @@
+//! Round-robin and gauntlet tournaments. Each pairing is played as a match between two bots, and
+//! the tables are built from the games it added to the PGN, so either arbiter can be used.
+
+use crate::results::{GameRecord, Wdl, read_records_file};
+use crate::run::{count_games, play_games};
+use crate::setup::{BotVsBotOptions, ChessBot};
+use color_print::cprintln;
+use itertools::Itertools;
+use std::fmt::{Display, Formatter};
+
+/// Every pair of `count` bots, as indices.
+pub fn round_robin_pairings(count: usize) -> Vec<(usize, usize)> {
+    (0..count)
+        .flat_map(|a| (a + 1..count).map(move |b| (a, b)))
+        .collect()
+}
+
+/// The first of `count` bots against each of the others.
+pub fn gauntlet_pairings(count: usize) -> Vec<(usize, usize)> {
+    (1..count).map(|b| (0, b)).collect()
+}
+
+/// Play `options.games` games between each pair of bots, then print the standings.
+pub fn tournament(bots: &[ChessBot], pairings: &[(usize, usize)], options: &BotVsBotOptions) {
+    let previous_games = count_games(options.settings.pgn_out());
+
+    for (i, &(a, b)) in pairings.iter().enumerate() {
+        cprintln!(
+            "<c,bold>Pairing {}/{}: {} vs {}</>",
+            i + 1,
+            pairings.len(),
+            bots[a].name,
+            bots[b].name
+        );
+        play_games(&bots[a], &bots[b], options);
+    }
+
+    let records = match read_records_file(options.settings.pgn_out()) {
+        Ok(records) => records,
+        Err(e) => {
+            cprintln!(
+                "<r,bold>Failed to read results from {}: {}</>",
+                options.settings.pgn_out(),
+                e
+            );
+            return;
+        }
+    };
+    let players = bots.iter().map(|bot| bot.name.clone()).collect_vec();
+    print!(
+        "{}",
+        Standings::new(&records[previous_games.min(records.len())..], &players)
+    );
+}
+
+/// Results of a tournament between `players`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standings {
+    pub players: Vec<String>,
+    /// `results[i][j]` is player `i`'s results against player `j`.
+    pub results: Vec<Vec<Wdl>>,
+}
+
+impl Standings {
+    /// Games with a player not in `players`, or without a result, are ignored.
+    pub fn new(records: &[GameRecord], players: &[String]) -> Standings {
+        let mut results = vec![vec![Wdl::default(); players.len()]; players.len()];
+
+        for record in records {
+            let index = |name: &str| players.iter().position(|p| p == name);
+            let (Some(white), Some(black)) = (index(&record.white), index(&record.black)) else {
+                continue;
+            };
+            let (Some(white_outcome), Some(black_outcome)) =
+                (record.outcome(&record.white), record.outcome(&record.black))
+            else {
+                continue;
+            };
+
+            results[white][black].add(white_outcome);
+            results[black][white].add(black_outcome);
+        }
+
+        Standings {
+            players: players.to_vec(),
+            results,
+        }
+    }
+
+    /// `player`'s results against everyone.
+    pub fn total(&self, player: usize) -> Wdl {
+        let mut total = Wdl::default();
+        for wdl in &self.results[player] {
+            total += *wdl;
+        }
+        total
+    }
+
+    /// Players from first to last, by score. Gauntlet opponents play fewer games than the
+    /// engine, so points alone would favour it.
+    pub fn ranking(&self) -> Vec<usize> {
+        let score = |player: usize| self.total(player).score().unwrap_or(0.0);
+        (0..self.players.len())
+            .sorted_by(|a, b| score(*b).total_cmp(&score(*a)))
+            .collect()
+    }
+}
+
+impl Display for Standings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let ranking = self.ranking();
+        let width = self.players.iter().map(|p| p.len()).max().unwrap_or(0);
+
+        writeln!(f, "Ranking:")?;
+        writeln!(
+            f,
+            "  {:>2}  {:width$}  {:>5}  {:>6}  {:>6}  Elo",
+            "#", "Name", "Games", "Points", "Score"
+        )?;
+        for (rank, &player) in ranking.iter().enumerate() {
+            let total = self.total(player);
+            let score = total
+                .score()
+                .map_or("-".to_string(), |s| format!("{:.1}%", s * 100.0));
+            let elo = total.elo().map_or("-".to_string(), |(elo, error)| {
+                format!("{elo:+.1} ± {error:.1}")
+            });
+            writeln!(
+                f,
+                "  {:>2}  {:width$}  {:>5}  {:>6.1}  {:>6}  {}",
+                rank + 1,
+                self.players[player],
+                total.games(),
+                total.points(),
+                score,
+                elo
+            )?;
+        }
+
+        writeln!(f, "Cross table:")?;
+        write!(f, "  {:>2}  {:width$}", "#", "Name")?;
+        for rank in 0..ranking.len() {
+            write!(f, "  {:>7}", rank + 1)?;
+        }
+        writeln!(f)?;
+        for (rank, &player) in ranking.iter().enumerate() {
+            write!(f, "  {:>2}  {:width$}", rank + 1, self.players[player])?;
+            for &opponent in &ranking {
+                let wdl = self.results[player][opponent];
+                let cell = if opponent == player {
+                    "X".to_string()
+                } else if wdl.games() == 0 {
+                    "-".to_string()
+                } else {
+                    format!("{:.1}/{}", wdl.points(), wdl.games())
+                };
+                write!(f, "  {cell:>7}")?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "Pairings:")?;
+        for (a, b) in round_robin_pairings(self.players.len()) {
+            let wdl = self.results[a][b];
+            if wdl.games() > 0 {
+                writeln!(f, "  {} vs {}: {}", self.players[a], self.players[b], wdl)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::read_records;
+
+    const PGN: &str = r#"[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 1-0
+
+[White "B"]
+[Black "A"]
+[Result "1/2-1/2"]
+
+1. e4 1/2-1/2
+
+[White "A"]
+[Black "C"]
+[Result "1-0"]
+
+1. d4 1-0
+
+[White "C"]
+[Black "B"]
+[Result "0-1"]
+
+1. d4 0-1
+
+[White "C"]
+[Black "Other"]
+[Result "1-0"]
+
+1. c4 1-0
+
+[White "B"]
+[Black "C"]
+[Result "*"]
+
+1. c4 *
+"#;
+
+    #[test]
+    fn test_pairings() {
+        assert_eq!(round_robin_pairings(3), vec![(0, 1), (0, 2), (1, 2)]);
+        assert_eq!(gauntlet_pairings(3), vec![(0, 1), (0, 2)]);
+        assert!(round_robin_pairings(1).is_empty());
+    }
+
+    #[test]
+    fn test_standings() {
+        let players = ["C", "B", "A"].map(String::from);
+        let standings = Standings::new(&read_records(PGN).unwrap(), &players);
+
+        assert_eq!(
+            standings.results[2][1],
+            Wdl {
+                wins: 1,
+                draws: 1,
+                losses: 0
+            }
+        );
+        assert_eq!(
+            standings.results[1][2],
+            Wdl {
+                wins: 0,
+                draws: 1,
+                losses: 1
+            }
+        );
+        assert_eq!(standings.results[0][0], Wdl::default());
+        assert_eq!(standings.total(0).games(), 2);
+        assert_eq!(standings.total(2).points(), 2.5);
+        assert_eq!(standings.ranking(), vec![2, 1, 0]);
+    }
+}
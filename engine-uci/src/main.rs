@@ -2,19 +2,24 @@
 #![allow(unused)]
 
 mod uci_conversions;
+mod uci_position;
 mod uci_state;
 
 use crate::uci_conversions::{from_uci_move, to_uci_piece, to_uci_square};
-use crate::uci_state::{UciOptions, UciState};
+use crate::uci_position::apply_position_message;
+use crate::uci_state::{BUILTIN_BOOK, UciOptions, UciState};
 use chess_lib::board::{Board, Move};
 use chrono::Local;
 use deepsize::DeepSizeOf;
 use engine::results::Score;
+use engine::syzygy::Tablebases;
 use engine::{InterMoveCache, search};
 use fern::Dispatch;
 use human_bytes::human_bytes;
 use log::{debug, error, info, warn};
-use opening_book::{DefaultOpeningBook, OpeningBook};
+use opening_book::{
+    BookLearning, BookSelector, BookStrategy, DefaultOpeningBook, OpeningBook, load_book,
+};
 use std::borrow::Borrow;
 use std::fs::File;
 use std::io::{BufRead, Write};
@@ -49,6 +54,74 @@ fn send_info<S1: AsRef<str>, S2: AsRef<str>>(info: S1, value: S2) {
     );
 }
 
+/// Apply a `position` command, reporting errors to the GUI and keeping the last valid position.
+fn update_position(board: &mut Board, msg: &UciMessage, state: &UciState) {
+    let chess960 = state.get_check_option(UciOptions::Chess960);
+    if let Some(Err(e)) = apply_position_message(board, msg, chess960) {
+        warn!("Rejected position command: {e}");
+        send_info("string", format!("error: {e}"));
+    }
+}
+
+/// Load the book selected by the `BookFile` option, either the built-in book or a Polyglot file.
+fn load_opening_book(state: &UciState) -> Result<Box<dyn OpeningBook + Send>, String> {
+    match state.get_option(UciOptions::BookFile) {
+        Some(path) if path != BUILTIN_BOOK && !path.is_empty() => {
+            load_book(&path).map_err(|e| format!("couldn't load book '{path}': {e}"))
+        }
+        _ => Ok(Box::new(DefaultOpeningBook::initialise())),
+    }
+}
+
+/// Load the tablebases in the `SyzygyPath` option, or none if it is empty.
+fn load_tablebases(state: &UciState) -> Result<Option<Arc<Tablebases>>, String> {
+    match state.get_option(UciOptions::SyzygyPath) {
+        Some(path) if !path.is_empty() => Tablebases::load(&path)
+            .map(|tablebases| Some(Arc::new(tablebases)))
+            .map_err(|e| format!("couldn't load tablebases from '{path}': {e}")),
+        _ => Ok(None),
+    }
+}
+
+fn book_selector(state: &UciState) -> BookSelector {
+    let strategy = state
+        .get_combo_option(UciOptions::BookStrategy)
+        .and_then(|name| BookStrategy::from_name(&name))
+        .unwrap_or(BookStrategy::Weighted);
+    let seed = state.get_spin_option(UciOptions::BookSeed);
+
+    let mut selector = BookSelector::new(strategy, (seed != 0).then_some(seed as u64));
+    selector.min_weight = state.get_spin_option(UciOptions::BookMinWeight) as u32;
+    selector.min_percentage = state.get_spin_option(UciOptions::BookMinPercent) as f64;
+    selector.top_n = match state.get_spin_option(UciOptions::BookTopN) {
+        0 => None,
+        n => Some(n as usize),
+    };
+    selector.max_ply = match state.get_spin_option(UciOptions::BookMaxPly) {
+        0 => None,
+        n => Some(n as u32),
+    };
+    // Learned results are reread every game, as they are updated outside the engine
+    selector.learning = match state.get_option(UciOptions::BookLearnFile) {
+        Some(path) if !path.is_empty() => match BookLearning::load(&path) {
+            Ok(learning) => {
+                info!("Loaded {} learned book moves from '{path}'", learning.len());
+                Some(learning)
+            }
+            Err(e) => {
+                warn!("couldn't load learn file '{path}': {e}");
+                send_info(
+                    "string",
+                    format!("error: couldn't load learn file '{path}': {e}"),
+                );
+                None
+            }
+        },
+        _ => None,
+    };
+    selector
+}
+
 static RUNNING: AtomicBool = AtomicBool::new(false);
 static SHOULD_STOP: AtomicBool = AtomicBool::new(false);
 
@@ -89,8 +162,9 @@ fn main() {
     let mut state = UciState::new();
     let mut board = Board::starting();
     let cache = Arc::new(Mutex::new(InterMoveCache::new()));
-    let opening_book: Arc<Mutex<DefaultOpeningBook>> =
-        Arc::new(Mutex::new(DefaultOpeningBook::initialise()));
+    let mut tablebases: Option<Arc<Tablebases>> = None;
+    let opening_book: Arc<Mutex<Box<dyn OpeningBook + Send>>> =
+        Arc::new(Mutex::new(Box::new(DefaultOpeningBook::initialise())));
 
     let info_text = format!(
         "Beans Gambit UCI v{} [Bot v{} | Chess Lib v{} | {} cache]",
@@ -135,10 +209,64 @@ fn main() {
                 send_uci(UciMessage::ReadyOk);
             }
             UciMessage::SetOption { name, value } => {
-                if let Some(value) = value {
-                    state.set_option_named(name, value).ok();
+                let option = if let Some(value) = value {
+                    state.set_option_named(name, value).ok()
                 } else {
-                    state.unset_option_named(name).ok();
+                    state.unset_option_named(name).ok()
+                };
+
+                if matches!(
+                    option,
+                    Some(
+                        UciOptions::BookStrategy
+                            | UciOptions::BookSeed
+                            | UciOptions::BookMinWeight
+                            | UciOptions::BookMinPercent
+                            | UciOptions::BookTopN
+                            | UciOptions::BookMaxPly
+                            | UciOptions::BookLearnFile
+                    )
+                ) {
+                    cache
+                        .lock()
+                        .unwrap()
+                        .set_book_selector(book_selector(&state));
+                }
+
+                if option == Some(UciOptions::BookFile) {
+                    match load_opening_book(&state) {
+                        Ok(book) => {
+                            info!("{}", book.statistics());
+                            send_info("string", book.statistics());
+                            *opening_book.lock().unwrap() = book;
+                        }
+                        Err(e) => {
+                            warn!("{e}");
+                            send_info("string", format!("error: {e}"));
+                        }
+                    }
+                }
+
+                if option == Some(UciOptions::SyzygyPath) {
+                    tablebases = match load_tablebases(&state) {
+                        Ok(tablebases) => {
+                            if let Some(tablebases) = &tablebases {
+                                let found = format!(
+                                    "found tablebases for up to {} pieces",
+                                    tablebases.max_pieces()
+                                );
+                                info!("{found}");
+                                send_info("string", found);
+                            }
+                            tablebases
+                        }
+                        Err(e) => {
+                            warn!("{e}");
+                            send_info("string", format!("error: {e}"));
+                            None
+                        }
+                    };
+                    cache.lock().unwrap().set_tablebases(tablebases.clone());
                 }
             }
             UciMessage::Register { later, name, code } => {
@@ -146,23 +274,14 @@ fn main() {
             }
             UciMessage::UciNewGame => {
                 // TODO
-                *cache.lock().unwrap() = InterMoveCache::new();
+                let mut new_cache = InterMoveCache::new();
+                // Reseeded every game, so games with a fixed seed are reproducible
+                new_cache.set_book_selector(book_selector(&state));
+                new_cache.set_tablebases(tablebases.clone());
+                *cache.lock().unwrap() = new_cache;
             }
-            UciMessage::Position {
-                startpos,
-                fen,
-                moves,
-            } => {
-                if startpos {
-                    board = Board::starting();
-                } else {
-                    board = Board::from_fen(fen.unwrap().as_str()).unwrap();
-                }
-                for m in moves {
-                    let m = from_uci_move(m);
-                    // TODO: Any move validation?
-                    board.make_move(m);
-                }
+            UciMessage::Position { .. } => {
+                update_position(&mut board, &msg, &state);
             }
             UciMessage::Go {
                 time_control,
@@ -204,23 +323,33 @@ fn main() {
 
                 let cache = cache.clone();
                 let opening_book = opening_book.clone();
+                let own_book = state.get_check_option(UciOptions::OwnBook);
                 let board = board.clone();
                 thread::spawn(move || {
                     let cache = cache;
                     let opening_book = opening_book;
                     let mut c = cache.lock().unwrap();
                     let mut board = board;
-                    let (best_move, eval) = search(
+                    let book = own_book.then(|| opening_book.lock().unwrap());
+                    let outcome = search(
                         &mut board,
                         &mut c,
                         || SHOULD_STOP.load(Ordering::Acquire),
                         time_remaining,
-                        Some(opening_book.lock().unwrap().deref()),
+                        book.as_deref()
+                            .map(|book| book.as_ref() as &dyn OpeningBook),
                     );
-                    let best_move = best_move.unwrap();
+                    drop(book);
+                    let best_move = outcome.best_move.unwrap();
 
-                    // fastchess requires at least one info message with score
-                    send_uci(UciMessage::Info(vec![match eval {
+                    // fastchess requires at least one info message with score. Book moves
+                    // aren't searched, so are reported at depth 0 with a score of 0.
+                    let mut info = Vec::new();
+                    if outcome.book {
+                        send_info("string", "book move");
+                        info.push(UciInfoAttribute::Depth(0));
+                    }
+                    info.push(match outcome.score {
                         Score::PositiveMateIn(pmi) => UciInfoAttribute::Score {
                             cp: None,
                             mate: Some(pmi as i8),
@@ -239,7 +368,8 @@ fn main() {
                             lower_bound: None,
                             upper_bound: None,
                         },
-                    }]));
+                    });
+                    send_uci(UciMessage::Info(info));
 
                     send_uci(UciMessage::BestMove {
                         best_move: UciMove {
@@ -272,6 +402,9 @@ fn main() {
                 info!("Exiting on UCI Quit command");
                 exit(0);
             }
+            UciMessage::Unknown(ref command, _) if command.trim_start().starts_with("position") => {
+                update_position(&mut board, &msg, &state);
+            }
             UciMessage::Unknown(_, _) => {
                 warn!("Unknown UCI message: {:?}", msg);
             }
@@ -0,0 +1,186 @@
+use crate::uci_conversions::from_uci_move;
+use chess_lib::board::Board;
+use std::fmt::{Display, Formatter};
+use vampirc_uci::{UciFen, UciMessage, UciMove};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PositionError {
+    /// The command couldn't be parsed at all.
+    Malformed(String),
+    InvalidFen(String),
+    IllegalMove {
+        mv: String,
+        fen: String,
+    },
+}
+
+impl Display for PositionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PositionError::Malformed(command) => write!(f, "could not parse '{command}'"),
+            PositionError::InvalidFen(message) => write!(f, "{message}"),
+            PositionError::IllegalMove { mv, fen } => {
+                write!(f, "illegal move {mv} in position {fen}")
+            }
+        }
+    }
+}
+
+/// Build the board for a UCI `position` command, checking the FEN and every move.
+pub fn board_for_position(
+    startpos: bool,
+    fen: Option<&UciFen>,
+    moves: &[UciMove],
+    chess960: bool,
+) -> Result<Board, PositionError> {
+    let mut board = match (startpos, fen) {
+        (true, _) => Board::starting(),
+        (false, Some(fen)) => Board::from_fen(fen.as_str()).map_err(|e| {
+            PositionError::InvalidFen(format!("invalid fen: {} - {e}", fen.as_str()))
+        })?,
+        (false, None) => {
+            return Err(PositionError::Malformed(
+                "position without startpos or fen".to_string(),
+            ));
+        }
+    };
+
+    // Castling is sent and received as king-takes-rook in Chess960 mode
+    if chess960 {
+        board.set_chess960(true);
+    }
+
+    for &m in moves {
+        let m = from_uci_move(m);
+        let Some(m) = board.normalise_move(m) else {
+            return Err(PositionError::IllegalMove {
+                mv: m.as_uci(),
+                fen: board.to_fen(),
+            });
+        };
+        board.make_move(m);
+    }
+
+    Ok(board)
+}
+
+/// Update `board` for a `position` command, including one that failed to parse. Returns None for
+/// any other message. On error the board is left unchanged, so the engine keeps its last valid
+/// position.
+pub fn apply_position_message(
+    board: &mut Board,
+    msg: &UciMessage,
+    chess960: bool,
+) -> Option<Result<(), PositionError>> {
+    match msg {
+        UciMessage::Position {
+            startpos,
+            fen,
+            moves,
+        } => Some(
+            board_for_position(*startpos, fen.as_ref(), moves, chess960)
+                .map(|new_board| *board = new_board),
+        ),
+        UciMessage::Unknown(command, _) if command.trim_start().starts_with("position") => {
+            Some(Err(PositionError::Malformed(command.trim().to_string())))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vampirc_uci::parse_one;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    /// Feed a stream of commands, returning the final position and any errors.
+    fn feed(lines: &[&str]) -> (Board, Vec<PositionError>) {
+        let mut board = Board::starting();
+        let mut errors = Vec::new();
+
+        for line in lines {
+            if let Some(Err(e)) = apply_position_message(&mut board, &parse_one(line), false) {
+                errors.push(e);
+            }
+        }
+
+        (board, errors)
+    }
+
+    #[test]
+    fn test_valid_positions() {
+        let (board, errors) = feed(&["position startpos moves e2e4 e7e5"]);
+        assert!(errors.is_empty());
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2"
+        );
+
+        // Castling as the king moving onto its rook
+        let (board, errors) =
+            feed(&["position fen r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1h1"]);
+        assert!(errors.is_empty());
+        assert_eq!(board.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+    }
+
+    #[test]
+    fn test_illegal_moves_keep_last_position() {
+        let (board, errors) = feed(&[
+            "position startpos moves e2e4",
+            "position startpos moves e2e4 e2e4",
+            "position startpos moves e3e4",
+            "position startpos moves e2e4 e7e5 e1e8",
+        ]);
+
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert_eq!(
+            errors,
+            [
+                PositionError::IllegalMove {
+                    mv: "e2e4".to_string(),
+                    fen: "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".to_string(),
+                },
+                PositionError::IllegalMove {
+                    mv: "e3e4".to_string(),
+                    fen: START.to_string(),
+                },
+                PositionError::IllegalMove {
+                    mv: "e1e8".to_string(),
+                    fen: "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_broken_fens_keep_last_position() {
+        let (board, errors) = feed(&[
+            "position fen 8/8/8/8/8/8/8/8 w - - 0 1",
+            "position fen 4k3/8/8/8/8/8/8/4KK2 w - - 0 1",
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "position fen 8/8/8/8/8/8/8/8 w - - 0 1 moves e2e4",
+        ]);
+
+        assert_eq!(board.to_fen(), START);
+        assert_eq!(errors.len(), 5);
+    }
+
+    #[test]
+    fn test_malformed_commands() {
+        let (board, errors) = feed(&[
+            "position",
+            "position moves e2e4",
+            "position startpos moves e2e4 zz",
+            "isready",
+        ]);
+
+        assert_eq!(board.to_fen(), START);
+        assert_eq!(errors.len(), 3);
+    }
+}
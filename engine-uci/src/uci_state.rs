@@ -1,3 +1,4 @@
+use opening_book::BookStrategy;
 use std::collections::HashMap;
 use strum::IntoEnumIterator;
 use strum_macros::{AsRefStr, EnumIter};
@@ -6,21 +7,103 @@ use vampirc_uci::UciOptionConfig;
 #[derive(Debug, EnumIter, AsRefStr, Eq, PartialEq, Hash, Copy, Clone)]
 pub enum UciOptions {
     Example,
+    Chess960,
+    OwnBook,
+    BookFile,
+    BookStrategy,
+    BookSeed,
+    BookMinWeight,
+    BookMinPercent,
+    BookTopN,
+    BookMaxPly,
+    BookLearnFile,
+    SyzygyPath,
 }
 
+/// Value of `BookFile` that selects the book built into the binary.
+pub const BUILTIN_BOOK: &str = "<builtin>";
+
 impl UciOptions {
     pub fn get_type(&self) -> UciOptionConfig {
         match self {
             UciOptions::Example => UciOptionConfig::Button {
                 name: "Example".to_string(),
             },
+            UciOptions::Chess960 => UciOptionConfig::Check {
+                name: "UCI_Chess960".to_string(),
+                default: Some(false),
+            },
+            // Disabled to measure the search without the book
+            UciOptions::OwnBook => UciOptionConfig::Check {
+                name: "OwnBook".to_string(),
+                default: Some(true),
+            },
+            UciOptions::BookFile => UciOptionConfig::String {
+                name: "BookFile".to_string(),
+                default: Some(BUILTIN_BOOK.to_string()),
+            },
+            UciOptions::BookStrategy => UciOptionConfig::Combo {
+                name: "BookStrategy".to_string(),
+                default: Some(BookStrategy::Weighted.name().to_string()),
+                var: BookStrategy::ALL
+                    .iter()
+                    .map(|strategy| strategy.name().to_string())
+                    .collect(),
+            },
+            // 0 seeds the book's RNG randomly
+            UciOptions::BookSeed => UciOptionConfig::Spin {
+                name: "BookSeed".to_string(),
+                default: Some(0),
+                min: Some(0),
+                max: Some(i64::MAX),
+            },
+            UciOptions::BookMinWeight => UciOptionConfig::Spin {
+                name: "BookMinWeight".to_string(),
+                default: Some(0),
+                min: Some(0),
+                max: Some(u16::MAX as i64),
+            },
+            UciOptions::BookMinPercent => UciOptionConfig::Spin {
+                name: "BookMinPercent".to_string(),
+                default: Some(0),
+                min: Some(0),
+                max: Some(100),
+            },
+            // 0 considers every book move
+            UciOptions::BookTopN => UciOptionConfig::Spin {
+                name: "BookTopN".to_string(),
+                default: Some(0),
+                min: Some(0),
+                max: Some(u8::MAX as i64),
+            },
+            // 0 uses the book for the whole game
+            UciOptions::BookMaxPly => UciOptionConfig::Spin {
+                name: "BookMaxPly".to_string(),
+                default: Some(0),
+                min: Some(0),
+                max: Some(1000),
+            },
+            // Empty disables book learning
+            UciOptions::BookLearnFile => UciOptionConfig::String {
+                name: "BookLearnFile".to_string(),
+                default: Some(String::new()),
+            },
+            // Directories of Syzygy tables, separated as in PATH. Empty disables tablebases
+            UciOptions::SyzygyPath => UciOptionConfig::String {
+                name: "SyzygyPath".to_string(),
+                default: Some(String::new()),
+            },
         }
     }
 
+    /// Option names are case-insensitive.
     pub fn from_string<S: AsRef<str>>(s: S) -> Option<UciOptions> {
-        let lower = s.as_ref().to_ascii_lowercase();
-
-        UciOptions::iter().find(|&option| option.get_type().get_name() == lower)
+        UciOptions::iter().find(|&option| {
+            option
+                .get_type()
+                .get_name()
+                .eq_ignore_ascii_case(s.as_ref())
+        })
     }
 }
 
@@ -91,6 +174,49 @@ impl UciState {
     pub fn get_option(&self, option: UciOptions) -> Option<String> {
         self.options.get(&option).cloned()
     }
+
+    /// The value of a check option, or its default if unset.
+    pub fn get_check_option(&self, option: UciOptions) -> bool {
+        match self.options.get(&option) {
+            Some(value) => value.eq_ignore_ascii_case("true"),
+            None => matches!(
+                option.get_type(),
+                UciOptionConfig::Check {
+                    default: Some(true),
+                    ..
+                }
+            ),
+        }
+    }
+
+    /// The value of a spin option clamped to its range, or its default if unset or invalid.
+    pub fn get_spin_option(&self, option: UciOptions) -> i64 {
+        let UciOptionConfig::Spin {
+            default, min, max, ..
+        } = option.get_type()
+        else {
+            panic!("{option:?} is not a spin option");
+        };
+
+        self.options
+            .get(&option)
+            .and_then(|value| value.trim().parse::<i64>().ok())
+            .map(|value| value.clamp(min.unwrap_or(i64::MIN), max.unwrap_or(i64::MAX)))
+            .or(default)
+            .unwrap_or(0)
+    }
+
+    /// The value of a combo option if it is one of the choices, otherwise its default.
+    pub fn get_combo_option(&self, option: UciOptions) -> Option<String> {
+        let UciOptionConfig::Combo { default, var, .. } = option.get_type() else {
+            panic!("{option:?} is not a combo option");
+        };
+
+        self.options
+            .get(&option)
+            .and_then(|value| var.iter().find(|v| v.eq_ignore_ascii_case(value)).cloned())
+            .or(default)
+    }
 }
 
 // static SLOW_GLOBAL_STATE: OnceLock<RwLock<UciState>> = OnceLock::new();
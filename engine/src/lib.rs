@@ -5,26 +5,42 @@ mod constant_heuristics;
 pub(crate) mod eval;
 mod minimax;
 pub mod results;
+pub mod syzygy;
 mod tt;
 
 use crate::minimax::{TimeManagementStrat, search_minimax};
-use crate::results::Score;
+use crate::results::{Score, SearchOutcome};
+use crate::syzygy::Tablebases;
 use crate::tt::TranspositionTable;
 use chess_lib::board::{Board, Move};
 use log::info;
-use opening_book::OpeningBook;
+use opening_book::{BookSelector, OpeningBook};
 use std::cmp::min;
+use std::sync::Arc;
 use std::time::Duration;
 
 pub const fn version() -> &'static str {
     env!("CARGO_PKG_VERSION")
 }
 
+/// Positions past this ply aren't looked up in the opening book.
+const BOOK_MAX_PLY: u32 = 60;
+/// Positions with fewer pieces than this aren't looked up in the opening book.
+const BOOK_MIN_PIECES: usize = 20;
+
+/// Cheap check for whether a position could still be in the opening book, so the book isn't
+/// consulted for the rest of the game. Games can transpose back into the book, so it is checked on
+/// every move before this.
+fn could_be_in_book(board: &Board) -> bool {
+    board.ply() <= BOOK_MAX_PLY && board.pieces().iter().count() >= BOOK_MIN_PIECES
+}
+
 pub struct InterMoveCache {
     // (depth searched, eval)
     // Evals from perspective of white
     pub(crate) transposition_table: TranspositionTable,
-    pub(crate) left_opening_book: bool,
+    pub(crate) book_selector: BookSelector,
+    pub(crate) tablebases: Option<Arc<Tablebases>>,
 }
 
 impl Default for InterMoveCache {
@@ -37,22 +53,53 @@ impl InterMoveCache {
     pub fn new() -> InterMoveCache {
         InterMoveCache {
             transposition_table: TranspositionTable::new(),
-            left_opening_book: false,
+            book_selector: BookSelector::default(),
+            tablebases: None,
         }
     }
 
+    /// How book moves are chosen for the rest of the game.
+    pub fn set_book_selector(&mut self, book_selector: BookSelector) {
+        self.book_selector = book_selector;
+    }
+
+    /// Endgame tablebases probed by the search, shared as they are slow to load.
+    pub fn set_tablebases(&mut self, tablebases: Option<Arc<Tablebases>>) {
+        self.tablebases = tablebases;
+    }
+
     pub fn size_bytes(&self) -> usize {
         self.transposition_table.size_bytes()
     }
 }
 
+/// Limits on a search, any of which ends it. Without a time limit the search continues until
+/// another limit is reached or it is stopped.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    pub time: Option<Duration>,
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+}
+
+/// The result of each completed iteration of iterative deepening.
+#[derive(Clone, Copy, Debug)]
+pub struct SearchProgress {
+    pub depth: u8,
+    pub best_move: Option<Move>,
+    pub score: Score,
+    /// Nodes searched since the start of the search.
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
 pub fn search(
     board: &mut Board,
     cache: &mut InterMoveCache,
     stop_fn: fn() -> bool,
     time_remaining: Duration,
     opening_book: Option<&dyn OpeningBook>,
-) -> (Option<Move>, Score) {
+) -> SearchOutcome {
     let target_move_time = min(Duration::from_secs(20), time_remaining / 10);
     let time_management_strat = TimeManagementStrat::TargetLimit;
     info!(
@@ -62,22 +109,234 @@ pub fn search(
         time_management_strat
     );
 
-    if !cache.left_opening_book
+    if could_be_in_book(board)
         && let Some(opening_book) = opening_book
+        && let Some(mv) = cache.book_selector.select(opening_book, board)
     {
-        if let Some(mv) = opening_book.get_weighted(board.hash()) {
-            info!("Playing book move {:?}", mv);
-            return (Some(mv), Score::ZERO);
-        } else {
-            cache.left_opening_book = true;
-        }
+        info!("Playing book move {:?}", mv);
+        return SearchOutcome {
+            best_move: Some(mv),
+            score: Score::ZERO,
+            book: true,
+        };
     }
 
-    search_minimax(
+    if let Some(tablebases) = &cache.tablebases
+        && let Some((mv, score)) = tablebases.probe_root(board)
+    {
+        info!("Playing tablebase move {:?}", mv);
+        return SearchOutcome {
+            best_move: Some(mv),
+            score,
+            book: false,
+        };
+    }
+
+    let (best_move, score) = search_minimax(
         board,
         cache,
         stop_fn,
-        target_move_time,
+        SearchLimits {
+            time: Some(target_move_time),
+            ..SearchLimits::default()
+        },
         time_management_strat,
+        &mut |_| {},
+    );
+    SearchOutcome {
+        best_move,
+        score,
+        book: false,
+    }
+}
+
+/// Search without the opening book or a tablebase move at the root, stopping strictly at the
+/// given limits. `on_progress` is called after every completed depth.
+pub fn search_with_limits(
+    board: &mut Board,
+    cache: &mut InterMoveCache,
+    stop_fn: fn() -> bool,
+    limits: SearchLimits,
+    on_progress: &mut dyn FnMut(&SearchProgress),
+) -> (Option<Move>, Score) {
+    info!("FEN {} | Limits {:?}", board.to_fen(), limits);
+
+    search_minimax(
+        board,
+        cache,
+        stop_fn,
+        limits,
+        TimeManagementStrat::StrictLimit,
+        on_progress,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syzygy::{TB_WIN, TEST_TABLES};
+    use chess_lib::movegen::{MoveList, compute_legal_moves};
+    use opening_book::{PolyglotBook, PolyglotEntry};
+
+    /// A book with only 1. e4 from the starting position.
+    fn book() -> PolyglotBook {
+        let start = Board::starting();
+        let entry = PolyglotEntry {
+            key: start.polyglot_key().u64(),
+            mv: PolyglotEntry::encode_move(&start, Move::from_uci("e2e4").unwrap()),
+            weight: 1,
+            learn: 0,
+        };
+        PolyglotBook::from_bytes(&entry.to_bytes()).unwrap()
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn test_could_be_in_book() {
+        assert!(could_be_in_book(&Board::starting()));
+
+        // Ply 60 is the last looked up
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+        assert!(could_be_in_book(&board(&format!("{start} w KQkq - 0 31"))));
+        assert!(!could_be_in_book(&board(&format!("{start} b KQkq - 0 31"))));
+
+        // 20 pieces is the fewest looked up
+        assert!(could_be_in_book(&board(
+            "r3k3/pppppppp/8/8/8/8/PPPPPPPP/R3K3 w - - 0 1"
+        )));
+        assert!(!could_be_in_book(&board(
+            "4k3/pppppppp/8/8/8/8/PPPPPPPP/R3K3 w - - 0 1"
+        )));
+    }
+
+    #[test]
+    fn test_search_book() {
+        let book = book();
+        let search = |board: &mut Board, book: Option<&dyn OpeningBook>| {
+            search(
+                board,
+                &mut InterMoveCache::new(),
+                || false,
+                Duration::from_millis(500),
+                book,
+            )
+        };
+
+        let outcome = search(&mut Board::starting(), Some(&book));
+        assert!(outcome.book);
+        assert_eq!(outcome.best_move, Move::from_uci("e2e4"));
+
+        let outcome = search(&mut Board::starting(), None);
+        assert!(!outcome.book);
+        assert!(outcome.best_move.is_some());
+
+        // Out of the book, or in it but too late in the game to look up
+        let mut after_d4 = Board::starting();
+        after_d4.make_move(Move::from_uci("d2d4").unwrap());
+        assert!(!search(&mut after_d4, Some(&book)).book);
+        let mut board = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 40");
+        assert!(!search(&mut board, Some(&book)).book);
+    }
+
+    /// Depths and node counts reported by a search of the starting position.
+    fn search_progress(limits: SearchLimits) -> (Option<Move>, Vec<(u8, u64)>) {
+        let mut progress = Vec::new();
+        let (best_move, _) = search_with_limits(
+            &mut Board::starting(),
+            &mut InterMoveCache::new(),
+            || false,
+            limits,
+            &mut |p| progress.push((p.depth, p.nodes)),
+        );
+        (best_move, progress)
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        for depth in 1..=4 {
+            let (best_move, progress) = search_progress(SearchLimits {
+                depth: Some(depth),
+                ..SearchLimits::default()
+            });
+            assert!(best_move.is_some());
+            assert_eq!(progress.last().unwrap().0, depth);
+            assert!(progress.is_sorted_by(|a, b| a.0 < b.0 && a.1 < b.1));
+        }
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let (best_move, progress) = search_progress(SearchLimits {
+            nodes: Some(20000),
+            ..SearchLimits::default()
+        });
+        assert!(best_move.is_some());
+        assert!(progress.len() >= 2);
+        assert!(progress.is_sorted_by(|a, b| a.0 < b.0 && a.1 < b.1));
+        assert!(progress.iter().all(|&(_, nodes)| nodes <= 20000));
+
+        // A limit too small to finish a depth still gives a move
+        let (best_move, progress) = search_progress(SearchLimits {
+            nodes: Some(5),
+            ..SearchLimits::default()
+        });
+        assert!(best_move.is_some());
+        assert!(progress.is_empty());
+    }
+
+    fn tablebase_cache() -> InterMoveCache {
+        let mut cache = InterMoveCache::new();
+        cache.set_tablebases(Some(Arc::new(Tablebases::load(TEST_TABLES).unwrap())));
+        cache
+    }
+
+    #[test]
+    fn test_search_tablebase_root() {
+        // Both sides play tablebase moves, so KRvK is mated well within the fifty move rule
+        let mut board = board("8/8/8/3k4/8/8/8/R3K3 w - - 0 1");
+        let mut cache = tablebase_cache();
+        for _ in 0..100 {
+            let mut moves = MoveList::new();
+            if compute_legal_moves(&mut moves, &board) && moves.is_empty() {
+                assert!(!board.color_to_move().is_white());
+                return;
+            }
+            let outcome = search(&mut board, &mut cache, || false, Duration::MAX, None);
+            let winning = board.color_to_move().is_white();
+            assert_eq!(
+                outcome.score,
+                Score::Score(if winning { TB_WIN } else { -TB_WIN })
+            );
+            board.make_move(outcome.best_move.unwrap());
+            assert!(board.halfmoves_since_event() < 100);
+        }
+        panic!("not mated in 100 plies");
+    }
+
+    #[test]
+    fn test_search_tablebase_cutoff() {
+        let search = |fen: &str, cache: &mut InterMoveCache| {
+            let limits = SearchLimits {
+                depth: Some(1),
+                ..SearchLimits::default()
+            };
+            search_with_limits(&mut board(fen), cache, || false, limits, &mut |_| {})
+        };
+
+        // Taking the undefended rook is scored by the tablebase rather than the eval
+        let undefended = "8/6k1/8/3r4/8/8/8/K2R4 w - - 0 1";
+        let (best_move, score) = search(undefended, &mut tablebase_cache());
+        assert_eq!(best_move, Move::from_uci("d1d5"));
+        assert_eq!(score, Score::Score(TB_WIN));
+
+        // Taking the defended rook only wins material at depth 1 without the tablebase
+        let defended = "8/8/4k3/3r4/8/8/8/K2R4 w - - 0 1";
+        let (best_move, _) = search(defended, &mut InterMoveCache::new());
+        assert_eq!(best_move, Move::from_uci("d1d5"));
+        let (best_move, _) = search(defended, &mut tablebase_cache());
+        assert_ne!(best_move, Move::from_uci("d1d5"));
+    }
+}
@@ -2,12 +2,13 @@ use crate::eval::eval;
 use crate::minimax::TimeManagementStrat::StrictLimit;
 use crate::results::{Score, SearchResult};
 use crate::tt::{TTEntry, TTEntryType};
-use crate::{InterMoveCache, results};
+use crate::{InterMoveCache, SearchLimits, SearchProgress, results};
 use chess_lib::board::{Board, Move};
 use chess_lib::movegen::{MoveList, compute_legal_moves};
 use log::{debug, info, log};
 #[cfg(debug_assertions)]
 use std::backtrace::Backtrace;
+use std::cell::Cell;
 use std::cmp::PartialOrd;
 use std::time::{Duration, Instant};
 
@@ -71,6 +72,15 @@ fn minimax_inner<F>(
 where
     F: Fn() -> bool,
 {
+    // Tablebase results only hold from a capture or pawn move, as the fifty move count is unknown
+    if !toplevel
+        && board.halfmoves_since_event() == 0
+        && let Some(tablebases) = &cache.tablebases
+        && let Some(score) = tablebases.probe_wdl(board)
+    {
+        return (SearchResult::normal(score), MoveType::Eval);
+    }
+
     if depth_remaining == 0 {
         let score = eval(board);
 
@@ -244,15 +254,21 @@ pub fn search_minimax(
     board: &mut Board,
     cache: &mut InterMoveCache,
     stop_fn: fn() -> bool,
-    target_move_time: Duration,
+    limits: SearchLimits,
     time_management_strat: TimeManagementStrat,
+    on_progress: &mut dyn FnMut(&SearchProgress),
 ) -> (Option<Move>, Score) {
-    let limit = Instant::now() + target_move_time;
-
-    let mm_stop_fn: Box<dyn Fn() -> bool> = if time_management_strat == StrictLimit {
-        Box::new(move || -> bool { stop_fn() || Instant::now() > limit })
-    } else {
-        Box::new(move || -> bool { stop_fn() })
+    let search_start = Instant::now();
+    let limit = limits.time.map(|t| search_start + t);
+
+    // Every move made by the search is counted as a node
+    let nodes = Cell::new(0u64);
+    let mm_stop_fn = || -> bool {
+        nodes.set(nodes.get() + 1);
+        stop_fn()
+            || limits.nodes.is_some_and(|n| nodes.get() > n)
+            || (time_management_strat == StrictLimit
+                && limit.is_some_and(|limit| Instant::now() > limit))
     };
 
     let mut options = MoveList::new();
@@ -262,9 +278,10 @@ pub fn search_minimax(
     let mut eval_after_move = eval(board);
     board.unmake_last_move(um);
 
-    let mut search_depth: u8 = 2; // Keep even to eval on our turn
+    let max_depth = limits.depth.unwrap_or(250);
+    let mut search_depth: u8 = max_depth.clamp(1, 2); // Keep even to eval on our turn
 
-    while search_depth < 250 {
+    while search_depth <= max_depth && search_depth < 250 {
         info!("Starting search at depth {search_depth}");
 
         let start = Instant::now();
@@ -296,9 +313,18 @@ pub fn search_minimax(
         debug!("Minimax Result {:#?} | {:?}", sr, best_move);
         eval_after_move = sr.score;
 
+        on_progress(&SearchProgress {
+            depth: search_depth,
+            best_move,
+            score: eval_after_move,
+            nodes: nodes.get(),
+            elapsed: search_start.elapsed(),
+        });
+
         // Assume next iteration will take 600x current iteration
         const ITERATION_COST_FACTOR: u32 = 10;
-        if Instant::now() + (time_taken * ITERATION_COST_FACTOR) > limit {
+        if limit.is_some_and(|limit| Instant::now() + (time_taken * ITERATION_COST_FACTOR) > limit)
+        {
             info!("Next depth expected to take to long");
             break;
         }
@@ -1,3 +1,4 @@
+use chess_lib::board::Move;
 #[cfg(debug_assertions)]
 use std::backtrace::Backtrace;
 use std::cmp::Ordering;
@@ -60,6 +61,16 @@ impl Neg for Score {
     }
 }
 
+/// The move chosen by `search`.
+#[derive(Clone, Copy, Debug)]
+pub struct SearchOutcome {
+    pub best_move: Option<Move>,
+    /// Score from the perspective of the side to move. Zero for book moves, which aren't searched.
+    pub score: Score,
+    /// Whether the move came from the opening book.
+    pub book: bool,
+}
+
 #[derive(Debug)]
 pub(crate) struct SearchResult {
     pub score: Score,
@@ -0,0 +1,163 @@
+use crate::results::Score;
+use chess_lib::board::{Board, Move};
+use shakmaty::fen::Fen;
+use shakmaty::uci::UciMove;
+use shakmaty::{CastlingMode, Chess};
+use shakmaty_syzygy::{AmbiguousWdl, Tablebase, Wdl};
+use std::ffi::OsStr;
+use std::io;
+
+/// Score of a position the tablebases say is won. Above any material eval, below any mate.
+pub const TB_WIN: f32 = 1000.0;
+
+/// Syzygy endgame tablebases. WDL tables score positions in the search, DTZ tables choose moves
+/// at the root.
+pub struct Tablebases {
+    tables: Tablebase<Chess>,
+}
+
+impl Tablebases {
+    /// Load every table in `paths`, a list of directories separated as in `PATH`.
+    pub fn load<P: AsRef<OsStr> + ?Sized>(paths: &P) -> io::Result<Tablebases> {
+        let mut tables = Tablebase::new();
+        for directory in std::env::split_paths(paths) {
+            tables.add_directory(directory)?;
+        }
+        Ok(Tablebases { tables })
+    }
+
+    /// Most pieces in a position covered by the loaded tables, 0 if none were found.
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    /// The position in shakmaty, if it is small enough to be in the tables.
+    fn position(&self, board: &Board) -> Option<Chess> {
+        if board.pieces().iter().count() > self.max_pieces() {
+            return None;
+        }
+        Fen::from_ascii(board.to_fen().as_bytes())
+            .ok()?
+            .into_position(CastlingMode::Standard)
+            .ok()
+    }
+
+    /// Score for the side to move of a position just after a capture or pawn move, or `None` if
+    /// it isn't in the tables. Cursed wins and blessed losses are draws by the fifty move rule.
+    pub fn probe_wdl(&self, board: &Board) -> Option<Score> {
+        let position = self.position(board)?;
+        let wdl = self.tables.probe_wdl_after_zeroing(&position).ok()?;
+        Some(wdl_score(wdl))
+    }
+
+    /// The move keeping the best result while making progress under the fifty move rule, with the
+    /// score of the position for the side to move. `None` if the position isn't in the tables.
+    pub fn probe_root(&self, board: &Board) -> Option<(Move, Score)> {
+        let position = self.position(board)?;
+        let wdl = match self.tables.probe_wdl(&position).ok()? {
+            AmbiguousWdl::Win => Wdl::Win,
+            AmbiguousWdl::Loss => Wdl::Loss,
+            // Too close to the fifty move rule to know, so assume it is reached
+            _ => Wdl::Draw,
+        };
+        let (mv, _) = self.tables.best_move(&position).ok()??;
+        let mv = Move::from_uci(&UciMove::from_standard(mv).to_string())?;
+        Some((board.normalise_move(mv)?, wdl_score(wdl)))
+    }
+}
+
+fn wdl_score(wdl: Wdl) -> Score {
+    match wdl {
+        Wdl::Win => Score::Score(TB_WIN),
+        Wdl::Loss => Score::Score(-TB_WIN),
+        Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => Score::ZERO,
+    }
+}
+
+/// The 3 and 4 piece tables used by the tests.
+#[cfg(test)]
+pub(crate) const TEST_TABLES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn test_load() {
+        assert_eq!(Tablebases::load(TEST_TABLES).unwrap().max_pieces(), 4);
+        assert!(Tablebases::load("/nonexistent/syzygy").is_err());
+    }
+
+    #[test]
+    fn test_probe_wdl() {
+        let tables = Tablebases::load(TEST_TABLES).unwrap();
+        let win = Some(Score::Score(TB_WIN));
+        let loss = Some(Score::Score(-TB_WIN));
+
+        // KRvK is won for the side with the rook, whoever is to move
+        assert_eq!(
+            tables.probe_wdl(&board("8/8/4k3/8/8/8/8/K2R4 w - - 0 1")),
+            win
+        );
+        assert_eq!(
+            tables.probe_wdl(&board("8/8/4k3/8/8/8/8/K2R4 b - - 0 1")),
+            loss
+        );
+        // Unless the rook is hanging
+        assert_eq!(
+            tables.probe_wdl(&board("8/8/4k3/3R4/8/8/8/K7 b - - 0 1")),
+            Some(Score::ZERO)
+        );
+        // KPvK with the king in front of the pawn, and stalemate
+        assert_eq!(
+            tables.probe_wdl(&board("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1")),
+            loss
+        );
+        assert_eq!(
+            tables.probe_wdl(&board("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1")),
+            Some(Score::ZERO)
+        );
+
+        // Missing tables, too many pieces and castling rights aren't probed
+        assert_eq!(
+            tables.probe_wdl(&board("7n/8/4k3/8/8/8/8/KQ6 w - - 0 1")),
+            None
+        );
+        assert_eq!(
+            tables.probe_wdl(&board("8/8/4k3/8/8/8/8/KRRQ4 w - - 0 1")),
+            None
+        );
+        assert_eq!(
+            tables.probe_wdl(&board("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_probe_root() {
+        let tables = Tablebases::load(TEST_TABLES).unwrap();
+
+        // The rook is attacked, so has to move somewhere safe
+        let mut hanging = board("7K/8/8/8/8/8/2k5/1R6 w - - 0 1");
+        let (mv, score) = tables.probe_root(&hanging).unwrap();
+        assert_eq!(score, Score::Score(TB_WIN));
+        hanging.make_move(mv);
+        assert_eq!(tables.probe_wdl(&hanging), Some(Score::Score(-TB_WIN)));
+
+        // The losing side still gets a move
+        let (_, score) = tables
+            .probe_root(&board("8/8/4k3/8/8/8/8/K2R4 b - - 0 1"))
+            .unwrap();
+        assert_eq!(score, Score::Score(-TB_WIN));
+
+        // A win that can't be completed before the fifty move rule is a draw
+        let (_, score) = tables
+            .probe_root(&board("8/8/4k3/8/8/8/8/K2R4 w - - 99 80"))
+            .unwrap();
+        assert_eq!(score, Score::ZERO);
+    }
+}
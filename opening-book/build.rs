@@ -1,7 +1,7 @@
 #[path = "src/shared.rs"]
 mod shared;
 
-use chess_lib::board::{Board, BoardFile, PieceKind};
+use chess_lib::board::{Board, PieceKind};
 use std::fs;
 
 use crate::shared::{SerialisedBookMove, serialise_book_move};
@@ -140,8 +140,9 @@ pub fn explore(start: &Chess, book: &HashMap<u64, Vec<BookMove>>, book_file: &mu
         let mut pos_board =
             Board::from_fen(&Fen::from_position(&pos, shakmaty::EnPassantMode::Legal).to_string())
                 .unwrap();
+        assert_eq!(pos_board.polyglot_key().u64(), hash);
         book_file
-            .write_u64::<LittleEndian>(pos_board.hash().u64())
+            .write_u64::<LittleEndian>(pos_board.polyglot_key().u64())
             .unwrap();
 
         let mut legal_moves = Vec::new();
@@ -203,37 +204,25 @@ pub fn explore(start: &Chess, book: &HashMap<u64, Vec<BookMove>>, book_file: &mu
             }
 
             let from = convert_square(from);
-            let original_to = *to;
-            let mut to = convert_square(to);
-
-            // TODO: Remove after board is made tolerant of castling by moving king to edge
-            // Castling represented as moving to corner for some reason
-            if pos_board.pieces().get(from).unwrap().kind() == PieceKind::King
-                && let delta = (to.file().as_u8() as i8) - (from.file().as_u8() as i8)
-                && delta.abs() > 2
-            {
-                // -4 * 2 = -8
-                // -8 / 4 = -2
-                let delta = (delta * 2) / delta.abs();
-                to = chess_lib::board::Square::at(
-                    BoardFile::from_u8(((from.file().as_u8() as i8) + delta) as u8).unwrap(),
-                    to.rank(),
-                )
-            }
-
             let promotion = promotion.as_ref().map(convert_role);
             let weight = *weight;
 
+            // Polyglot castling (king onto its own rook) is stored as the king moving two files
+            let mv = pos_board
+                .normalise_move(chess_lib::board::Move {
+                    source: from,
+                    destination: convert_square(to),
+                    promotion,
+                })
+                .expect("Book move should be legal");
+            let to = mv.destination;
+
             let pre_fen = pos_board.to_fen();
-            let um = pos_board.make_move(chess_lib::board::Move {
-                source: from,
-                destination: to,
-                promotion,
-            });
+            let um = pos_board.make_move(mv);
             assert_eq!(
                 pos_board.hash(),
                 Board::from_fen(expected_fen).unwrap().hash(),
-                "\n{pre_fen}\n{from:?} {to:?}[{original_to}] {promotion:?}\n{} vs. {expected_fen}",
+                "\n{pre_fen}\n{from:?} {to:?} {promotion:?}\n{} vs. {expected_fen}",
                 pos_board.to_fen(),
             );
             pos_board.unmake_last_move(um);
@@ -257,7 +246,7 @@ fn main() {
 
     eprintln!("Running engine build.rs");
 
-    let characteristic_hash = Board::starting().hash();
+    let characteristic_hash = Board::starting().polyglot_key();
 
     fs::create_dir_all("static/gen").unwrap();
     fs::write("static/gen/hash.txt", format!("{characteristic_hash}")).unwrap();
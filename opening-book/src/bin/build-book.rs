@@ -0,0 +1,107 @@
+//! Builds an opening book from PGN files, e.g. our own engine-vs-engine games.
+//! The book is written either in our own format, which can replace `static/gen/book.bin` or be
+//! loaded with the `BookFile` option, or as a Polyglot book for use with other programs.
+
+use std::fs;
+use std::process::exit;
+
+use chess_lib::pgn::PgnReader;
+use clap::Parser;
+use clap_derive::{Parser, ValueEnum};
+use color_print::{ceprintln, cprintln};
+use opening_book::{BookBuilder, Weighting};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// Our own format, as read by `DefaultOpeningBook`.
+    Internal,
+    Polyglot,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum WeightingArg {
+    /// Number of games the move was played in.
+    Frequency,
+    /// Two points for each win and one for each draw.
+    Results,
+    /// Sum of the ratings of the players who made the move.
+    Elo,
+}
+
+#[derive(Parser)]
+#[command(version, about, long_about = Some("Builds an opening book from PGN files."))]
+struct Cli {
+    /// PGN files to read games from.
+    #[arg(required = true)]
+    pgn: Vec<String>,
+    /// File to write the book to.
+    #[arg(short, long)]
+    output: String,
+    #[arg(long, value_enum, default_value = "polyglot")]
+    format: Format,
+    /// Only add moves from the first N plies of each game.
+    #[arg(long, default_value_t = 20)]
+    max_ply: usize,
+    /// Leave out moves played in fewer games than this.
+    #[arg(long, default_value_t = 1)]
+    min_games: u32,
+    #[arg(long, value_enum, default_value = "frequency")]
+    weighting: WeightingArg,
+    /// Only add the moves of the side that won the game.
+    #[arg(long)]
+    winning_side_only: bool,
+}
+
+fn user_error(message: impl AsRef<str>) -> ! {
+    ceprintln!("<bold><red>error:</red></bold> {}", message.as_ref());
+    exit(1);
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let mut builder = BookBuilder::new()
+        .max_ply(cli.max_ply)
+        .min_games(cli.min_games)
+        .weighting(match cli.weighting {
+            WeightingArg::Frequency => Weighting::Frequency,
+            WeightingArg::Results => Weighting::Results,
+            WeightingArg::Elo => Weighting::Elo,
+        })
+        .winning_side_only(cli.winning_side_only);
+
+    let mut skipped = 0;
+    for path in &cli.pgn {
+        let text = fs::read_to_string(path)
+            .unwrap_or_else(|e| user_error(format!("couldn't read '{path}': {e}")));
+
+        for game in PgnReader::new(&text) {
+            let result = game
+                .map_err(|e| e.to_string())
+                .and_then(|game| builder.add_game(&game).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                cprintln!("<yellow>Skipping game in '{}'</yellow>: {}", path, e);
+                skipped += 1;
+            }
+        }
+    }
+
+    let bytes = match cli.format {
+        Format::Internal => builder.to_book_bytes(),
+        Format::Polyglot => builder.to_polyglot_bytes(),
+    };
+    fs::write(&cli.output, bytes)
+        .unwrap_or_else(|e| user_error(format!("couldn't write '{}': {e}", cli.output)));
+
+    let (positions, moves) = builder.size();
+    cprintln!(
+        "<bold>Wrote {} positions and {} moves from {} games to '{}'</bold>",
+        positions,
+        moves,
+        builder.games(),
+        cli.output
+    );
+    if skipped > 0 {
+        cprintln!("<yellow>{} invalid games skipped</yellow>", skipped);
+    }
+}
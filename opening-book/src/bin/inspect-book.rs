@@ -0,0 +1,140 @@
+//! Looks inside an opening book. Lists the book moves in a position with their weights, prints
+//! the tree of book lines from it in SAN, and reports structural problems such as positions that
+//! can't be reached from the start or moves that are illegal in their position.
+
+use std::process::exit;
+
+use chess_lib::board::{Board, Move};
+use chess_lib::san::{move_to_san, parse_san};
+use clap::Parser;
+use clap_derive::Parser;
+use color_print::{ceprintln, cprintln};
+use opening_book::{BookTreeNode, DefaultOpeningBook, OpeningBook, load_book};
+
+#[derive(Parser)]
+#[command(version, about, long_about = Some("Inspects an opening book."))]
+struct Cli {
+    /// Book in our format or Polyglot format. Defaults to the built-in book.
+    book: Option<String>,
+    /// Position to look up. Defaults to the starting position.
+    #[arg(long)]
+    fen: Option<String>,
+    /// Moves to make from the position first, in SAN or UCI notation, separated by spaces.
+    #[arg(long)]
+    moves: Option<String>,
+    /// Print the tree of book lines from the position to this many plies.
+    #[arg(long)]
+    depth: Option<usize>,
+    /// Report the structure of the whole book.
+    #[arg(long)]
+    stats: bool,
+}
+
+fn user_error(message: impl AsRef<str>) -> ! {
+    ceprintln!("<bold><red>error:</red></bold> {}", message.as_ref());
+    exit(1);
+}
+
+fn parse_move(board: &Board, text: &str) -> Option<Move> {
+    parse_san(board, text)
+        .ok()
+        .or_else(|| board.normalise_move(Move::from_uci(text)?))
+}
+
+fn print_tree(nodes: &[BookTreeNode], board: &mut Board, indent: usize) {
+    for node in nodes {
+        let prefix = if board.color_to_move().is_white() {
+            format!("{}.", board.fullmoves())
+        } else {
+            format!("{}...", board.fullmoves())
+        };
+        cprintln!(
+            "{:indent$}{} <bold>{}</bold> {} ({:.1}%)",
+            "",
+            prefix,
+            node.book_move.san,
+            node.book_move.weight,
+            node.book_move.percentage,
+        );
+
+        let um = board.make_move(node.book_move.mv);
+        print_tree(&node.children, board, indent + 2);
+        board.unmake_last_move(um);
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let book: Box<dyn OpeningBook> = match &cli.book {
+        Some(path) => load_book(path)
+            .unwrap_or_else(|e| user_error(format!("couldn't load book '{path}': {e}"))),
+        None => Box::new(DefaultOpeningBook::initialise()),
+    };
+    println!("{}", book.statistics());
+
+    let mut board = match &cli.fen {
+        Some(fen) => Board::from_fen_lenient(fen)
+            .unwrap_or_else(|e| user_error(format!("invalid fen '{fen}': {e}"))),
+        None => Board::starting(),
+    };
+    let mut line = Vec::new();
+    for text in cli.moves.iter().flat_map(|moves| moves.split_whitespace()) {
+        let mv = parse_move(&board, text)
+            .unwrap_or_else(|| user_error(format!("illegal move '{text}' in {}", board.to_fen())));
+        line.push(move_to_san(&mut board.clone(), mv));
+        board.make_move(mv);
+    }
+
+    cprintln!("\n<bold>{}</bold>", board.to_fen());
+    if !line.is_empty() {
+        println!("after {}", line.join(" "));
+    }
+    let moves = book.book_moves(&board);
+    if moves.is_empty() {
+        cprintln!("<yellow>Position is not in the book</yellow>");
+    }
+    for mv in &moves {
+        cprintln!(
+            "  <bold>{:<8}</bold> {:<6} {:>8} {:>6.1}%",
+            mv.san,
+            mv.mv.as_uci(),
+            mv.weight,
+            mv.percentage
+        );
+    }
+
+    if let Some(depth) = cli.depth {
+        cprintln!("\n<bold>Book tree to depth {}</bold>", depth);
+        print_tree(&book.tree(&board, depth), &mut board, 0);
+    }
+
+    if cli.stats {
+        let structure = book.structure();
+        cprintln!("\n<bold>Structure</bold>");
+        println!(
+            "{} positions | {} entries",
+            structure.positions, structure.entries
+        );
+        for (ply, positions) in structure.positions_per_ply.iter().enumerate() {
+            println!("  ply {ply:>3}: {positions} positions");
+        }
+        let (orphans, illegal) = (structure.orphan_positions, structure.illegal_entries);
+        if orphans > 0 {
+            cprintln!(
+                "<yellow>{} orphan positions ({} entries) can't be reached from the start</yellow>",
+                orphans,
+                structure.orphan_entries
+            );
+        }
+        if illegal > 0 {
+            cprintln!(
+                "<red>{} entries have moves that are illegal in their position</red>",
+                illegal
+            );
+        }
+        if orphans == 0 && illegal == 0 {
+            cprintln!("<green>Every position is reachable and every move is legal</green>");
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! Building opening books from collections of games, e.g. our own engine-vs-engine matches.
+//! Every position reached in the first `max_ply` plies of a game is added with the move played,
+//! and the move's weight is accumulated over all games according to the `Weighting`.
+
+use crate::polyglot::PolyglotEntry;
+use crate::shared::{SerialisedBookMove, serialise_book_move};
+use chess_lib::board::{Board, Color, FenError, Move, PolyglotKey};
+use chess_lib::pgn::{GameResult, PgnGame};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weighting {
+    /// Every game counts once.
+    Frequency,
+    /// Polyglot's weighting: two points for a win and one for a draw, from the mover's side.
+    Results,
+    /// Every game counts in proportion to the rating of the player making the move, so moves
+    /// played by stronger players are preferred. Games without an Elo tag count as `DEFAULT_ELO`.
+    Elo,
+}
+
+/// Rating used for the Elo weighting when the game doesn't have one.
+pub const DEFAULT_ELO: u32 = 1500;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MoveStats {
+    games: u32,
+    weight: u64,
+    /// The move in Polyglot's encoding, which depends on the position for castling.
+    polyglot_move: u16,
+}
+
+/// A move with its statistics and its weight in the written book.
+type WeightedMove = (Move, MoveStats, u16);
+
+pub struct BookBuilder {
+    max_ply: usize,
+    min_games: u32,
+    weighting: Weighting,
+    winning_side_only: bool,
+    positions: HashMap<PolyglotKey, HashMap<Move, MoveStats>>,
+    games: usize,
+}
+
+impl Default for BookBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BookBuilder {
+    pub fn new() -> BookBuilder {
+        BookBuilder {
+            max_ply: 20,
+            min_games: 1,
+            weighting: Weighting::Frequency,
+            winning_side_only: false,
+            positions: HashMap::new(),
+            games: 0,
+        }
+    }
+
+    /// Only moves made in the first `max_ply` plies of each game are added (default 20).
+    pub fn max_ply(mut self, max_ply: usize) -> BookBuilder {
+        self.max_ply = max_ply;
+        self
+    }
+
+    /// Moves played in fewer games than this are left out of the book (default 1).
+    pub fn min_games(mut self, min_games: u32) -> BookBuilder {
+        self.min_games = min_games;
+        self
+    }
+
+    pub fn weighting(mut self, weighting: Weighting) -> BookBuilder {
+        self.weighting = weighting;
+        self
+    }
+
+    /// Only add the moves of the side that won. Drawn and unfinished games are skipped.
+    pub fn winning_side_only(mut self, winning_side_only: bool) -> BookBuilder {
+        self.winning_side_only = winning_side_only;
+        self
+    }
+
+    /// Number of games added so far.
+    pub fn games(&self) -> usize {
+        self.games
+    }
+
+    pub fn add_game(&mut self, game: &PgnGame) -> Result<(), FenError> {
+        let winner = match game.result {
+            GameResult::WhiteWin => Some(Color::White),
+            GameResult::BlackWin => Some(Color::Black),
+            GameResult::Draw | GameResult::Unknown => None,
+        };
+        if self.winning_side_only && winner.is_none() {
+            return Ok(());
+        }
+
+        let elo = |color: Color| {
+            let tag = if color.is_white() {
+                "WhiteElo"
+            } else {
+                "BlackElo"
+            };
+            game.tag(tag)
+                .and_then(|elo| elo.parse().ok())
+                .unwrap_or(DEFAULT_ELO)
+        };
+
+        let mut board = game.initial_board()?;
+        for mv in game.mainline().take(self.max_ply) {
+            let mover = board.color_to_move();
+            if !self.winning_side_only || winner == Some(mover) {
+                let weight = match self.weighting {
+                    Weighting::Frequency => 1,
+                    Weighting::Results => match (game.result, winner) {
+                        (GameResult::Draw, _) => 1,
+                        (_, Some(winner)) if winner == mover => 2,
+                        _ => 0,
+                    },
+                    Weighting::Elo => elo(mover) as u64,
+                };
+
+                let stats = self
+                    .positions
+                    .entry(board.polyglot_key())
+                    .or_default()
+                    .entry(mv)
+                    .or_default();
+                stats.games += 1;
+                stats.weight += weight;
+                stats.polyglot_move = PolyglotEntry::encode_move(&board, mv);
+            }
+
+            board.make_move(mv);
+        }
+
+        self.games += 1;
+        Ok(())
+    }
+
+    /// The moves of every position that has any left after filtering, sorted by key and with the
+    /// highest weight first. Weights are scaled down to fit in a `u16` where needed and moves
+    /// with no weight are dropped.
+    fn moves(&self) -> Vec<(PolyglotKey, Vec<WeightedMove>)> {
+        let mut positions = self
+            .positions
+            .iter()
+            .filter_map(|(&key, moves)| {
+                let mut moves = moves
+                    .iter()
+                    .filter(|(_, stats)| stats.games >= self.min_games && stats.weight > 0)
+                    .map(|(&mv, &stats)| (mv, stats))
+                    .collect::<Vec<_>>();
+                if moves.is_empty() {
+                    return None;
+                }
+
+                let max_weight = moves.iter().map(|(_, stats)| stats.weight).max().unwrap();
+                let scale = |weight: u64| {
+                    if max_weight <= u16::MAX as u64 {
+                        weight as u16
+                    } else {
+                        (weight * u16::MAX as u64 / max_weight).max(1) as u16
+                    }
+                };
+
+                moves.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.weight));
+                Some((
+                    key,
+                    moves
+                        .into_iter()
+                        .map(|(mv, stats)| (mv, stats, scale(stats.weight)))
+                        .collect(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        positions.sort_by_key(|&(key, _)| key);
+        positions
+    }
+
+    /// Number of positions and moves that would be written.
+    pub fn size(&self) -> (usize, usize) {
+        let moves = self.moves();
+        (
+            moves.len(),
+            moves.iter().map(|(_, moves)| moves.len()).sum(),
+        )
+    }
+
+    pub fn to_polyglot_entries(&self) -> Vec<PolyglotEntry> {
+        self.moves()
+            .into_iter()
+            .flat_map(|(key, moves)| {
+                moves
+                    .into_iter()
+                    .map(move |(_, stats, weight)| PolyglotEntry {
+                        key: key.u64(),
+                        mv: stats.polyglot_move,
+                        weight,
+                        learn: 0,
+                    })
+            })
+            .collect()
+    }
+
+    pub fn to_polyglot_bytes(&self) -> Vec<u8> {
+        self.to_polyglot_entries()
+            .into_iter()
+            .flat_map(PolyglotEntry::to_bytes)
+            .collect()
+    }
+
+    /// The book in the format read by `DefaultOpeningBook`. At most 255 moves are kept for each
+    /// position.
+    pub fn to_book_bytes(&self) -> Vec<u8> {
+        let mut bytes = Board::starting()
+            .polyglot_key()
+            .u64()
+            .to_le_bytes()
+            .to_vec();
+
+        for (key, mut moves) in self.moves() {
+            moves.truncate(u8::MAX as usize);
+            bytes.extend_from_slice(&key.u64().to_le_bytes());
+            bytes.push(moves.len() as u8);
+            for (mv, _, weight) in moves {
+                bytes.extend_from_slice(&serialise_book_move(SerialisedBookMove {
+                    from: mv.source,
+                    to: mv.destination,
+                    promotion: mv.promotion,
+                    weight,
+                }));
+            }
+        }
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OpeningBook, PolyglotBook, load_book};
+    use chess_lib::pgn::read_games;
+
+    const GAMES: &str = r#"
+[Result "1-0"]
+[WhiteElo "2000"]
+
+1. e4 e5 2. Nf3 1-0
+
+[Result "0-1"]
+
+1. e4 c5 0-1
+
+[Result "1/2-1/2"]
+
+1. d4 d5 1/2-1/2
+"#;
+
+    fn build(mut builder: BookBuilder, pgn: &str) -> BookBuilder {
+        for game in read_games(pgn).unwrap() {
+            builder.add_game(&game).unwrap();
+        }
+        builder
+    }
+
+    /// The book moves after `line`, as written to and read back from a Polyglot book.
+    fn moves(builder: &BookBuilder, line: &[&str]) -> Vec<(u32, String)> {
+        let book = PolyglotBook::from_bytes(&builder.to_polyglot_bytes()).unwrap();
+        let mut board = Board::starting();
+        for mv in line {
+            board.make_move(Move::from_uci(mv).unwrap());
+        }
+        book.moves(&board)
+            .into_iter()
+            .map(|(weight, mv)| (weight, mv.as_uci()))
+            .collect()
+    }
+
+    fn entry(weight: u32, mv: &str) -> (u32, String) {
+        (weight, mv.to_string())
+    }
+
+    #[test]
+    fn test_frequency() {
+        let builder = build(BookBuilder::new(), GAMES);
+        assert_eq!(builder.games(), 3);
+        assert_eq!(builder.size(), (4, 6));
+        assert_eq!(
+            moves(&builder, &[]),
+            vec![entry(2, "e2e4"), entry(1, "d2d4")]
+        );
+        let mut replies = moves(&builder, &["e2e4"]);
+        replies.sort();
+        assert_eq!(replies, vec![entry(1, "c7c5"), entry(1, "e7e5")]);
+        assert_eq!(moves(&builder, &["e2e4", "e7e5"]), vec![entry(1, "g1f3")]);
+    }
+
+    #[test]
+    fn test_results() {
+        let builder = build(BookBuilder::new().weighting(Weighting::Results), GAMES);
+        assert_eq!(
+            moves(&builder, &[]),
+            vec![entry(2, "e2e4"), entry(1, "d2d4")]
+        );
+        // Black's e5 lost, so has no weight and is dropped
+        assert_eq!(moves(&builder, &["e2e4"]), vec![entry(2, "c7c5")]);
+        assert_eq!(moves(&builder, &["d2d4"]), vec![entry(1, "d7d5")]);
+    }
+
+    #[test]
+    fn test_elo() {
+        let builder = build(BookBuilder::new().weighting(Weighting::Elo), GAMES);
+        assert_eq!(
+            moves(&builder, &[]),
+            vec![
+                entry(2000 + DEFAULT_ELO, "e2e4"),
+                entry(DEFAULT_ELO, "d2d4")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let builder = build(BookBuilder::new().min_games(2), GAMES);
+        assert_eq!(builder.size(), (1, 1));
+        assert_eq!(moves(&builder, &[]), vec![entry(2, "e2e4")]);
+
+        let builder = build(BookBuilder::new().max_ply(1), GAMES);
+        assert_eq!(builder.size(), (1, 2));
+        assert!(moves(&builder, &["e2e4"]).is_empty());
+
+        // Only White's moves from the first game and Black's from the second
+        let builder = build(BookBuilder::new().winning_side_only(true), GAMES);
+        assert_eq!(builder.games(), 2);
+        assert_eq!(builder.size(), (3, 3));
+        assert_eq!(moves(&builder, &[]), vec![entry(1, "e2e4")]);
+        assert_eq!(moves(&builder, &["e2e4"]), vec![entry(1, "c7c5")]);
+        assert_eq!(moves(&builder, &["e2e4", "e7e5"]), vec![entry(1, "g1f3")]);
+    }
+
+    #[test]
+    fn test_weight_scaling() {
+        let mut pgn = String::from("[Result \"1/2-1/2\"]\n\n1. d4 1/2-1/2\n\n");
+        for _ in 0..40 {
+            pgn.push_str("[Result \"1-0\"]\n[WhiteElo \"3000\"]\n\n1. e4 1-0\n\n");
+        }
+        let builder = build(BookBuilder::new().weighting(Weighting::Elo), &pgn);
+
+        // 120000 for e4 is scaled down to the largest weight, and d4 in proportion
+        assert_eq!(
+            moves(&builder, &[]),
+            vec![
+                entry(u16::MAX as u32, "e2e4"),
+                entry(DEFAULT_ELO * u16::MAX as u32 / 120000, "d2d4")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_book() {
+        let builder = build(BookBuilder::new(), GAMES);
+        let directory = std::env::temp_dir();
+        let polyglot_path = directory.join(format!("opening_book_{}.bin", std::process::id()));
+        let book_path = directory.join(format!("opening_book_{}.book", std::process::id()));
+        std::fs::write(&polyglot_path, builder.to_polyglot_bytes()).unwrap();
+        std::fs::write(&book_path, builder.to_book_bytes()).unwrap();
+
+        let polyglot = load_book(&polyglot_path).unwrap();
+        let book = load_book(&book_path).unwrap();
+        std::fs::remove_file(polyglot_path).unwrap();
+        std::fs::remove_file(book_path).unwrap();
+
+        assert!(polyglot.statistics().starts_with("Polyglot"));
+        assert!(book.statistics().starts_with("Opening Book"));
+        for book in [polyglot, book] {
+            assert_eq!(book.positions().len(), 4);
+            let start = Board::starting();
+            assert_eq!(book.moves(&start).len(), 2);
+            assert_eq!(book.get_fast(&start), Move::from_uci("e2e4"));
+        }
+    }
+}
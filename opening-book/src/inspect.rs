@@ -0,0 +1,214 @@
+//! Looking inside a book: the moves in a position with their share of the weight, the tree of
+//! book lines from a position, and structural checks over the whole book.
+
+use crate::OpeningBook;
+use chess_lib::board::{Board, Move, PolyglotKey};
+use chess_lib::san::move_to_san;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Clone)]
+pub struct BookMove {
+    pub mv: Move,
+    pub san: String,
+    pub weight: u32,
+    /// Share of the total weight in the position, from 0 to 100.
+    pub percentage: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BookTreeNode {
+    pub book_move: BookMove,
+    /// Book moves in the position after `book_move`.
+    pub children: Vec<BookTreeNode>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BookStructure {
+    pub positions: usize,
+    pub entries: usize,
+    /// Number of positions first reached at each ply from the starting position, following only
+    /// legal book moves.
+    pub positions_per_ply: Vec<usize>,
+    /// Positions that can't be reached from the starting position by book moves. Their moves
+    /// can't be checked for legality without a board.
+    pub orphan_positions: usize,
+    pub orphan_entries: usize,
+    /// Entries in reachable positions whose move isn't legal there.
+    pub illegal_entries: usize,
+}
+
+pub(crate) fn book_moves(book: &(impl OpeningBook + ?Sized), board: &Board) -> Vec<BookMove> {
+    let moves = book.moves(board);
+    let total = moves.iter().map(|&(weight, _)| weight as f64).sum::<f64>();
+    moves
+        .into_iter()
+        .map(|(weight, mv)| BookMove {
+            mv,
+            san: move_to_san(&mut board.clone(), mv),
+            weight,
+            percentage: if total == 0.0 {
+                0.0
+            } else {
+                weight as f64 * 100.0 / total
+            },
+        })
+        .collect()
+}
+
+pub(crate) fn tree(
+    book: &(impl OpeningBook + ?Sized),
+    board: &mut Board,
+    depth: usize,
+) -> Vec<BookTreeNode> {
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    book_moves(book, board)
+        .into_iter()
+        .map(|book_move| {
+            let um = board.make_move(book_move.mv);
+            let children = tree(book, board, depth - 1);
+            board.unmake_last_move(um);
+            BookTreeNode {
+                book_move,
+                children,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn structure(book: &(impl OpeningBook + ?Sized)) -> BookStructure {
+    let keys = book.positions();
+    let mut structure = BookStructure {
+        positions: keys.len(),
+        entries: keys.iter().map(|&key| book.entry_count(key)).sum(),
+        ..Default::default()
+    };
+
+    // Breadth first, so each position is counted at the lowest ply it can be reached
+    let start = Board::starting();
+    let mut reached: HashMap<PolyglotKey, usize> = HashMap::new();
+    let mut queue = VecDeque::from([(start, 0)]);
+    while let Some((mut board, ply)) = queue.pop_front() {
+        let key = board.polyglot_key();
+        let entries = book.entry_count(key);
+        if entries == 0 || reached.contains_key(&key) {
+            continue;
+        }
+        reached.insert(key, ply);
+
+        let moves = book.moves(&board);
+        structure.illegal_entries += entries - moves.len();
+        for (_, mv) in moves {
+            let um = board.make_move(mv);
+            if !reached.contains_key(&board.polyglot_key()) {
+                queue.push_back((board.clone(), ply + 1));
+            }
+            board.unmake_last_move(um);
+        }
+    }
+
+    for &ply in reached.values() {
+        if structure.positions_per_ply.len() <= ply {
+            structure.positions_per_ply.resize(ply + 1, 0);
+        }
+        structure.positions_per_ply[ply] += 1;
+    }
+    for key in keys.into_iter().filter(|key| !reached.contains_key(key)) {
+        structure.orphan_positions += 1;
+        structure.orphan_entries += book.entry_count(key);
+    }
+
+    structure
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PolyglotBook, PolyglotEntry};
+
+    /// The lines e4 (weight 3) e5 and d4 (weight 1), an illegal e2e5 from the start and an
+    /// orphan position with two moves.
+    fn book() -> PolyglotBook {
+        let start = Board::starting();
+        let mut after_e4 = start.clone();
+        after_e4.make_move(Move::from_uci("e2e4").unwrap());
+
+        let entry = |board: &Board, mv: &str, weight: u16| PolyglotEntry {
+            key: board.polyglot_key().u64(),
+            mv: PolyglotEntry::encode_move(board, Move::from_uci(mv).unwrap()),
+            weight,
+            learn: 0,
+        };
+        let orphan = |mv: u16| PolyglotEntry {
+            key: 12345,
+            mv,
+            weight: 1,
+            learn: 0,
+        };
+
+        let entries = [
+            entry(&start, "e2e4", 3),
+            entry(&start, "d2d4", 1),
+            entry(&start, "e2e5", 1),
+            entry(&after_e4, "e7e5", 1),
+            orphan(1),
+            orphan(2),
+        ];
+        PolyglotBook::from_bytes(
+            &entries
+                .into_iter()
+                .flat_map(PolyglotEntry::to_bytes)
+                .collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_book_moves() {
+        let moves = book().book_moves(&Board::starting());
+        assert_eq!(
+            moves
+                .iter()
+                .map(|m| (m.san.as_str(), m.weight, m.percentage))
+                .collect::<Vec<_>>(),
+            vec![("e4", 3, 75.0), ("d4", 1, 25.0)]
+        );
+
+        let mut board = Board::starting();
+        board.make_move(Move::from_uci("d2d4").unwrap());
+        assert!(book().book_moves(&board).is_empty());
+    }
+
+    #[test]
+    fn test_tree() {
+        let book = book();
+        let start = Board::starting();
+        assert!(book.tree(&start, 0).is_empty());
+
+        let tree = book.tree(&start, 1);
+        assert_eq!(tree.len(), 2);
+        assert!(tree.iter().all(|node| node.children.is_empty()));
+
+        let tree = book.tree(&start, 3);
+        assert_eq!(tree[0].book_move.san, "e4");
+        assert_eq!(tree[0].children.len(), 1);
+        assert_eq!(tree[0].children[0].book_move.san, "e5");
+        assert_eq!(tree[0].children[0].book_move.percentage, 100.0);
+        assert!(tree[0].children[0].children.is_empty());
+        assert_eq!(tree[1].book_move.san, "d4");
+        assert!(tree[1].children.is_empty());
+    }
+
+    #[test]
+    fn test_structure() {
+        let structure = book().structure();
+        assert_eq!(structure.positions, 3);
+        assert_eq!(structure.entries, 6);
+        assert_eq!(structure.positions_per_ply, vec![1, 1]);
+        assert_eq!(structure.illegal_entries, 1);
+        assert_eq!(structure.orphan_positions, 1);
+        assert_eq!(structure.orphan_entries, 2);
+    }
+}
@@ -0,0 +1,237 @@
+//! Learning from game results. Each move in the opening of a finished game gets a score from the
+//! point of view of the side that played it, and book moves that have lost repeatedly are no
+//! longer played. Scores are kept in a sidecar file next to the book, in Polyglot's format with
+//! the number of games as the weight and the score in the `learn` field, so the book itself is
+//! never modified.
+
+use crate::polyglot::{POLYGLOT_ENTRY_SIZE, PolyglotEntry};
+use chess_lib::board::{Board, Color, FenError, Move, PolyglotKey};
+use chess_lib::pgn::{GameResult, PgnGame};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Only moves in the first plies of a game are learned.
+pub const LEARN_MAX_PLY: usize = 30;
+
+/// Book moves whose score is this or lower are avoided.
+pub const AVOID_SCORE: i32 = -2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LearnScore {
+    pub games: u32,
+    /// Wins minus losses for the side that played the move.
+    pub score: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BookLearning {
+    /// Keyed by position and Polyglot move encoding.
+    scores: HashMap<(PolyglotKey, u16), LearnScore>,
+}
+
+impl BookLearning {
+    pub fn new() -> BookLearning {
+        BookLearning::default()
+    }
+
+    /// A missing file is treated as empty, as nothing has been learned yet.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<BookLearning> {
+        match std::fs::read(path) {
+            Ok(bytes) => BookLearning::from_bytes(&bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BookLearning::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<BookLearning> {
+        if !bytes.len().is_multiple_of(POLYGLOT_ENTRY_SIZE) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "learn file size {} is not a multiple of {POLYGLOT_ENTRY_SIZE} bytes",
+                    bytes.len()
+                ),
+            ));
+        }
+
+        let scores = bytes
+            .chunks_exact(POLYGLOT_ENTRY_SIZE)
+            .map(|chunk| {
+                let entry = PolyglotEntry::from_bytes(chunk.try_into().unwrap());
+                (
+                    (PolyglotKey::from_u64(entry.key), entry.mv),
+                    LearnScore {
+                        games: entry.weight as u32,
+                        score: entry.learn as i32,
+                    },
+                )
+            })
+            .collect();
+        Ok(BookLearning { scores })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries = self
+            .scores
+            .iter()
+            .map(|(&(key, mv), score)| PolyglotEntry {
+                key: key.u64(),
+                mv,
+                weight: score.games.min(u16::MAX as u32) as u16,
+                learn: score.score as u32,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|e| (e.key, e.mv));
+        entries
+            .into_iter()
+            .flat_map(PolyglotEntry::to_bytes)
+            .collect()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    /// Number of moves with a score.
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Score the opening moves of a finished game. Games without a result are ignored.
+    pub fn learn_game(&mut self, game: &PgnGame) -> Result<(), FenError> {
+        let winner = match game.result {
+            GameResult::WhiteWin => Some(Color::White),
+            GameResult::BlackWin => Some(Color::Black),
+            GameResult::Draw => None,
+            GameResult::Unknown => return Ok(()),
+        };
+
+        let mut board = game.initial_board()?;
+        for mv in game.mainline().take(LEARN_MAX_PLY) {
+            let score = self
+                .scores
+                .entry((board.polyglot_key(), PolyglotEntry::encode_move(&board, mv)))
+                .or_default();
+            score.games += 1;
+            score.score += match winner {
+                Some(winner) if winner == board.color_to_move() => 1,
+                Some(_) => -1,
+                None => 0,
+            };
+
+            board.make_move(mv);
+        }
+
+        Ok(())
+    }
+
+    pub fn score(&self, board: &Board, mv: Move) -> Option<LearnScore> {
+        self.scores
+            .get(&(board.polyglot_key(), PolyglotEntry::encode_move(board, mv)))
+            .copied()
+    }
+
+    /// Whether the move has lost often enough that it shouldn't be played from the book.
+    pub fn avoids(&self, board: &Board, mv: Move) -> bool {
+        self.score(board, mv)
+            .is_some_and(|score| score.score <= AVOID_SCORE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_lib::pgn::read_games;
+
+    fn learn(pgn: &str) -> BookLearning {
+        let mut learning = BookLearning::new();
+        for game in read_games(pgn).unwrap() {
+            learning.learn_game(&game).unwrap();
+        }
+        learning
+    }
+
+    /// The score of each move of `line` in turn.
+    fn scores(learning: &BookLearning, line: &[&str]) -> Vec<Option<LearnScore>> {
+        let mut board = Board::starting();
+        line.iter()
+            .map(|mv| {
+                let mv = Move::from_uci(mv).unwrap();
+                let score = learning.score(&board, mv);
+                board.make_move(mv);
+                score
+            })
+            .collect()
+    }
+
+    fn score(games: u32, score: i32) -> Option<LearnScore> {
+        Some(LearnScore { games, score })
+    }
+
+    #[test]
+    fn test_learn_game() {
+        let learning = learn(
+            "1. e4 e5 2. Nf3 1-0\n\n1. e4 e5 2. Nf3 1/2-1/2\n\n1. e4 c5 0-1\n\n1. e4 c5 0-1\n\n\
+             1. e4 c5 0-1\n\n1. d4 *",
+        );
+        assert_eq!(learning.len(), 4);
+        assert_eq!(
+            scores(&learning, &["e2e4", "e7e5", "g1f3"]),
+            vec![score(5, -2), score(2, -1), score(2, 1)]
+        );
+        assert_eq!(scores(&learning, &["e2e4", "c7c5"])[1], score(3, 3));
+        assert_eq!(scores(&learning, &["d2d4"]), vec![None]);
+
+        let start = Board::starting();
+        assert!(learning.avoids(&start, Move::from_uci("e2e4").unwrap()));
+        assert!(!learning.avoids(&start, Move::from_uci("d2d4").unwrap()));
+        let mut board = start.clone();
+        board.make_move(Move::from_uci("e2e4").unwrap());
+        assert!(!learning.avoids(&board, Move::from_uci("e7e5").unwrap()));
+        assert!(!learning.avoids(&board, Move::from_uci("c7c5").unwrap()));
+    }
+
+    #[test]
+    fn test_max_ply() {
+        // The knights return to the start every four plies
+        let learning = learn(&format!("{} 1-0", "Nf3 Nf6 Ng1 Ng8 ".repeat(10)));
+        // Nf3 is played at plies 0, 4, ..., 36 and learned up to ply 28
+        let games = LEARN_MAX_PLY.div_ceil(4) as u32;
+        assert_eq!(games, 8);
+        assert_eq!(
+            scores(&learning, &["g1f3", "g8f6", "f3g1", "f6g8"]),
+            vec![
+                score(games, games as i32),
+                score(games, -(games as i32)),
+                score(games - 1, games as i32 - 1),
+                score(games - 1, -(games as i32 - 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        let learning = learn("1. e4 e5 0-1\n\n1. e4 e5 0-1\n\n1. e4 c5 0-1\n\n1. d4 d5 1/2-1/2");
+        let bytes = learning.to_bytes();
+        assert_eq!(bytes.len(), 5 * POLYGLOT_ENTRY_SIZE);
+
+        let read = BookLearning::from_bytes(&bytes).unwrap();
+        assert_eq!(read.len(), 5);
+        assert_eq!(read.scores, learning.scores);
+        assert_eq!(
+            scores(&read, &["e2e4", "e7e5"]),
+            vec![score(3, -3), score(2, 2)]
+        );
+        assert_eq!(read.to_bytes(), bytes);
+
+        assert!(BookLearning::from_bytes(&bytes[1..]).is_err());
+        assert!(BookLearning::from_bytes(&[]).unwrap().is_empty());
+        let missing = std::env::temp_dir().join("opening_book_missing_learning.bin");
+        assert!(BookLearning::load(missing).unwrap().is_empty());
+    }
+}
@@ -1,39 +1,94 @@
+mod builder;
+mod inspect;
+mod learning;
+mod polyglot;
+mod selection;
 mod shared;
 
+pub use crate::builder::{BookBuilder, DEFAULT_ELO, Weighting};
+pub use crate::inspect::{BookMove, BookStructure, BookTreeNode};
+pub use crate::learning::{AVOID_SCORE, BookLearning, LEARN_MAX_PLY, LearnScore};
+pub use crate::polyglot::{POLYGLOT_ENTRY_SIZE, PolyglotBook, PolyglotEntry};
+pub use crate::selection::{BookSelector, BookStrategy};
 use crate::shared::{SERIALISED_MOVE_SIZE, deserialise_book_move};
-use chess_lib::board::{Board, BoardHash, Move};
+use chess_lib::board::{Board, Move, PolyglotKey};
 use human_bytes::human_bytes;
-use rand::RngExt;
+use rand::{Rng, RngExt};
 use std::collections::HashMap;
+use std::io;
+use std::path::Path;
 
 const BOOK_BYTES: &[u8] = include_bytes!("../static/gen/book.bin");
 
 pub trait OpeningBook {
     fn statistics(&self) -> String;
-    fn get_fast(&self, position: BoardHash) -> Option<Move>;
-    fn get_weighted(&self, position: BoardHash) -> Option<Move>;
+    fn get_fast(&self, board: &Board) -> Option<Move>;
+    fn get_weighted(&self, board: &Board) -> Option<Move>;
+
+    /// Legal book moves in the position with their weights, highest weight first.
+    fn moves(&self, board: &Board) -> Vec<MoveEntry>;
+
+    /// Keys of every position in the book.
+    fn positions(&self) -> Vec<PolyglotKey>;
+
+    /// Number of moves stored for the position, including any that aren't legal.
+    fn entry_count(&self, key: PolyglotKey) -> usize;
+
+    /// Book moves in the position in SAN, with their share of the total weight.
+    fn book_moves(&self, board: &Board) -> Vec<BookMove> {
+        inspect::book_moves(self, board)
+    }
+
+    /// The tree of book lines from the position, up to `depth` plies deep.
+    fn tree(&self, board: &Board, depth: usize) -> Vec<BookTreeNode> {
+        inspect::tree(self, &mut board.clone(), depth)
+    }
+
+    /// Structural checks over every position in the book. Walks the whole book, so is slow for
+    /// large books.
+    fn structure(&self) -> BookStructure {
+        inspect::structure(self)
+    }
 }
 
 pub type MoveEntry = (u32, Move);
 pub type OpeningBookEntry = (u32, Vec<MoveEntry>);
 pub struct DefaultOpeningBook {
-    book: HashMap<BoardHash, OpeningBookEntry>,
+    book: HashMap<PolyglotKey, OpeningBookEntry>,
     total_moves: usize,
 }
 
 impl DefaultOpeningBook {
     pub fn initialise() -> DefaultOpeningBook {
-        let characteristic_hash = Board::starting().hash().u64().to_le_bytes();
-        assert!(BOOK_BYTES.starts_with(&characteristic_hash));
+        DefaultOpeningBook::from_bytes(BOOK_BYTES).expect("Built-in book should be valid")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<DefaultOpeningBook> {
+        DefaultOpeningBook::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Whether `bytes` start with the characteristic hash of our book format.
+    pub fn is_book_bytes(bytes: &[u8]) -> bool {
+        bytes.starts_with(&Board::starting().polyglot_key().u64().to_le_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<DefaultOpeningBook> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        if !DefaultOpeningBook::is_book_bytes(bytes) {
+            return Err(invalid("missing characteristic hash"));
+        }
 
         let mut book = HashMap::new();
         let mut total_moves = 0;
 
         let mut i = 8;
-        while i < BOOK_BYTES.len() {
-            let position_hash = u64::from_le_bytes(BOOK_BYTES[i..i + 8].try_into().unwrap());
+        while i < bytes.len() {
+            let position_hash = bytes
+                .get(i..i + 8)
+                .ok_or_else(|| invalid("truncated position"))?;
+            let position_hash = u64::from_le_bytes(position_hash.try_into().unwrap());
             i += 8;
-            let move_options = BOOK_BYTES[i];
+            let move_options = *bytes.get(i).ok_or_else(|| invalid("truncated position"))?;
             total_moves += move_options as usize;
             i += 1;
 
@@ -41,7 +96,10 @@ impl DefaultOpeningBook {
             let mut weight_total: u32 = 0;
 
             for _ in 0..move_options {
-                let mv = deserialise_book_move(&BOOK_BYTES[i..i + SERIALISED_MOVE_SIZE]);
+                let mv = bytes
+                    .get(i..i + SERIALISED_MOVE_SIZE)
+                    .and_then(deserialise_book_move)
+                    .ok_or_else(|| invalid("invalid move"))?;
                 weight_total += mv.weight as u32;
                 moves.push(mv);
                 i += SERIALISED_MOVE_SIZE;
@@ -50,7 +108,7 @@ impl DefaultOpeningBook {
             moves.sort_by(|mv1, mv2| mv2.weight.cmp(&mv1.weight));
 
             book.insert(
-                BoardHash::from_u64(position_hash),
+                PolyglotKey::from_u64(position_hash),
                 (
                     weight_total,
                     moves
@@ -70,10 +128,20 @@ impl DefaultOpeningBook {
             );
         }
 
-        DefaultOpeningBook { book, total_moves }
+        Ok(DefaultOpeningBook { book, total_moves })
     }
 }
 
+/// Load a book in either our format or Polyglot format, depending on its contents.
+pub fn load_book(path: impl AsRef<Path>) -> io::Result<Box<dyn OpeningBook + Send>> {
+    let bytes = std::fs::read(path)?;
+    Ok(if DefaultOpeningBook::is_book_bytes(&bytes) {
+        Box::new(DefaultOpeningBook::from_bytes(&bytes)?)
+    } else {
+        Box::new(PolyglotBook::from_bytes(&bytes)?)
+    })
+}
+
 impl OpeningBook for DefaultOpeningBook {
     fn statistics(&self) -> String {
         format!(
@@ -82,29 +150,58 @@ impl OpeningBook for DefaultOpeningBook {
             self.total_moves,
             human_bytes(
                 (size_of::<OpeningBookEntry>()
-                    + self.book.len() * (size_of::<BoardHash>() + size_of::<OpeningBookEntry>())
+                    + self.book.len() * (size_of::<PolyglotKey>() + size_of::<OpeningBookEntry>())
                     + self.total_moves * size_of::<MoveEntry>()) as f32
             )
         )
     }
 
-    fn get_fast(&self, position: BoardHash) -> Option<Move> {
-        self.book.get(&position).map(|v| v.1[0].1)
+    fn get_fast(&self, board: &Board) -> Option<Move> {
+        self.book.get(&board.polyglot_key()).map(|v| v.1[0].1)
     }
 
-    fn get_weighted(&self, position: BoardHash) -> Option<Move> {
-        let mut rng = rand::rng();
-        let (total, options) = self.book.get(&position)?;
+    fn get_weighted(&self, board: &Board) -> Option<Move> {
+        let (total, options) = self.book.get(&board.polyglot_key())?;
+        weighted_choice(&mut rand::rng(), *total, options)
+    }
 
-        let mut selection = rng.random_range(0..*total);
+    fn moves(&self, board: &Board) -> Vec<MoveEntry> {
+        self.book
+            .get(&board.polyglot_key())
+            .map(|(_, options)| {
+                options
+                    .iter()
+                    .filter(|&&(_, mv)| board.normalise_move(mv) == Some(mv))
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-        for (weight, mv) in options {
-            if selection < *weight {
-                return Some(*mv);
-            }
-            selection -= *weight;
-        }
+    fn positions(&self) -> Vec<PolyglotKey> {
+        self.book.keys().copied().collect()
+    }
 
-        panic!()
+    fn entry_count(&self, key: PolyglotKey) -> usize {
+        self.book.get(&key).map_or(0, |(_, options)| options.len())
     }
 }
+
+/// Pick a move at random in proportion to its weight. If every weight is zero, the first move is
+/// picked.
+fn weighted_choice(rng: &mut impl Rng, total: u32, options: &[MoveEntry]) -> Option<Move> {
+    if total == 0 {
+        return options.first().map(|&(_, mv)| mv);
+    }
+
+    let mut selection = rng.random_range(0..total);
+
+    for (weight, mv) in options {
+        if selection < *weight {
+            return Some(*mv);
+        }
+        selection -= *weight;
+    }
+
+    panic!()
+}
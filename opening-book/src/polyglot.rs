@@ -0,0 +1,284 @@
+//! http://hgm.nubati.net/book_format.html
+//! Polyglot opening books, loaded at runtime. A book is a list of 16 byte big-endian entries
+//! sorted by position key, so lookups are a binary search over the entries.
+
+use crate::{MoveEntry, OpeningBook, weighted_choice};
+use chess_lib::board::{Board, Move, PieceKind, PolyglotKey, Square};
+use human_bytes::human_bytes;
+use std::io;
+use std::path::Path;
+
+pub const POLYGLOT_ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolyglotEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl PolyglotEntry {
+    pub fn from_bytes(bytes: &[u8; POLYGLOT_ENTRY_SIZE]) -> PolyglotEntry {
+        PolyglotEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; POLYGLOT_ENTRY_SIZE] {
+        let mut bytes = [0; POLYGLOT_ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+
+    /// Encode a legal move in `board`, with castling as the king capturing its own rook.
+    pub fn encode_move(board: &Board, mv: Move) -> u16 {
+        let destination = match board.castling_rook(mv) {
+            Some((rook_square, _)) => rook_square,
+            None => mv.destination,
+        };
+        let promotion = match mv.promotion {
+            None => 0,
+            Some(PieceKind::Knight) => 1,
+            Some(PieceKind::Bishop) => 2,
+            Some(PieceKind::Rook) => 3,
+            Some(_) => 4,
+        };
+
+        (promotion << 12) | ((mv.source.as_u8() as u16) << 6) | destination.as_u8() as u16
+    }
+
+    /// The move in `board`, or None if it isn't legal there. Castling is stored as the king
+    /// capturing its own rook.
+    //  bits 0–2   to-file
+    //  bits 3–5   to-rank
+    //  bits 6–8   from-file
+    //  bits 9–11  from-rank
+    //  bits 12–14 promotion piece (0=none 1=knight 2=bishop 3=rook 4=queen)
+    pub fn decode_move(&self, board: &Board) -> Option<Move> {
+        let square = |bits: u16| Square::from_u8((((bits >> 3) & 7) * 8 + (bits & 7)) as u8);
+        let promotion = match (self.mv >> 12) & 7 {
+            0 => None,
+            1 => Some(PieceKind::Knight),
+            2 => Some(PieceKind::Bishop),
+            3 => Some(PieceKind::Rook),
+            4 => Some(PieceKind::Queen),
+            _ => return None,
+        };
+
+        board.normalise_move(Move {
+            source: square(self.mv >> 6)?,
+            destination: square(self.mv)?,
+            promotion,
+        })
+    }
+}
+
+pub struct PolyglotBook {
+    entries: Vec<PolyglotEntry>,
+}
+
+impl PolyglotBook {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<PolyglotBook> {
+        PolyglotBook::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<PolyglotBook> {
+        if !bytes.len().is_multiple_of(POLYGLOT_ENTRY_SIZE) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "book size {} is not a multiple of {POLYGLOT_ENTRY_SIZE} bytes",
+                    bytes.len()
+                ),
+            ));
+        }
+
+        let mut entries = bytes
+            .chunks_exact(POLYGLOT_ENTRY_SIZE)
+            .map(|chunk| PolyglotEntry::from_bytes(chunk.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        // Books should already be sorted, but binary search relies on it
+        if !entries.is_sorted_by_key(|e| e.key) {
+            entries.sort_by_key(|e| e.key);
+        }
+
+        Ok(PolyglotBook { entries })
+    }
+
+    /// All entries for the position, in file order.
+    pub fn entries(&self, key: PolyglotKey) -> &[PolyglotEntry] {
+        let start = self.entries.partition_point(|e| e.key < key.u64());
+        let end = start + self.entries[start..].partition_point(|e| e.key == key.u64());
+        &self.entries[start..end]
+    }
+}
+
+impl OpeningBook for PolyglotBook {
+    fn statistics(&self) -> String {
+        let positions = self.entries.chunk_by(|a, b| a.key == b.key).count();
+        format!(
+            "Polyglot Book: [{} positions | {} moves | {}]",
+            positions,
+            self.entries.len(),
+            human_bytes((self.entries.len() * size_of::<PolyglotEntry>()) as f32)
+        )
+    }
+
+    fn get_fast(&self, board: &Board) -> Option<Move> {
+        self.moves(board).first().map(|&(_, mv)| mv)
+    }
+
+    fn get_weighted(&self, board: &Board) -> Option<Move> {
+        let moves = self.moves(board);
+        let total = moves.iter().map(|&(weight, _)| weight).sum();
+        weighted_choice(&mut rand::rng(), total, &moves)
+    }
+
+    fn moves(&self, board: &Board) -> Vec<MoveEntry> {
+        let mut moves = self
+            .entries(board.polyglot_key())
+            .iter()
+            .filter_map(|e| Some((e.weight as u32, e.decode_move(board)?)))
+            .collect::<Vec<_>>();
+        moves.sort_by_key(|&(weight, _)| std::cmp::Reverse(weight));
+        moves
+    }
+
+    fn positions(&self) -> Vec<PolyglotKey> {
+        let mut keys = self
+            .entries
+            .iter()
+            .map(|e| PolyglotKey::from_u64(e.key))
+            .collect::<Vec<_>>();
+        keys.dedup();
+        keys
+    }
+
+    fn entry_count(&self, key: PolyglotKey) -> usize {
+        self.entries(key).len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: u64, mv: u16) -> PolyglotEntry {
+        PolyglotEntry {
+            key,
+            mv,
+            weight: 1,
+            learn: 0,
+        }
+    }
+
+    fn book_bytes(entries: &[PolyglotEntry]) -> Vec<u8> {
+        entries.iter().flat_map(|&e| e.to_bytes()).collect()
+    }
+
+    #[test]
+    fn test_from_bytes_length() {
+        let bytes = book_bytes(&[entry(1, 2), entry(3, 4)]);
+        assert_eq!(PolyglotBook::from_bytes(&bytes).unwrap().entries.len(), 2);
+        assert!(PolyglotBook::from_bytes(&[]).unwrap().entries.is_empty());
+
+        for len in [1, 15, 17, 31] {
+            let error = PolyglotBook::from_bytes(&bytes[..len]).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        let mut odd = bytes.clone();
+        odd.push(0);
+        assert!(PolyglotBook::from_bytes(&odd).is_err());
+    }
+
+    #[test]
+    fn test_entries() {
+        let book = PolyglotBook::from_bytes(&book_bytes(&[
+            entry(30, 1),
+            entry(10, 2),
+            entry(20, 3),
+            entry(10, 4),
+            entry(u64::MAX, 5),
+        ]))
+        .unwrap();
+
+        assert!(book.entries.is_sorted_by_key(|e| e.key));
+        let moves = |key| {
+            book.entries(PolyglotKey::from_u64(key))
+                .iter()
+                .map(|e| e.mv)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(moves(10), vec![2, 4]);
+        assert_eq!(moves(20), vec![3]);
+        assert_eq!(moves(30), vec![1]);
+        assert_eq!(moves(u64::MAX), vec![5]);
+        assert!(moves(0).is_empty());
+        assert!(moves(15).is_empty());
+        assert!(moves(31).is_empty());
+    }
+
+    fn decode(board: &Board, mv: u16) -> Option<Move> {
+        entry(board.polyglot_key().u64(), mv).decode_move(board)
+    }
+
+    #[test]
+    fn test_decode_move() {
+        let board = Board::starting();
+        // e2e4: from e2 (rank 1, file 4), to e4 (rank 3, file 4)
+        let e2e4 = (12 << 6) | 28;
+        assert_eq!(decode(&board, e2e4), Move::from_uci("e2e4"));
+        assert_eq!(
+            PolyglotEntry::encode_move(&board, Move::from_uci("e2e4").unwrap()),
+            e2e4
+        );
+        assert_eq!(decode(&board, (12 << 6) | 36), None);
+    }
+
+    #[test]
+    fn test_decode_castling() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let kingside = board
+            .normalise_move(Move::from_uci("e1g1").unwrap())
+            .unwrap();
+        let queenside = board
+            .normalise_move(Move::from_uci("e1c1").unwrap())
+            .unwrap();
+        assert_ne!(kingside, queenside);
+
+        // The king takes its own rook on h1 and a1
+        assert_eq!(decode(&board, (4 << 6) | 7), Some(kingside));
+        assert_eq!(decode(&board, 4 << 6), Some(queenside));
+        assert_eq!(PolyglotEntry::encode_move(&board, kingside), (4 << 6) | 7);
+        assert_eq!(PolyglotEntry::encode_move(&board, queenside), 4 << 6);
+
+        // Without castling rights, e1h1 isn't a legal move
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1").unwrap();
+        assert_eq!(decode(&board, (4 << 6) | 7), None);
+    }
+
+    #[test]
+    fn test_decode_promotion() {
+        let board = Board::from_fen("8/P6k/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        // a7a8: from a7 (48), to a8 (56)
+        let a7a8 = (48 << 6) | 56;
+        for (code, promotion) in [(1, "n"), (2, "b"), (3, "r"), (4, "q")] {
+            let mv = Move::from_uci(&format!("a7a8{promotion}")).unwrap();
+            assert_eq!(decode(&board, (code << 12) | a7a8), Some(mv));
+            assert_eq!(PolyglotEntry::encode_move(&board, mv), (code << 12) | a7a8);
+        }
+
+        // A pawn reaching the last rank must promote, and 5–7 aren't pieces
+        assert_eq!(decode(&board, a7a8), None);
+        for code in 5..8 {
+            assert_eq!(decode(&board, (code << 12) | a7a8), None);
+        }
+    }
+}
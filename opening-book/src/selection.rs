@@ -0,0 +1,240 @@
+//! Choosing which book move to play. The candidates in a position are filtered by weight, count
+//! and learned results, then picked from according to the `BookStrategy`. Random choices use the
+//! selector's own RNG, so a seeded selector plays the same moves every game.
+
+use crate::{BookLearning, MoveEntry, OpeningBook, weighted_choice};
+use chess_lib::board::{Board, Move};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookStrategy {
+    /// Always the highest weighted move.
+    Best,
+    /// At random in proportion to weight.
+    Weighted,
+    /// At random with every candidate equally likely.
+    Uniform,
+}
+
+impl BookStrategy {
+    pub const ALL: [BookStrategy; 3] = [
+        BookStrategy::Best,
+        BookStrategy::Weighted,
+        BookStrategy::Uniform,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            BookStrategy::Best => "Best",
+            BookStrategy::Weighted => "Weighted",
+            BookStrategy::Uniform => "Uniform",
+        }
+    }
+
+    /// Names are case-insensitive.
+    pub fn from_name(name: &str) -> Option<BookStrategy> {
+        BookStrategy::ALL
+            .into_iter()
+            .find(|strategy| strategy.name().eq_ignore_ascii_case(name))
+    }
+}
+
+pub struct BookSelector {
+    pub strategy: BookStrategy,
+    /// Moves with a lower weight are never played.
+    pub min_weight: u32,
+    /// Moves with a lower share of the position's total weight, from 0 to 100, are never played.
+    pub min_percentage: f64,
+    /// Only the N highest weighted moves are considered.
+    pub top_n: Option<usize>,
+    /// The book isn't used once this many plies of the game have been played.
+    pub max_ply: Option<u32>,
+    /// Results of earlier games. Moves that have lost repeatedly are never played.
+    pub learning: Option<BookLearning>,
+    rng: StdRng,
+}
+
+impl Default for BookSelector {
+    fn default() -> Self {
+        Self::new(BookStrategy::Weighted, None)
+    }
+}
+
+impl BookSelector {
+    /// Without a seed, the RNG is seeded from the system's entropy source.
+    pub fn new(strategy: BookStrategy, seed: Option<u64>) -> BookSelector {
+        BookSelector {
+            strategy,
+            min_weight: 0,
+            min_percentage: 0.0,
+            top_n: None,
+            max_ply: None,
+            learning: None,
+            rng: match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => rand::make_rng(),
+            },
+        }
+    }
+
+    /// The moves that may be played in the position, highest weight first. If the filters leave
+    /// nothing, the position is treated as out of book.
+    pub fn candidates(&self, book: &(impl OpeningBook + ?Sized), board: &Board) -> Vec<MoveEntry> {
+        if self.max_ply.is_some_and(|max_ply| board.ply() >= max_ply) {
+            return Vec::new();
+        }
+
+        let mut moves = book.moves(board);
+        let total = moves.iter().map(|&(weight, _)| weight as f64).sum::<f64>();
+        moves.retain(|&(weight, _)| {
+            weight >= self.min_weight
+                && (total == 0.0 || weight as f64 * 100.0 / total >= self.min_percentage)
+        });
+        if let Some(learning) = &self.learning {
+            moves.retain(|&(_, mv)| !learning.avoids(board, mv));
+        }
+        if let Some(top_n) = self.top_n {
+            moves.truncate(top_n);
+        }
+        moves
+    }
+
+    pub fn select(&mut self, book: &(impl OpeningBook + ?Sized), board: &Board) -> Option<Move> {
+        let candidates = self.candidates(book, board);
+        match self.strategy {
+            BookStrategy::Best => candidates.first().map(|&(_, mv)| mv),
+            BookStrategy::Weighted => {
+                let total = candidates.iter().map(|&(weight, _)| weight).sum();
+                weighted_choice(&mut self.rng, total, &candidates)
+            }
+            BookStrategy::Uniform => (!candidates.is_empty())
+                .then(|| candidates[self.rng.random_range(0..candidates.len())].1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PolyglotBook, PolyglotEntry};
+    use std::collections::HashMap;
+
+    /// From the starting position: e4 60, d4 30, c4 9 and Nf3 1.
+    fn book() -> PolyglotBook {
+        let start = Board::starting();
+        let bytes = [("e2e4", 60), ("d2d4", 30), ("c2c4", 9), ("g1f3", 1)]
+            .into_iter()
+            .flat_map(|(mv, weight)| {
+                PolyglotEntry {
+                    key: start.polyglot_key().u64(),
+                    mv: PolyglotEntry::encode_move(&start, Move::from_uci(mv).unwrap()),
+                    weight,
+                    learn: 0,
+                }
+                .to_bytes()
+            })
+            .collect::<Vec<_>>();
+        PolyglotBook::from_bytes(&bytes).unwrap()
+    }
+
+    fn candidates(selector: &BookSelector) -> Vec<String> {
+        selector
+            .candidates(&book(), &Board::starting())
+            .into_iter()
+            .map(|(_, mv)| mv.as_uci())
+            .collect()
+    }
+
+    /// How often each move is selected from the starting position in 1000 tries.
+    fn counts(selector: &mut BookSelector) -> HashMap<String, usize> {
+        let (book, start) = (book(), Board::starting());
+        let mut counts = HashMap::new();
+        for _ in 0..1000 {
+            *counts
+                .entry(selector.select(&book, &start).unwrap().as_uci())
+                .or_default() += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_seed() {
+        let (book, start) = (book(), Board::starting());
+        for strategy in BookStrategy::ALL {
+            let mut a = BookSelector::new(strategy, Some(7));
+            let mut b = BookSelector::new(strategy, Some(7));
+            let a = (0..50).map(|_| a.select(&book, &start)).collect::<Vec<_>>();
+            let b = (0..50).map(|_| b.select(&book, &start)).collect::<Vec<_>>();
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_best() {
+        let counts = counts(&mut BookSelector::new(BookStrategy::Best, None));
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts["e2e4"], 1000);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let counts = counts(&mut BookSelector::new(BookStrategy::Weighted, Some(1)));
+        assert!((500..700).contains(&counts["e2e4"]));
+        assert!((200..400).contains(&counts["d2d4"]));
+        assert!((40..150).contains(&counts["c2c4"]));
+        assert!(counts.get("g1f3").copied().unwrap_or(0) < 40);
+    }
+
+    #[test]
+    fn test_uniform() {
+        let counts = counts(&mut BookSelector::new(BookStrategy::Uniform, Some(1)));
+        assert_eq!(counts.len(), 4);
+        assert!(counts.values().all(|&count| (150..350).contains(&count)));
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut selector = BookSelector::new(BookStrategy::Weighted, Some(1));
+        assert_eq!(candidates(&selector), ["e2e4", "d2d4", "c2c4", "g1f3"]);
+
+        selector.min_weight = 10;
+        assert_eq!(candidates(&selector), ["e2e4", "d2d4"]);
+
+        selector.min_weight = 0;
+        selector.min_percentage = 5.0;
+        assert_eq!(candidates(&selector), ["e2e4", "d2d4", "c2c4"]);
+
+        selector.min_percentage = 0.0;
+        selector.top_n = Some(2);
+        assert_eq!(candidates(&selector), ["e2e4", "d2d4"]);
+        assert!(
+            counts(&mut selector)
+                .keys()
+                .all(|mv| mv == "e2e4" || mv == "d2d4")
+        );
+
+        selector.top_n = None;
+        selector.max_ply = Some(1);
+        assert_eq!(candidates(&selector).len(), 4);
+        selector.max_ply = Some(0);
+        assert!(candidates(&selector).is_empty());
+    }
+
+    #[test]
+    fn test_all_filtered() {
+        let (book, start) = (book(), Board::starting());
+        for strategy in BookStrategy::ALL {
+            for filter in 0..3 {
+                let mut selector = BookSelector::new(strategy, Some(1));
+                match filter {
+                    0 => selector.min_weight = 61,
+                    1 => selector.min_percentage = 61.0,
+                    _ => selector.top_n = Some(0),
+                }
+                assert!(selector.candidates(&book, &start).is_empty());
+                assert_eq!(selector.select(&book, &start), None);
+            }
+        }
+    }
+}
@@ -26,15 +26,15 @@ pub fn serialise_book_move(mv: SerialisedBookMove) -> [u8; SERIALISED_MOVE_SIZE]
 }
 
 #[allow(dead_code)]
-pub fn deserialise_book_move(bytes: &[u8]) -> SerialisedBookMove {
-    SerialisedBookMove {
-        from: Square::from_u8(bytes[0]).unwrap(),
-        to: Square::from_u8(bytes[1]).unwrap(),
+pub fn deserialise_book_move(bytes: &[u8]) -> Option<SerialisedBookMove> {
+    Some(SerialisedBookMove {
+        from: Square::from_u8(bytes[0])?,
+        to: Square::from_u8(bytes[1])?,
         promotion: if bytes[2] == 0 {
             None
         } else {
-            Some(PieceKind::from_u8(bytes[3]).unwrap())
+            Some(PieceKind::from_u8(bytes[3])?)
         },
         weight: ((bytes[4] as u16) << 8) + (bytes[5] as u16),
-    }
+    })
 }
@@ -0,0 +1,336 @@
+//! Runs the engine on a tactical test suite (e.g. WAC, STS or Bratko-Kopec) in EPD format.
+//! Each position is searched with a fixed time, depth or node budget, and scored against its `bm`
+//! and `am` operations. Reports the solve rate, the time at which each solution was found and
+//! the positions that failed, as a table and optionally as JSON, so tactical regressions between
+//! versions show up in seconds.
+
+use std::fs;
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+use chess_lib::board::Move;
+use chess_lib::epd::{Epd, read_epds};
+use chess_lib::san::move_to_san;
+use clap::Parser;
+use clap_derive::Parser;
+use color_print::{ceprintln, cprintln};
+use engine::{InterMoveCache, SearchLimits, search_with_limits};
+use serde::Serialize;
+
+/// Time per position if no limit is given.
+const DEFAULT_TIME_MS: u64 = 1000;
+
+#[derive(Parser)]
+#[command(version, about, long_about = Some("Runs the engine on a test suite of EPD positions."))]
+struct Cli {
+    /// EPD file with one position per line.
+    suite: String,
+    /// Time limit per position in milliseconds. Defaults to 1000 if no other limit is given.
+    #[arg(long)]
+    time_ms: Option<u64>,
+    /// Depth limit per position.
+    #[arg(long)]
+    depth: Option<u8>,
+    /// Node limit per position.
+    #[arg(long)]
+    nodes: Option<u64>,
+    /// Write the full report as JSON to this file.
+    #[arg(long)]
+    json: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Limits {
+    time_ms: Option<u64>,
+    depth: Option<u8>,
+    nodes: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct PositionResult {
+    line: usize,
+    id: Option<String>,
+    fen: String,
+    best_moves: Vec<String>,
+    avoid_moves: Vec<String>,
+    played: Option<String>,
+    solved: bool,
+    /// When the search first chose a solution and kept it until the end.
+    time_to_solution_ms: Option<u64>,
+    depth: u8,
+    nodes: u64,
+    time_ms: u64,
+}
+
+#[derive(Serialize)]
+struct InvalidRecord {
+    line: usize,
+    error: String,
+}
+
+#[derive(Serialize)]
+struct Report {
+    suite: String,
+    limits: Limits,
+    positions: usize,
+    solved: usize,
+    solve_rate: f64,
+    average_time_to_solution_ms: Option<f64>,
+    total_time_ms: u64,
+    results: Vec<PositionResult>,
+    invalid: Vec<InvalidRecord>,
+}
+
+fn user_error(message: impl AsRef<str>) -> ! {
+    ceprintln!("<bold><red>error:</red></bold> {}", message.as_ref());
+    exit(1);
+}
+
+fn sans(epd: &Epd, moves: &[Move]) -> Vec<String> {
+    moves
+        .iter()
+        .map(|&mv| move_to_san(&mut epd.board.clone(), mv))
+        .collect()
+}
+
+fn run_position(line: usize, epd: &Epd, limits: SearchLimits) -> PositionResult {
+    let mut board = epd.board.clone();
+    let mut cache = InterMoveCache::new();
+    let mut solved_at = None;
+    let mut depth = 0;
+    let mut nodes = 0;
+
+    let start = Instant::now();
+    let (best_move, _) =
+        search_with_limits(&mut board, &mut cache, || false, limits, &mut |progress| {
+            depth = progress.depth;
+            nodes = progress.nodes;
+            if !progress.best_move.is_some_and(|mv| epd.is_solution(mv)) {
+                solved_at = None;
+            } else if solved_at.is_none() {
+                solved_at = Some(progress.elapsed);
+            }
+        });
+    let time_taken = start.elapsed();
+
+    let solved = best_move.is_some_and(|mv| epd.is_solution(mv));
+
+    PositionResult {
+        line,
+        id: epd.id.clone(),
+        fen: epd.board.to_fen(),
+        best_moves: sans(epd, &epd.best_moves),
+        avoid_moves: sans(epd, &epd.avoid_moves),
+        played: best_move.map(|mv| move_to_san(&mut epd.board.clone(), mv)),
+        solved,
+        time_to_solution_ms: solved_at.filter(|_| solved).map(|t| t.as_millis() as u64),
+        depth,
+        nodes,
+        time_ms: time_taken.as_millis() as u64,
+    }
+}
+
+fn expected(result: &PositionResult) -> String {
+    let mut parts = Vec::new();
+    if !result.best_moves.is_empty() {
+        parts.push(format!("bm {}", result.best_moves.join(" ")));
+    }
+    if !result.avoid_moves.is_empty() {
+        parts.push(format!("am {}", result.avoid_moves.join(" ")));
+    }
+    parts.join("; ")
+}
+
+fn print_row(result: &PositionResult) {
+    let name = result
+        .id
+        .clone()
+        .unwrap_or_else(|| format!("line {}", result.line));
+    let played = result.played.as_deref().unwrap_or("-");
+    let solved_at = result
+        .time_to_solution_ms
+        .map(|t| format!("{t} ms"))
+        .unwrap_or_else(|| "-".to_string());
+
+    let row = format!(
+        "{name:<16} {played:<8} {:<20} {solved_at:>10} {:>5} {:>12}",
+        expected(result),
+        result.depth,
+        result.nodes
+    );
+    if result.solved {
+        cprintln!("<green>PASS</green> {}", row);
+    } else {
+        cprintln!("<red>FAIL</red> {}", row);
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let text = fs::read_to_string(&cli.suite)
+        .unwrap_or_else(|e| user_error(format!("couldn't read '{}': {e}", cli.suite)));
+
+    let time_ms = if cli.time_ms.is_none() && cli.depth.is_none() && cli.nodes.is_none() {
+        Some(DEFAULT_TIME_MS)
+    } else {
+        cli.time_ms
+    };
+    let limits = SearchLimits {
+        time: time_ms.map(Duration::from_millis),
+        depth: cli.depth,
+        nodes: cli.nodes,
+    };
+
+    let mut results = Vec::new();
+    let mut invalid = Vec::new();
+
+    cprintln!(
+        "<bold>     {:<16} {:<8} {:<20} {:>10} {:>5} {:>12}</bold>",
+        "Position",
+        "Played",
+        "Expected",
+        "Solved at",
+        "Depth",
+        "Nodes"
+    );
+    for (line, epd) in read_epds(&text) {
+        match epd {
+            Ok(epd) => {
+                let result = run_position(line, &epd, limits);
+                print_row(&result);
+                results.push(result);
+            }
+            Err(e) => {
+                cprintln!("<yellow>SKIP</yellow> line {}: {}", line, e);
+                invalid.push(InvalidRecord {
+                    line,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    let solved = results.iter().filter(|r| r.solved).count();
+    let solve_times = results
+        .iter()
+        .filter_map(|r| r.time_to_solution_ms)
+        .collect::<Vec<_>>();
+    let report = Report {
+        suite: cli.suite.clone(),
+        limits: Limits {
+            time_ms,
+            depth: cli.depth,
+            nodes: cli.nodes,
+        },
+        positions: results.len(),
+        solved,
+        solve_rate: if results.is_empty() {
+            0.0
+        } else {
+            solved as f64 / results.len() as f64
+        },
+        average_time_to_solution_ms: (!solve_times.is_empty())
+            .then(|| solve_times.iter().sum::<u64>() as f64 / solve_times.len() as f64),
+        total_time_ms: results.iter().map(|r| r.time_ms).sum(),
+        results,
+        invalid,
+    };
+
+    let failures = report
+        .results
+        .iter()
+        .filter(|r| !r.solved)
+        .collect::<Vec<_>>();
+    if !failures.is_empty() {
+        cprintln!("\n<bold>Failures</bold>");
+        for failure in failures {
+            cprintln!(
+                "<red>{}</red>: played {}, expected {} - {}",
+                failure
+                    .id
+                    .clone()
+                    .unwrap_or_else(|| format!("line {}", failure.line)),
+                failure.played.as_deref().unwrap_or("nothing"),
+                expected(failure),
+                failure.fen
+            );
+        }
+    }
+
+    cprintln!(
+        "\n<bold>Solved {}/{} ({:.1}%)</bold> | Average time to solution: {} | Total time: {:.1} s",
+        report.solved,
+        report.positions,
+        report.solve_rate * 100.0,
+        report
+            .average_time_to_solution_ms
+            .map(|t| format!("{t:.0} ms"))
+            .unwrap_or_else(|| "-".to_string()),
+        report.total_time_ms as f64 / 1000.0
+    );
+    if !report.invalid.is_empty() {
+        cprintln!(
+            "<yellow>{} invalid records skipped</yellow>",
+            report.invalid.len()
+        );
+    }
+
+    if let Some(path) = &cli.json {
+        let json = serde_json::to_string_pretty(&report).unwrap();
+        fs::write(path, json)
+            .unwrap_or_else(|e| user_error(format!("couldn't write '{path}': {e}")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// White mates with Ra8.
+    const MATE_IN_ONE: &str = "6k1/5ppp/8/8/8/8/8/R5K1 w - -";
+
+    fn run(operations: &str) -> PositionResult {
+        let epd = Epd::from_epd(&format!("{MATE_IN_ONE} {operations}")).unwrap();
+        run_position(
+            1,
+            &epd,
+            SearchLimits {
+                depth: Some(2),
+                ..SearchLimits::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_best_move() {
+        let result = run("bm Ra8#; id \"mate\";");
+        assert_eq!(result.played.as_deref(), Some("Ra8#"));
+        assert!(result.solved);
+        assert!(result.time_to_solution_ms.is_some());
+        assert_eq!(result.depth, 2);
+        assert!(result.nodes > 0);
+        assert_eq!(result.id.as_deref(), Some("mate"));
+        assert_eq!(expected(&result), "bm Ra8#");
+
+        let result = run("bm Kf1;");
+        assert!(!result.solved);
+        assert_eq!(result.time_to_solution_ms, None);
+    }
+
+    #[test]
+    fn test_avoid_move() {
+        let result = run("am Kf1;");
+        assert!(result.solved);
+        assert!(result.time_to_solution_ms.is_some());
+
+        let result = run("am Ra8#;");
+        assert_eq!(result.played.as_deref(), Some("Ra8#"));
+        assert!(!result.solved);
+        assert_eq!(result.time_to_solution_ms, None);
+
+        let result = run("bm Ra8#; am Kf1;");
+        assert!(result.solved);
+        assert_eq!(expected(&result), "bm Ra8#; am Kf1");
+    }
+}